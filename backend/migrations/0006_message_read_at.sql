@@ -0,0 +1,2 @@
+-- Read receipts: set when the recipient acknowledges a message over /ws.
+ALTER TABLE messages ADD COLUMN read_at TEXT;
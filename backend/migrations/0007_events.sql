@@ -0,0 +1,15 @@
+-- Durable per-user event log backing the `/events` SSE stream. Each user's
+-- events are numbered by their own sequence (`users.event_seq`); `seq` doubles
+-- as the SSE event id so clients can resume with `Last-Event-ID`, and a gap in
+-- it means an event really was missed.
+ALTER TABLE users ADD COLUMN event_seq INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS events (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id    INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    seq        INTEGER NOT NULL,
+    kind       TEXT    NOT NULL,
+    payload    TEXT    NOT NULL,
+    created_at TEXT    NOT NULL DEFAULT (datetime('now')),
+    UNIQUE (user_id, seq)
+);
@@ -0,0 +1,5 @@
+-- Messages are archived rather than deleted when a match is undone, so they
+-- stay available for moderation but drop out of both users' conversations.
+ALTER TABLE messages ADD COLUMN archived_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_messages_archived ON messages(archived_at);
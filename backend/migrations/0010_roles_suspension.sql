@@ -0,0 +1,7 @@
+-- Moderation: user roles plus account suspension / ban state.
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user'
+    CHECK (role IN ('user', 'moderator', 'admin'));
+-- While in the future the account is suspended; NULL = not suspended.
+ALTER TABLE users ADD COLUMN suspended_until TEXT;
+-- Set when the account is permanently banned.
+ALTER TABLE users ADD COLUMN banned_at TEXT;
@@ -0,0 +1,10 @@
+-- Persistent storage for tower-sessions (see src/session_store.rs).
+-- Timestamps are unix seconds so expiry checks are plain integer compares.
+CREATE TABLE IF NOT EXISTS sessions (
+    id          TEXT    PRIMARY KEY NOT NULL,
+    data        TEXT    NOT NULL,
+    expiry_date INTEGER NOT NULL,
+    created_at  INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_expiry ON sessions(expiry_date);
@@ -0,0 +1,13 @@
+-- Per-user secret used as the axum-login session auth hash. Rotating it logs
+-- the user out everywhere; it no longer changes on every sign-in.
+ALTER TABLE users ADD COLUMN session_secret TEXT;
+
+UPDATE users SET session_secret = lower(hex(randomblob(32))) WHERE session_secret IS NULL;
+
+-- Rows inserted without a secret (e.g. by the seeder) get one immediately.
+CREATE TRIGGER IF NOT EXISTS users_session_secret_default
+AFTER INSERT ON users
+FOR EACH ROW WHEN NEW.session_secret IS NULL
+BEGIN
+    UPDATE users SET session_secret = lower(hex(randomblob(32))) WHERE id = NEW.id;
+END;
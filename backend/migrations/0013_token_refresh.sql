@@ -0,0 +1,6 @@
+-- Refresh-token renewal: when the stored access token expires, when the
+-- tokens were last renewed, and accounts deactivated because the identity
+-- provider no longer accepts their refresh token.
+ALTER TABLE users ADD COLUMN token_expires_at TEXT;
+ALTER TABLE users ADD COLUMN tokens_refreshed_at TEXT;
+ALTER TABLE users ADD COLUMN deactivated_at TEXT;
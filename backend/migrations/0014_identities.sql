@@ -0,0 +1,34 @@
+-- Users are linked to identity providers by (provider, subject) instead of
+-- only the Microsoft `oid`. OAuth tokens belong to the identity they were
+-- issued for, so they move off `users`. Every existing login came from
+-- Microsoft, whose subject is the `oid`.
+CREATE TABLE IF NOT EXISTS identities (
+    provider            TEXT    NOT NULL,
+    subject             TEXT    NOT NULL,
+    user_id             INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    email               TEXT,
+    access_token        TEXT,
+    refresh_token       TEXT,
+    id_token            TEXT,
+    token_expires_at    TEXT,
+    tokens_refreshed_at TEXT,
+    created_at          TEXT NOT NULL DEFAULT (datetime('now')),
+    updated_at          TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (provider, subject)
+);
+
+CREATE INDEX IF NOT EXISTS idx_identities_user ON identities(user_id);
+
+INSERT OR IGNORE INTO identities
+    (provider, subject, user_id, email, access_token, refresh_token, id_token,
+     token_expires_at, tokens_refreshed_at)
+SELECT 'microsoft', oid, id, email, access_token, refresh_token, id_token,
+       token_expires_at, tokens_refreshed_at
+FROM users
+WHERE access_token IS NOT NULL OR refresh_token IS NOT NULL OR id_token IS NOT NULL;
+
+ALTER TABLE users DROP COLUMN access_token;
+ALTER TABLE users DROP COLUMN refresh_token;
+ALTER TABLE users DROP COLUMN id_token;
+ALTER TABLE users DROP COLUMN token_expires_at;
+ALTER TABLE users DROP COLUMN tokens_refreshed_at;
@@ -0,0 +1,6 @@
+-- Data fixes that need Rust rather than SQL run once after the schema
+-- migrations (see `db::run_data_migrations`) and are recorded here.
+CREATE TABLE IF NOT EXISTS data_migrations (
+    name       TEXT PRIMARY KEY,
+    applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
@@ -0,0 +1,5 @@
+-- Content type of the re-encoded image behind each image_key. NULL for
+-- uploads stored before images were sniffed and re-encoded; those are served
+-- with a type sniffed from the stored bytes.
+ALTER TABLE users    ADD COLUMN image_content_type TEXT;
+ALTER TABLE messages ADD COLUMN image_content_type TEXT;
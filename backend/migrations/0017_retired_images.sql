@@ -0,0 +1,10 @@
+-- Profile photos that were replaced or removed. Their objects stay in the
+-- store for the retention period (for caches still pointing at them and for
+-- moderation), after which garbage collection may delete them.
+CREATE TABLE IF NOT EXISTS retired_images (
+    key        TEXT    PRIMARY KEY,
+    user_id    INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    retired_at TEXT    NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_retired_images_retired_at ON retired_images(retired_at);
@@ -0,0 +1,20 @@
+-- Profile photo gallery: up to the configured number of photos per user, in
+-- `position` order, exactly one of them primary while any exist. The primary
+-- photo's key and type are mirrored to users.image_key / image_content_type,
+-- which remain the user's avatar.
+CREATE TABLE IF NOT EXISTS profile_photos (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id      INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    image_key    TEXT    NOT NULL,
+    content_type TEXT,
+    position     INTEGER NOT NULL,
+    is_primary   BOOLEAN NOT NULL DEFAULT 0,
+    caption      TEXT,
+    created_at   TEXT    NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_profile_photos_user ON profile_photos(user_id, position);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_profile_photos_primary ON profile_photos(user_id) WHERE is_primary;
+
+INSERT INTO profile_photos (user_id, image_key, content_type, position, is_primary)
+SELECT id, image_key, image_content_type, 0, 1 FROM users WHERE image_key IS NOT NULL;
@@ -0,0 +1,364 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_login::AuthSession;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    auth::{backend::AuthBackend, rotate_session_secret},
+    error::{AppError, FieldError},
+    media::{IMMUTABLE, object_response},
+    models::{Message, Report, ReportStatus, Role, User},
+    photos,
+};
+
+// ---------------------------------------------------------------------------
+// Helpers
+// ---------------------------------------------------------------------------
+
+/// Load `target_id` if the acting moderator outranks them. Moderators may not
+/// act on other moderators or admins; admins may not act on other admins.
+/// Fails with `NotFound` / `Forbidden` otherwise.
+async fn load_target(
+    state: &AppState,
+    actor: &User,
+    target_id: i64,
+) -> Result<User, AppError> {
+    let target = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?1")
+        .bind(target_id)
+        .fetch_optional(&state.pool)
+        .await?;
+
+    match target {
+        None => Err(AppError::NotFound("user")),
+        Some(t) if t.role >= actor.role => Err(AppError::Forbidden(
+            "cannot moderate a user with an equal or higher role".into(),
+        )),
+        Some(t) => Ok(t),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Reports
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Deserialize)]
+pub struct ReportsParams {
+    /// Defaults to `open`.
+    pub status: Option<ReportStatus>,
+}
+
+/// `GET /admin/reports?status=open`
+///
+/// Lists reports with the given status, oldest first so the queue is worked
+/// through in order.
+pub async fn list_reports(
+    State(state): State<AppState>,
+    Query(params): Query<ReportsParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let status = params.status.unwrap_or(ReportStatus::Open);
+
+    let reports = sqlx::query_as::<_, Report>(
+        "SELECT * FROM reports WHERE status = ?1 ORDER BY created_at ASC, id ASC",
+    )
+    .bind(status)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(reports))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportDetail {
+    #[serde(flatten)]
+    pub report: Report,
+    /// Messages attached as evidence, including archived ones.
+    pub messages: Vec<Message>,
+}
+
+/// `GET /admin/reports/:id`
+///
+/// Returns a report together with the messages attached to it. Images on
+/// those messages are fetched through `GET /admin/messages/:id/image`.
+pub async fn get_report(
+    State(state): State<AppState>,
+    Path(report_id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let report = sqlx::query_as::<_, Report>("SELECT * FROM reports WHERE id = ?1")
+        .bind(report_id)
+        .fetch_optional(&state.pool)
+        .await?;
+
+    let Some(report) = report else {
+        return Err(AppError::NotFound("report"));
+    };
+
+    let messages = sqlx::query_as::<_, Message>(
+        r#"
+        SELECT m.id, m.sender_id, m.recipient_id, m.content, m.image_key, m.created_at, m.read_at
+        FROM messages m
+        JOIN report_messages rm ON rm.message_id = m.id
+        WHERE rm.report_id = ?1
+        ORDER BY m.created_at ASC
+        "#,
+    )
+    .bind(report.id)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(ReportDetail { report, messages }).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveReport {
+    /// `resolved` or `dismissed`.
+    pub status: ReportStatus,
+}
+
+/// `POST /admin/reports/:id/resolve`
+///
+/// Closes a report as `resolved` or `dismissed`.
+pub async fn resolve_report(
+    State(state): State<AppState>,
+    Path(report_id): Path<i64>,
+    Json(payload): Json<ResolveReport>,
+) -> Result<impl IntoResponse, AppError> {
+    if payload.status == ReportStatus::Open {
+        return Err(AppError::Validation(vec![FieldError::new(
+            "status",
+            "must be resolved or dismissed",
+        )]));
+    }
+
+    let report = sqlx::query_as::<_, Report>(
+        r#"
+        UPDATE reports
+        SET status = ?1, resolved_at = datetime('now')
+        WHERE id = ?2
+        RETURNING *
+        "#,
+    )
+    .bind(payload.status)
+    .bind(report_id)
+    .fetch_optional(&state.pool)
+    .await?;
+
+    match report {
+        Some(r) => Ok(Json(r).into_response()),
+        None => Err(AppError::NotFound("report")),
+    }
+}
+
+/// `GET /admin/messages/:id/image`
+///
+/// Streams any message image, archived or not, for review.
+pub async fn get_message_image(
+    State(state): State<AppState>,
+    Path(message_id): Path<i64>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let row: Option<(Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT image_key, image_content_type FROM messages WHERE id = ?1",
+    )
+    .bind(message_id)
+    .fetch_optional(&state.pool)
+    .await?;
+
+    let Some((Some(key), content_type)) = row else {
+        return Err(AppError::NotFound("image"));
+    };
+
+    object_response(state.store.as_ref(), &key, content_type.as_deref(), IMMUTABLE, &headers).await
+}
+
+// ---------------------------------------------------------------------------
+// Accounts
+// ---------------------------------------------------------------------------
+
+/// Longest suspension a moderator can hand out; anything longer is a ban.
+const MAX_SUSPENSION_DAYS: i64 = 365;
+
+#[derive(Debug, Deserialize)]
+pub struct SuspendUser {
+    pub days: i64,
+}
+
+/// `POST /admin/users/:id/suspend`
+///
+/// Suspends an account for `days` days. The user's session secret is rotated,
+/// so they are logged out everywhere and stay logged out once it lapses.
+pub async fn suspend_user(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path(user_id): Path<i64>,
+    Json(payload): Json<SuspendUser>,
+) -> Result<Response, AppError> {
+    let actor = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    if !(1..=MAX_SUSPENSION_DAYS).contains(&payload.days) {
+        return Err(AppError::Validation(vec![FieldError::new(
+            "days",
+            format!("must be between 1 and {MAX_SUSPENSION_DAYS}"),
+        )]));
+    }
+
+    let target = load_target(&state, &actor, user_id).await?;
+
+    sqlx::query(
+        "UPDATE users SET suspended_until = datetime('now', ?1), updated_at = datetime('now') WHERE id = ?2",
+    )
+    .bind(format!("+{} days", payload.days))
+    .bind(target.id)
+    .execute(&state.pool)
+    .await?;
+    rotate_session_secret(&state.pool, target.id).await?;
+
+    tracing::info!(moderator_id = actor.id, user_id = target.id, days = payload.days, "user suspended");
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `POST /admin/users/:id/ban` (admin only)
+///
+/// Permanently bans an account.
+pub async fn ban_user(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path(user_id): Path<i64>,
+) -> Result<Response, AppError> {
+    let actor = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let target = load_target(&state, &actor, user_id).await?;
+
+    sqlx::query(
+        "UPDATE users SET banned_at = datetime('now'), updated_at = datetime('now') WHERE id = ?1",
+    )
+    .bind(target.id)
+    .execute(&state.pool)
+    .await?;
+    rotate_session_secret(&state.pool, target.id).await?;
+
+    tracing::info!(admin_id = actor.id, user_id = target.id, "user banned");
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `POST /admin/users/:id/reinstate`
+///
+/// Lifts a suspension. Lifting a ban additionally requires the admin role.
+pub async fn reinstate_user(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path(user_id): Path<i64>,
+) -> Result<Response, AppError> {
+    let actor = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let target = load_target(&state, &actor, user_id).await?;
+
+    if target.banned_at.is_some() && actor.role < Role::Admin {
+        return Err(AppError::Forbidden("only admins can lift a ban".into()));
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET suspended_until = NULL, banned_at = NULL, updated_at = datetime('now')
+        WHERE id = ?1
+        "#,
+    )
+    .bind(target.id)
+    .execute(&state.pool)
+    .await?;
+
+    tracing::info!(moderator_id = actor.id, user_id = target.id, "user reinstated");
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRole {
+    pub role: Role,
+}
+
+/// `PUT /admin/users/:id/role` (admin only)
+///
+/// Grants or revokes the moderator role. Promoting to admin is not possible
+/// through the API.
+pub async fn set_role(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path(user_id): Path<i64>,
+    Json(payload): Json<SetRole>,
+) -> Result<Response, AppError> {
+    let actor = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    if payload.role >= actor.role {
+        return Err(AppError::Forbidden("cannot grant a role equal to or above your own".into()));
+    }
+
+    let target = load_target(&state, &actor, user_id).await?;
+
+    sqlx::query("UPDATE users SET role = ?1, updated_at = datetime('now') WHERE id = ?2")
+        .bind(payload.role)
+        .bind(target.id)
+        .execute(&state.pool)
+        .await?;
+
+    tracing::info!(admin_id = actor.id, user_id = target.id, role = ?payload.role, "user role changed");
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `DELETE /admin/users/:id/photo`
+///
+/// Removes a user's primary photo; the next photo in their gallery takes its
+/// place. The object stays in the store for the image retention period, so it
+/// can still be reviewed.
+pub async fn remove_profile_photo(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path(user_id): Path<i64>,
+) -> Result<Response, AppError> {
+    let actor = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let target = load_target(&state, &actor, user_id).await?;
+
+    let mut tx = state.pool.begin().await?;
+    let photo = photos::remove_primary(&mut tx, target.id).await?;
+    tx.commit().await?;
+
+    tracing::info!(
+        moderator_id = actor.id,
+        user_id = target.id,
+        key = photo.image_key,
+        "profile photo removed"
+    );
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `DELETE /admin/users/:id/photos/:photo_id`
+///
+/// Removes one photo from a user's gallery, like
+/// [`remove_profile_photo`] does for the primary one.
+pub async fn remove_photo(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path((user_id, photo_id)): Path<(i64, i64)>,
+) -> Result<Response, AppError> {
+    let actor = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let target = load_target(&state, &actor, user_id).await?;
+
+    let mut tx = state.pool.begin().await?;
+    let photo = photos::remove(&mut tx, target.id, photo_id).await?;
+    tx.commit().await?;
+
+    tracing::info!(
+        moderator_id = actor.id,
+        user_id = target.id,
+        photo_id,
+        key = photo.image_key,
+        "profile photo removed"
+    );
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
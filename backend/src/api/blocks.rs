@@ -0,0 +1,64 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use axum_login::AuthSession;
+
+use crate::{
+    AppState,
+    api::matches::unmatch,
+    auth::backend::AuthBackend,
+    authz::canonical_pair,
+    error::AppError,
+    models::Match,
+};
+
+/// `POST /users/:id/block`
+///
+/// Blocks another user. From then on neither side sees the other in the feed,
+/// profile lookups or match list, and neither can message the other. An
+/// existing match between the two is removed as if the caller had unmatched.
+///
+/// Returns `204 No Content`; blocking someone twice is a no-op.
+pub async fn block_user(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path(blocked_id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let me = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    if blocked_id == me.id {
+        return Err(AppError::BadRequest("cannot block yourself".into()));
+    }
+
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = ?1)")
+        .bind(blocked_id)
+        .fetch_one(&state.pool)
+        .await?;
+    if !exists {
+        return Err(AppError::NotFound("user"));
+    }
+
+    sqlx::query("INSERT OR IGNORE INTO blocks (blocker_id, blocked_id) VALUES (?1, ?2)")
+        .bind(me.id)
+        .bind(blocked_id)
+        .execute(&state.pool)
+        .await?;
+
+    let (user1_id, user2_id) = canonical_pair(me.id, blocked_id);
+    let existing = sqlx::query_as::<_, Match>(
+        "SELECT id, user1_id, user2_id, created_at FROM matches WHERE user1_id = ?1 AND user2_id = ?2",
+    )
+    .bind(user1_id)
+    .bind(user2_id)
+    .fetch_optional(&state.pool)
+    .await?;
+
+    if let Some(m) = existing {
+        unmatch(&state, &m, me.id).await?;
+    }
+
+    tracing::info!(user_id = me.id, blocked_id, "user blocked");
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
@@ -0,0 +1,145 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use axum_login::AuthSession;
+use serde::Deserialize;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    AppState,
+    auth::backend::AuthBackend,
+    error::AppError,
+    realtime::{self, StoredEvent},
+};
+
+/// `EventSource` can't set headers on its first connection, so the resume
+/// point may also be passed as `?last_event_id=`.
+#[derive(Debug, Deserialize)]
+pub struct EventsParams {
+    pub last_event_id: Option<i64>,
+}
+
+/// `GET /events`
+///
+/// Server-Sent Events fallback for clients that can't hold a WebSocket open.
+/// Streams `match.created`, `message.created` and `profile.updated` events for
+/// the authenticated user. Every event carries the user's sequence number for
+/// it as its id; when the client reconnects with `Last-Event-ID` everything
+/// logged since is replayed before live events resume.
+pub async fn event_stream(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Query(params): Query<EventsParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
+    let user_id = user.id;
+
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .or(params.last_event_id);
+
+    // Fix the cursor, then subscribe, then replay the log from the cursor:
+    // anything published in between is in the log, and live duplicates are
+    // filtered by id below.
+    let mut cursor = match last_event_id {
+        Some(id) => id,
+        // Fresh connection: start from the newest logged event.
+        None => {
+            sqlx::query_scalar::<_, i64>("SELECT event_seq FROM users WHERE id = ?1")
+                .bind(user_id)
+                .fetch_one(&state.pool)
+                .await?
+        }
+    };
+    let mut rx = state.hub.subscribe(user_id);
+    let (tx, out) = mpsc::channel::<Result<Event, Infallible>>(16);
+
+    tokio::spawn(async move {
+        if replay(&state, user_id, &mut cursor, &tx).await.is_err() {
+            return;
+        }
+
+        loop {
+            tokio::select! {
+                delivery = rx.recv() => match delivery {
+                    Ok(delivery) => {
+                        let (Some(seq), Some(kind)) = (delivery.seq, delivery.event.kind()) else {
+                            continue;
+                        };
+                        if seq <= cursor {
+                            continue;
+                        }
+                        // Publishers log before they deliver, so concurrent
+                        // ones can deliver out of order: a gap in the user's
+                        // sequence is an event still on its way. The log has it.
+                        if seq > cursor + 1 {
+                            if replay(&state, user_id, &mut cursor, &tx).await.is_err() {
+                                break;
+                            }
+                            if seq <= cursor {
+                                continue;
+                            }
+                        }
+                        let Ok(payload) = serde_json::to_string(&delivery.event) else {
+                            continue;
+                        };
+                        cursor = seq;
+                        let event = Event::default().id(seq.to_string()).event(kind).data(payload);
+                        if tx.send(Ok(event)).await.is_err() {
+                            break;
+                        }
+                    }
+                    // Fell behind the broadcast buffer: catch up from the log.
+                    Err(RecvError::Lagged(_)) => {
+                        if replay(&state, user_id, &mut cursor, &tx).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = tx.closed() => break,
+            }
+        }
+
+        drop(rx);
+        state.hub.prune(user_id);
+    });
+
+    Ok(Sse::new(ReceiverStream::new(out))
+        .keep_alive(KeepAlive::new().interval(Duration::from_secs(15))))
+}
+
+/// Send every logged event after `cursor`, advancing it. Errors only when the
+/// client has gone away.
+async fn replay(
+    state: &AppState,
+    user_id: i64,
+    cursor: &mut i64,
+    tx: &mpsc::Sender<Result<Event, Infallible>>,
+) -> Result<(), ()> {
+    let backlog = match realtime::events_since(&state.pool, user_id, *cursor).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            tracing::warn!(user_id, error = %e, "failed to load event backlog");
+            return Ok(());
+        }
+    };
+
+    for StoredEvent { seq, kind, payload } in backlog {
+        *cursor = seq;
+        let event = Event::default().id(seq.to_string()).event(kind).data(payload);
+        tx.send(Ok(event)).await.map_err(|_| ())?;
+    }
+    Ok(())
+}
@@ -1,22 +1,25 @@
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use axum_login::AuthSession;
-use sqlx::SqlitePool;
 
 use crate::{
-    auth::backend::MicrosoftBackend,
+    AppState,
+    auth::backend::AuthBackend,
+    authz::{canonical_pair, is_blocked},
     error::AppError,
     models::{Match, NewLike},
+    realtime::ServerEvent,
 };
 
 /// `POST /api/likes`
 ///
 /// Records a like (or pass) from the authenticated user toward another profile.
 /// If both users have liked each other a new entry is inserted into `matches`
-/// and the match is returned as JSON with `201 Created`.
+/// and the match is returned as JSON with `201 Created`; both users receive a
+/// `match.created` event the first time the match is made.
 /// Otherwise `204 No Content` is returned.
 pub async fn submit_like(
-    auth_session: AuthSession<MicrosoftBackend>,
-    State(pool): State<SqlitePool>,
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
     Json(payload): Json<NewLike>,
 ) -> Result<impl IntoResponse, AppError> {
     let user = auth_session.user.ok_or(AppError::Unauthorized)?;
@@ -35,7 +38,7 @@ pub async fn submit_like(
     .bind(liker_id)
     .bind(liked_id)
     .bind(is_like)
-    .execute(&pool)
+    .execute(&state.pool)
     .await?;
 
     // Only attempt to match when the current user actually liked the other.
@@ -49,33 +52,51 @@ pub async fn submit_like(
     )
     .bind(liked_id)
     .bind(liker_id)
-    .fetch_one(&pool)
+    .fetch_one(&state.pool)
     .await?;
 
-    if mutual == 0 {
+    // A block in either direction means no match, even with mutual likes.
+    if mutual == 0 || is_blocked(&state.pool, liker_id, liked_id).await? {
         return Ok(StatusCode::NO_CONTENT.into_response());
     }
 
     // Canonical ordering: smaller id is always user1.
-    let (user1_id, user2_id) = if liker_id < liked_id {
-        (liker_id, liked_id)
-    } else {
-        (liked_id, liker_id)
-    };
+    let (user1_id, user2_id) = canonical_pair(liker_id, liked_id);
 
     // Insert the match; if it already exists return the existing row.
-    let new_match = sqlx::query_as::<_, Match>(
+    let inserted = sqlx::query_as::<_, Match>(
         r#"
         INSERT INTO matches (user1_id, user2_id)
         VALUES (?1, ?2)
-        ON CONFLICT(user1_id, user2_id) DO UPDATE SET user1_id = user1_id
+        ON CONFLICT(user1_id, user2_id) DO NOTHING
         RETURNING id, user1_id, user2_id, created_at
         "#,
     )
     .bind(user1_id)
     .bind(user2_id)
-    .fetch_one(&pool)
+    .fetch_optional(&state.pool)
     .await?;
 
+    let new_match = match inserted {
+        Some(m) => {
+            for user_id in [m.user1_id, m.user2_id] {
+                state
+                    .hub
+                    .publish(&state.pool, user_id, ServerEvent::Match { new_match: m.clone() })
+                    .await?;
+            }
+            m
+        }
+        None => {
+            sqlx::query_as::<_, Match>(
+                "SELECT id, user1_id, user2_id, created_at FROM matches WHERE user1_id = ?1 AND user2_id = ?2",
+            )
+            .bind(user1_id)
+            .bind(user2_id)
+            .fetch_one(&state.pool)
+            .await?
+        }
+    };
+
     Ok((StatusCode::CREATED, Json(new_match)).into_response())
 }
@@ -1,12 +1,20 @@
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
 use axum_login::AuthSession;
 use serde::Serialize;
 use sqlx::SqlitePool;
 
 use crate::{
-    auth::backend::MicrosoftBackend,
+    AppState,
+    auth::backend::AuthBackend,
     error::AppError,
     models::{Match, User, UserResponse},
+    photos,
+    realtime::ServerEvent,
 };
 
 #[derive(Debug, Serialize)]
@@ -21,17 +29,22 @@ pub struct MatchResponse {
 /// Returns all matches for the authenticated user, each with the other user's
 /// public profile (including interests).
 pub async fn get_matches(
-    auth_session: AuthSession<MicrosoftBackend>,
+    auth_session: AuthSession<AuthBackend>,
     State(pool): State<SqlitePool>,
 ) -> Result<impl IntoResponse, AppError> {
     let me = auth_session.user.ok_or(AppError::Unauthorized)?;
 
     let matches = sqlx::query_as::<_, Match>(
         r#"
-        SELECT id, user1_id, user2_id, created_at
-        FROM matches
-        WHERE user1_id = ?1 OR user2_id = ?1
-        ORDER BY created_at DESC
+        SELECT m.id, m.user1_id, m.user2_id, m.created_at
+        FROM matches m
+        WHERE (m.user1_id = ?1 OR m.user2_id = ?1)
+          AND NOT EXISTS (
+              SELECT 1 FROM blocks b
+              WHERE (b.blocker_id = m.user1_id AND b.blocked_id = m.user2_id)
+                 OR (b.blocker_id = m.user2_id AND b.blocked_id = m.user1_id)
+          )
+        ORDER BY m.created_at DESC
         "#,
     )
     .bind(me.id)
@@ -65,12 +78,105 @@ pub async fn get_matches(
         .fetch_all(&pool)
         .await?;
 
+        let photos = photos::for_user(&pool, other_id).await?;
+
         result.push(MatchResponse {
             id: m.id,
-            user: UserResponse::from_user(other, interests),
+            user: UserResponse::from_user(other, interests, photos),
             created_at: m.created_at,
         });
     }
 
     Ok(Json(result))
 }
+
+/// `DELETE /matches/:id`
+///
+/// Undoes a match the authenticated user is part of. The caller's like becomes
+/// a pass so the other user doesn't resurface in the feed, and the pair's
+/// messages are archived (kept for moderation, hidden from both sides).
+/// Both users receive a `match.deleted` event.
+///
+/// Returns `204 No Content`, or 404 if the match doesn't exist or belongs to
+/// someone else.
+pub async fn delete_match(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path(match_id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let me = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let found = sqlx::query_as::<_, Match>(
+        r#"
+        SELECT id, user1_id, user2_id, created_at
+        FROM matches
+        WHERE id = ?1 AND (user1_id = ?2 OR user2_id = ?2)
+        "#,
+    )
+    .bind(match_id)
+    .bind(me.id)
+    .fetch_optional(&state.pool)
+    .await?;
+
+    let Some(m) = found else {
+        return Err(AppError::NotFound("match"));
+    };
+
+    unmatch(&state, &m, me.id).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Remove match `m` on behalf of `by_id` (one of its two users): delete the
+/// row, turn `by_id`'s like into a pass, archive the conversation and notify
+/// both sides. Shared by `DELETE /matches/:id` and blocking.
+pub(crate) async fn unmatch(state: &AppState, m: &Match, by_id: i64) -> Result<(), AppError> {
+    let other_id = if m.user1_id == by_id { m.user2_id } else { m.user1_id };
+
+    let mut tx = state.pool.begin().await?;
+
+    sqlx::query("DELETE FROM matches WHERE id = ?1")
+        .bind(m.id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO likes (liker_id, liked_id, is_like)
+        VALUES (?1, ?2, 0)
+        ON CONFLICT(liker_id, liked_id) DO UPDATE SET is_like = 0
+        "#,
+    )
+    .bind(by_id)
+    .bind(other_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE messages
+        SET archived_at = datetime('now')
+        WHERE ((sender_id = ?1 AND recipient_id = ?2)
+            OR (sender_id = ?2 AND recipient_id = ?1))
+          AND archived_at IS NULL
+        "#,
+    )
+    .bind(by_id)
+    .bind(other_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    tracing::info!(match_id = m.id, user_id = by_id, other_id, "match removed");
+
+    // Each side is told who they are no longer matched with.
+    for (recipient_id, user_id) in [(other_id, by_id), (by_id, other_id)] {
+        state
+            .hub
+            .publish(&state.pool, recipient_id, ServerEvent::Unmatched { match_id: m.id, user_id })
+            .await?;
+    }
+
+    Ok(())
+}
@@ -1,7 +1,6 @@
 use axum::{
-    body::Body,
     extract::{Multipart, Path, State},
-    http::{StatusCode, header},
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -10,29 +9,35 @@ use object_store::{ObjectStoreExt, PutPayload, path::Path as StorePath};
 
 use crate::{
     AppState,
-    auth::backend::MicrosoftBackend,
-    error::AppError,
+    auth::backend::AuthBackend,
+    authz::ensure_matched,
+    error::{AppError, FieldError},
+    media::{IMMUTABLE, object_response},
     models::Message,
+    realtime::ServerEvent,
 };
 
 /// `GET /messages/:user_id`
 ///
 /// Retrieves all messages between the authenticated user and the specified user,
-/// ordered by creation time (oldest first).
+/// ordered by creation time (oldest first). Returns 403 unless the two are matched.
 pub async fn get_messages(
-    auth_session: AuthSession<MicrosoftBackend>,
+    auth_session: AuthSession<AuthBackend>,
     State(state): State<AppState>,
     Path(user_id): Path<i64>,
 ) -> Result<impl IntoResponse, AppError> {
     let user = auth_session.user.ok_or(AppError::Unauthorized)?;
     let current_user_id = user.id;
 
+    ensure_matched(&state.pool, current_user_id, user_id).await?;
+
     let messages = sqlx::query_as::<_, Message>(
         r#"
-        SELECT id, sender_id, recipient_id, content, image_key, created_at
+        SELECT id, sender_id, recipient_id, content, image_key, created_at, read_at
         FROM messages
-        WHERE (sender_id = ?1 AND recipient_id = ?2)
-           OR (sender_id = ?2 AND recipient_id = ?1)
+        WHERE ((sender_id = ?1 AND recipient_id = ?2)
+            OR (sender_id = ?2 AND recipient_id = ?1))
+          AND archived_at IS NULL
         ORDER BY created_at ASC
         "#,
     )
@@ -49,11 +54,14 @@ pub async fn get_messages(
 /// Accepts multipart/form-data:
 ///   - `recipient_id` — text, required
 ///   - `content`      — text, optional
-///   - `image`        — file, optional
+///   - `image`        — file, optional; JPEG, PNG or WebP (HEIC with the `heic` feature)
+///
+/// Images are re-encoded without their metadata before they are stored.
 ///
-/// Returns the created message with status 201.
+/// Returns the created message with status 201, or 403 if the sender and
+/// recipient are not matched.
 pub async fn send_message(
-    auth_session: AuthSession<MicrosoftBackend>,
+    auth_session: AuthSession<AuthBackend>,
     State(state): State<AppState>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, AppError> {
@@ -66,28 +74,24 @@ pub async fn send_message(
 
     while let Some(field) = multipart
         .next_field()
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?
+        .await?
     {
         match field.name().unwrap_or("") {
             "recipient_id" => {
                 let text = field
                     .text()
-                    .await
-                    .map_err(|e| AppError::Internal(e.to_string()))?;
+                    .await?;
                 recipient_id = text.trim().parse::<i64>().ok();
             }
             "content" => {
                 content = field
                     .text()
-                    .await
-                    .map_err(|e| AppError::Internal(e.to_string()))?;
+                    .await?;
             }
             "image" => {
                 let data = field
                     .bytes()
-                    .await
-                    .map_err(|e| AppError::Internal(e.to_string()))?;
+                    .await?;
                 if !data.is_empty() {
                     image_data = Some(data);
                 }
@@ -96,14 +100,30 @@ pub async fn send_message(
         }
     }
 
-    let recipient_id = recipient_id.ok_or_else(|| AppError::Internal("missing recipient_id".into()))?;
+    let recipient_id = recipient_id.ok_or_else(|| {
+        AppError::Validation(vec![FieldError::new("recipient_id", "must be a user id")])
+    })?;
+
+    ensure_matched(&state.pool, sender_id, recipient_id).await?;
+
+    let image = match image_data {
+        Some(data) => Some(
+            state
+                .config
+                .images
+                .clean(data)
+                .await
+                .map_err(|e| e.for_field("image"))?,
+        ),
+        None => None,
+    };
 
     // Insert message row first (image_key is filled in after we know the id)
     let message = sqlx::query_as::<_, Message>(
         r#"
         INSERT INTO messages (sender_id, recipient_id, content)
         VALUES (?1, ?2, ?3)
-        RETURNING id, sender_id, recipient_id, content, image_key, created_at
+        RETURNING id, sender_id, recipient_id, content, image_key, created_at, read_at
         "#,
     )
     .bind(sender_id)
@@ -113,25 +133,26 @@ pub async fn send_message(
     .await?;
 
     // If an image was uploaded, store it and update image_key
-    let message = if let Some(data) = image_data {
+    let message = if let Some(image) = image {
         let key = format!("messages/{}", message.id);
         let path = StorePath::from(key.as_str());
         state
             .store
-            .put(&path, PutPayload::from(data))
+            .put(&path, PutPayload::from(image.data))
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         sqlx::query(
-            "UPDATE messages SET image_key = ?1 WHERE id = ?2",
+            "UPDATE messages SET image_key = ?1, image_content_type = ?2 WHERE id = ?3",
         )
         .bind(&key)
+        .bind(image.content_type)
         .bind(message.id)
         .execute(&state.pool)
         .await?;
 
         sqlx::query_as::<_, Message>(
-            "SELECT id, sender_id, recipient_id, content, image_key, created_at FROM messages WHERE id = ?1",
+            "SELECT id, sender_id, recipient_id, content, image_key, created_at, read_at FROM messages WHERE id = ?1",
         )
         .bind(message.id)
         .fetch_one(&state.pool)
@@ -140,45 +161,73 @@ pub async fn send_message(
         message
     };
 
+    // Push to the recipient and to the sender's other open connections.
+    for user_id in [recipient_id, sender_id] {
+        state
+            .hub
+            .publish(&state.pool, user_id, ServerEvent::Message { message: message.clone() })
+            .await?;
+    }
+
     Ok((StatusCode::CREATED, Json(message)))
 }
 
 /// `GET /messages/:message_id/image`
 ///
-/// Streams the image for a message from object storage.
+/// Streams the image for a message from object storage. Message images never
+/// change, so they may be cached indefinitely; conditional and range requests
+/// are supported.
+/// Only the sender and recipient may fetch it, and only while they are matched.
 /// Returns 404 if the message has no image.
 pub async fn get_message_image(
-    _auth_session: AuthSession<MicrosoftBackend>,
+    auth_session: AuthSession<AuthBackend>,
     State(state): State<AppState>,
     Path(message_id): Path<i64>,
+    headers: HeaderMap,
 ) -> Result<Response, AppError> {
-    let image_key: Option<String> = sqlx::query_scalar(
-        "SELECT image_key FROM messages WHERE id = ?1",
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let row = sqlx::query_as::<_, ImageRow>(
+        r#"
+        SELECT sender_id, recipient_id, image_key, image_content_type,
+               archived_at IS NOT NULL AS archived
+        FROM messages WHERE id = ?1
+        "#,
     )
     .bind(message_id)
     .fetch_optional(&state.pool)
-    .await?
-    .flatten();
+    .await?;
 
-    let Some(key) = image_key else {
-        return Ok(StatusCode::NOT_FOUND.into_response());
+    let Some(row) = row else {
+        return Err(AppError::NotFound("message"));
     };
 
-    let path = StorePath::from(key.as_str());
-    let result = state
-        .store
-        .get(&path)
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    let bytes = result
-        .bytes()
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    Ok((
-        [(header::CONTENT_TYPE, "image/jpeg")],
-        Body::from(bytes),
-    )
-        .into_response())
+    let other_id = if user.id == row.sender_id {
+        row.recipient_id
+    } else if user.id == row.recipient_id {
+        row.sender_id
+    } else {
+        return Err(AppError::NotMatched);
+    };
+    // Checked before `archived`: unmatching or blocking archives the
+    // conversation, and that should read as 403 like any other unmatched pair.
+    ensure_matched(&state.pool, user.id, other_id).await?;
+    if row.archived {
+        return Err(AppError::NotFound("message"));
+    }
+
+    let Some(key) = row.image_key else {
+        return Err(AppError::NotFound("image"));
+    };
+
+    object_response(state.store.as_ref(), &key, row.image_content_type.as_deref(), IMMUTABLE, &headers).await
+}
+
+#[derive(sqlx::FromRow)]
+struct ImageRow {
+    sender_id: i64,
+    recipient_id: i64,
+    image_key: Option<String>,
+    image_content_type: Option<String>,
+    archived: bool,
 }
@@ -1,5 +1,10 @@
+pub mod admin;
+pub mod blocks;
+pub mod events;
 pub mod likes;
 pub mod matches;
 pub mod messages;
 pub mod profiles;
+pub mod reports;
 pub mod user;
+pub mod ws;
@@ -2,16 +2,24 @@ use std::collections::HashMap;
 
 use axum::{
     Json,
-    body::Body,
-    extract::{Path, State},
-    http::{StatusCode, header},
+    extract::{Path, Query, State},
+    http::HeaderMap,
     response::{IntoResponse, Response},
 };
 use axum_login::AuthSession;
-use object_store::{ObjectStoreExt, path::Path as StorePath};
+use serde::Deserialize;
 use sqlx::SqlitePool;
 
-use crate::{AppState, auth::backend::MicrosoftBackend, error::AppError, models::{User, UserResponse}};
+use crate::{
+    AppState,
+    auth::backend::AuthBackend,
+    authz::is_blocked,
+    error::AppError,
+    images::ensure_variant,
+    media::{IMMUTABLE, REVALIDATE, object_response},
+    models::{User, UserResponse},
+    photos,
+};
 
 // ---------------------------------------------------------------------------
 // Helpers
@@ -63,20 +71,35 @@ async fn fetch_interests_for_users(
 // GET /api/profiles/:id
 // ---------------------------------------------------------------------------
 
-/// Returns a single user's public profile (with interests) by their `id`.
-/// Returns 404 if the user does not exist.
+/// Returns a single user's public profile (with interests and photos) by
+/// their `id`.
+/// Returns 404 if the user does not exist, is banned or suspended, or either
+/// side has blocked the other.
 pub async fn get_profile(
-    _auth_session: AuthSession<MicrosoftBackend>,
+    auth_session: AuthSession<AuthBackend>,
     State(pool): State<SqlitePool>,
     Path(profile_id): Path<i64>,
 ) -> Result<impl IntoResponse, AppError> {
-    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?1")
-        .bind(profile_id)
-        .fetch_optional(&pool)
-        .await?;
+    let me = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    if is_blocked(&pool, me.id, profile_id).await? {
+        return Err(AppError::NotFound("profile"));
+    }
+
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        SELECT * FROM users
+        WHERE id = ?1
+          AND banned_at IS NULL
+          AND (suspended_until IS NULL OR suspended_until <= datetime('now'))
+        "#,
+    )
+    .bind(profile_id)
+    .fetch_optional(&pool)
+    .await?;
 
     let Some(user) = user else {
-        return Ok(StatusCode::NOT_FOUND.into_response());
+        return Err(AppError::NotFound("profile"));
     };
 
     let interests: Vec<String> = sqlx::query_scalar(
@@ -91,8 +114,9 @@ pub async fn get_profile(
     .bind(user.id)
     .fetch_all(&pool)
     .await?;
+    let photos = photos::for_user(&pool, user.id).await?;
 
-    Ok(Json(UserResponse::from_user(user, interests)).into_response())
+    Ok(Json(UserResponse::from_user(user, interests, photos)).into_response())
 }
 
 // ---------------------------------------------------------------------------
@@ -104,10 +128,11 @@ pub async fn get_profile(
 ///   +2 for the same major
 ///   +1 for the same RSO status
 ///
-/// Users the current user has already liked/passed are excluded.
+/// Users the current user has already liked/passed, anyone blocked in
+/// either direction, and banned or suspended accounts are excluded.
 /// Results are sorted by score descending and capped at 50.
 pub async fn compatible_profiles(
-    auth_session: AuthSession<MicrosoftBackend>,
+    auth_session: AuthSession<AuthBackend>,
     State(pool): State<SqlitePool>,
 ) -> Result<impl IntoResponse, AppError> {
     let me = auth_session.user.ok_or(AppError::Unauthorized)?;
@@ -119,9 +144,16 @@ pub async fn compatible_profiles(
         SELECT u.*
         FROM users u
         WHERE u.id != ?1
+          AND u.banned_at IS NULL
+          AND (u.suspended_until IS NULL OR u.suspended_until <= datetime('now'))
           AND u.id NOT IN (
               SELECT liked_id FROM likes WHERE liker_id = ?1
           )
+          AND u.id NOT IN (
+              SELECT blocked_id FROM blocks WHERE blocker_id = ?1
+              UNION
+              SELECT blocker_id FROM blocks WHERE blocked_id = ?1
+          )
         ORDER BY
             -- shared interests (×3)
             (
@@ -151,12 +183,14 @@ pub async fn compatible_profiles(
 
     let ids: Vec<i64> = scored_users.iter().map(|u| u.id).collect();
     let mut interest_map = fetch_interests_for_users(&pool, &ids).await?;
+    let mut photo_map = photos::for_users(&pool, &ids).await?;
 
     let profiles: Vec<UserResponse> = scored_users
         .into_iter()
         .map(|u| {
             let interests = interest_map.remove(&u.id).unwrap_or_default();
-            UserResponse::from_user(u, interests)
+            let photos = photo_map.remove(&u.id).unwrap_or_default();
+            UserResponse::from_user(u, interests, photos)
         })
         .collect();
 
@@ -167,40 +201,106 @@ pub async fn compatible_profiles(
 // GET /profiles/:id/image
 // ---------------------------------------------------------------------------
 
-/// Streams the profile image for user `id` from object storage.
-/// Returns 404 if the user has no image on file.
+#[derive(Debug, Deserialize)]
+pub struct ImageParams {
+    /// Longest side wanted, in pixels; rounded up to the nearest of
+    /// [`VARIANT_SIZES`](crate::images::VARIANT_SIZES).
+    pub size: Option<u32>,
+    /// Version of the photo the client expects: the last segment of its
+    /// `image_key`. A current version makes the response cacheable
+    /// indefinitely, as a new photo gets a new key.
+    pub v: Option<String>,
+}
+
+/// Streams the profile image for user `id` from object storage: the original
+/// upload, or with `?size=` a downscaled WebP variant. Supports conditional
+/// and range requests. Without a current `?v=` the photo behind this URL can
+/// change, so clients must revalidate.
+/// Returns 404 if the user has no image on file or either user has blocked
+/// the other.
 pub async fn get_profile_image(
-    _auth_session: AuthSession<MicrosoftBackend>,
+    auth_session: AuthSession<AuthBackend>,
     State(state): State<AppState>,
     Path(profile_id): Path<i64>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
 ) -> Result<Response, AppError> {
-    let image_key: Option<String> = sqlx::query_scalar(
-        "SELECT image_key FROM users WHERE id = ?1",
+    let me = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    if is_blocked(&state.pool, me.id, profile_id).await? {
+        return Err(AppError::NotFound("image"));
+    }
+
+    let row: Option<(Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT image_key, image_content_type FROM users WHERE id = ?1",
     )
     .bind(profile_id)
     .fetch_optional(&state.pool)
-    .await?
-    .flatten();
+    .await?;
 
-    let Some(key) = image_key else {
-        return Ok(StatusCode::NOT_FOUND.into_response());
+    let Some((Some(key), content_type)) = row else {
+        return Err(AppError::NotFound("image"));
     };
 
-    let path = StorePath::from(key.as_str());
-    let result = state
-        .store
-        .get(&path)
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    let bytes = result
-        .bytes()
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    Ok((
-        [(header::CONTENT_TYPE, "image/jpeg")],
-        Body::from(bytes),
+    photo_response(&state, key, content_type, &params, &headers).await
+}
+
+// ---------------------------------------------------------------------------
+// GET /profiles/:id/photos/:photo_id/image
+// ---------------------------------------------------------------------------
+
+/// Streams one photo of user `id`'s gallery, with the same `?size=` / `?v=`
+/// handling as [`get_profile_image`].
+/// Returns 404 if the photo doesn't exist, belongs to someone else, or
+/// either user has blocked the other.
+pub async fn get_profile_photo_image(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path((profile_id, photo_id)): Path<(i64, i64)>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let me = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    if is_blocked(&state.pool, me.id, profile_id).await? {
+        return Err(AppError::NotFound("image"));
+    }
+
+    let row: Option<(String, Option<String>)> = sqlx::query_as(
+        "SELECT image_key, content_type FROM profile_photos WHERE id = ?1 AND user_id = ?2",
     )
-        .into_response())
+    .bind(photo_id)
+    .bind(profile_id)
+    .fetch_optional(&state.pool)
+    .await?;
+
+    let Some((key, content_type)) = row else {
+        return Err(AppError::NotFound("image"));
+    };
+
+    photo_response(&state, key, content_type, &params, &headers).await
+}
+
+/// Serve the photo stored at `key`, or its variant for `?size=`; immutable
+/// when `?v=` names this very key.
+async fn photo_response(
+    state: &AppState,
+    key: String,
+    content_type: Option<String>,
+    params: &ImageParams,
+    headers: &HeaderMap,
+) -> Result<Response, AppError> {
+    let cache_control = match &params.v {
+        Some(v) if key.rsplit('/').next() == Some(v.as_str()) => IMMUTABLE,
+        _ => REVALIDATE,
+    };
+    let store = state.store.as_ref();
+    let (key, content_type) = match params.size {
+        Some(size) => (
+            ensure_variant(store, &state.config.images, &key, size).await?,
+            Some("image/webp".to_string()),
+        ),
+        None => (key, content_type),
+    };
+    object_response(store, &key, content_type.as_deref(), cache_control, headers).await
 }
@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_login::AuthSession;
+
+use crate::{
+    AppState,
+    auth::backend::AuthBackend,
+    error::{AppError, FieldError},
+    models::{NewReport, Report},
+};
+
+/// Upper bound on the free-text part of a report.
+const MAX_DETAILS_LEN: usize = 2000;
+
+/// Most messages a single report may attach as evidence.
+const MAX_MESSAGE_IDS: usize = 50;
+
+/// `POST /users/:id/report`
+///
+/// Files a report against another user for moderators to review. The body is
+/// JSON: `reason` (enum), optional `details` text and optional `message_ids`
+/// (at most 50) from the conversation between the two users to attach as
+/// evidence.
+///
+/// Returns the created report with `201 Created`.
+pub async fn report_user(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path(reported_id): Path<i64>,
+    Json(payload): Json<NewReport>,
+) -> Result<impl IntoResponse, AppError> {
+    let me = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    if reported_id == me.id {
+        return Err(AppError::BadRequest("cannot report yourself".into()));
+    }
+
+    let details = payload
+        .details
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty());
+    if details.as_ref().is_some_and(|d| d.chars().count() > MAX_DETAILS_LEN) {
+        return Err(AppError::Validation(vec![FieldError::new(
+            "details",
+            format!("must be at most {MAX_DETAILS_LEN} characters"),
+        )]));
+    }
+
+    let mut message_ids = payload.message_ids;
+    message_ids.sort_unstable();
+    message_ids.dedup();
+    if message_ids.len() > MAX_MESSAGE_IDS {
+        return Err(AppError::Validation(vec![FieldError::new(
+            "message_ids",
+            format!("must list at most {MAX_MESSAGE_IDS} messages"),
+        )]));
+    }
+
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = ?1)")
+        .bind(reported_id)
+        .fetch_one(&state.pool)
+        .await?;
+    if !exists {
+        return Err(AppError::NotFound("user"));
+    }
+
+    // Evidence must come from the conversation between reporter and reported
+    // (archived messages included); anything else is rejected.
+    if !message_ids.is_empty() {
+        let placeholders: String = (0..message_ids.len())
+            .map(|i| format!("?{}", i + 3))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            r#"
+            SELECT id FROM messages
+            WHERE id IN ({placeholders})
+              AND ((sender_id = ?1 AND recipient_id = ?2)
+                OR (sender_id = ?2 AND recipient_id = ?1))
+            "#,
+        );
+        let mut q = sqlx::query_scalar::<_, i64>(&sql).bind(me.id).bind(reported_id);
+        for id in &message_ids {
+            q = q.bind(id);
+        }
+        let found: HashSet<i64> = q.fetch_all(&state.pool).await?.into_iter().collect();
+
+        if let Some(message_id) = message_ids.iter().find(|id| !found.contains(id)) {
+            return Err(AppError::BadRequest(format!(
+                "message {message_id} is not part of this conversation"
+            )));
+        }
+    }
+
+    let mut tx = state.pool.begin().await?;
+
+    let report = sqlx::query_as::<_, Report>(
+        r#"
+        INSERT INTO reports (reporter_id, reported_id, reason, details)
+        VALUES (?1, ?2, ?3, ?4)
+        RETURNING *
+        "#,
+    )
+    .bind(me.id)
+    .bind(reported_id)
+    .bind(payload.reason)
+    .bind(&details)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    for message_id in &message_ids {
+        sqlx::query("INSERT INTO report_messages (report_id, message_id) VALUES (?1, ?2)")
+            .bind(report.id)
+            .bind(message_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    tracing::info!(report_id = report.id, reporter_id = me.id, reported_id, reason = ?report.reason, "user reported");
+    Ok((StatusCode::CREATED, Json(report)).into_response())
+}
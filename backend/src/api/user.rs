@@ -1,19 +1,26 @@
-use axum::{Json, extract::{Multipart, State}, http::StatusCode, response::IntoResponse};
+use axum::{Json, extract::{Multipart, Path, State}, http::StatusCode, response::IntoResponse};
+use bytes::Bytes;
 use axum_login::AuthSession;
-use object_store::{ObjectStoreExt, PutPayload, path::Path as StorePath};
-use sqlx::SqlitePool;
+use sqlx::{Sqlite, SqlitePool, Transaction};
 
-use crate::{AppState, auth::backend::MicrosoftBackend, error::AppError, models::{User, UserResponse}};
+use crate::{
+    AppState,
+    auth::backend::AuthBackend,
+    error::{AppError, FieldError},
+    images::{CleanImage, photo_key, store_image},
+    models::{PhotoOrder, User, UserResponse},
+    photos,
+    realtime::ServerEvent,
+    validation::{PhotoPatch, ProfileInput, ProfilePatch, ProfileUpdate, check},
+};
 
-/// Returns the currently authenticated user (tokens redacted), including interests.
+/// Returns the currently authenticated user (tokens redacted), including
+/// interests and photos.
 pub async fn me(
-    auth_session: AuthSession<MicrosoftBackend>,
+    auth_session: AuthSession<AuthBackend>,
     State(pool): State<SqlitePool>,
 ) -> Result<impl IntoResponse, AppError> {
-    let user = match auth_session.user {
-        Some(u) => u,
-        None => return Ok(StatusCode::UNAUTHORIZED.into_response()),
-    };
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
 
     let interests: Vec<String> = sqlx::query_scalar(
         r#"
@@ -27,8 +34,9 @@ pub async fn me(
     .bind(user.id)
     .fetch_all(&pool)
     .await?;
+    let photos = photos::for_user(&pool, user.id).await?;
 
-    Ok(Json(UserResponse::from_user(user, interests)).into_response())
+    Ok(Json(UserResponse::own(user, interests, photos)).into_response())
 }
 
 // ---------------------------------------------------------------------------
@@ -36,135 +44,351 @@ pub async fn me(
 // ---------------------------------------------------------------------------
 
 /// Accepts multipart/form-data with optional fields:
-///   - `bio`   — plain text biography
-///   - `image` — image file (stored in object_store)
+///   - `bio`       — plain text biography
+///   - `major`     — free text, or one of the configured majors
+///   - `age`       — whole number within the configured bounds
+///   - `interests` — comma-separated and/or repeated; replaces the full list
+///   - `image`     — JPEG, PNG or WebP (HEIC with the `heic` feature);
+///     replaces the primary photo
 ///
 /// Only provided fields are updated; omitted fields keep their current value.
+/// Fields are validated against the configured
+/// [`ProfileRules`](crate::validation::ProfileRules) and the image against
+/// [`ImageRules`](crate::images::ImageRules) first, and every failure is
+/// reported at once; nothing is written unless all of them pass. The image is
+/// stored re-encoded, without its metadata.
+/// A `profile.updated` event is published to the user and all of their matches.
 pub async fn update_profile(
-    auth_session: AuthSession<MicrosoftBackend>,
+    auth_session: AuthSession<AuthBackend>,
     State(state): State<AppState>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, AppError> {
     let user = auth_session.user.ok_or(AppError::Unauthorized)?;
 
-    let mut bio: Option<String> = None;
-    let mut image_key: Option<String> = None;
-    let mut major: Option<String> = None;
-    let mut age: Option<i64> = None;
-    let mut interests: Option<Vec<String>> = None;
+    let mut input = ProfileInput::default();
+    let mut image: Option<Bytes> = None;
 
-    while let Some(field) = multipart
-        .next_field()
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?
-    {
+    while let Some(field) = multipart.next_field().await? {
         match field.name().unwrap_or("") {
-            "bio" => {
-                bio = Some(
-                    field
-                        .text()
-                        .await
-                        .map_err(|e| AppError::Internal(e.to_string()))?,
-                );
-            }
-            "major" => {
-                major = Some(
-                    field
-                        .text()
-                        .await
-                        .map_err(|e| AppError::Internal(e.to_string()))?,
-                );
-            }
-            "age" => {
-                let text = field
-                    .text()
-                    .await
-                    .map_err(|e| AppError::Internal(e.to_string()))?;
-                age = text.trim().parse::<i64>().ok();
-            }
+            "bio" => input.bio = Some(field.text().await?),
+            "major" => input.major = Some(field.text().await?),
+            "age" => input.age = Some(field.text().await?),
             "interests" => {
-                let text = field
-                    .text()
-                    .await
-                    .map_err(|e| AppError::Internal(e.to_string()))?;
-                interests = Some(
-                    text.split(',')
-                        .map(|s| s.trim().to_string())
-                        .filter(|s| !s.is_empty())
-                        .collect(),
-                );
-            }
-            "image" => {
-                let data = field
-                    .bytes()
-                    .await
-                    .map_err(|e| AppError::Internal(e.to_string()))?;
-
-                let key = format!("profiles/{}", user.id);
-                let path = StorePath::from(key.as_str());
-
-                state
-                    .store
-                    .put(&path, PutPayload::from(data))
-                    .await
-                    .map_err(|e| AppError::Internal(e.to_string()))?;
-
-                image_key = Some(key);
+                let text = field.text().await?;
+                input
+                    .interests
+                    .get_or_insert_with(Vec::new)
+                    .extend(text.split(',').map(str::to_string));
             }
+            "image" => image = Some(field.bytes().await?),
             _ => {}
         }
     }
 
+    let update = state.config.profile.validate(input);
+    let image = match image {
+        Some(data) => Some(state.config.images.clean(data).await.map_err(|e| e.for_field("image"))),
+        None => None,
+    }
+    .transpose();
+    let (update, image) = match (update, image) {
+        (Ok(update), Ok(image)) => (update, image),
+        (Err(AppError::Validation(mut fields)), Err(AppError::Validation(more))) => {
+            fields.extend(more);
+            return Err(AppError::Validation(fields));
+        }
+        (Err(e), _) | (_, Err(e)) => return Err(e),
+    };
+
+    let image_key = match &image {
+        Some(image) => Some(store_photo(&state, user.id, image).await?),
+        None => None,
+    };
+
+    let mut tx = state.pool.begin().await?;
+    apply_update(&mut tx, user.id, &update).await?;
+    if let (Some(key), Some(image)) = (&image_key, &image) {
+        photos::replace_primary(&mut tx, user.id, key, image.content_type).await?;
+    }
+    tx.commit().await?;
+
+    let profile = publish_profile(&state, user.id).await?;
+    Ok(Json(profile).into_response())
+}
+
+// ---------------------------------------------------------------------------
+// PATCH /user/profile — update profile fields from JSON
+// ---------------------------------------------------------------------------
+
+/// Accepts a JSON object with any of `full_name`, `bio`, `major`, `age`,
+/// `is_rso` and `interests`. Omitted fields keep their current value; `null`
+/// clears a field. Validation and the single-transaction write are the same
+/// as for `POST /user/profile`.
+///
+/// Returns the updated profile and publishes `profile.updated` like the
+/// multipart endpoint.
+pub async fn patch_profile(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Json(patch): Json<ProfilePatch>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let update = state.config.profile.validate_patch(patch)?;
+
+    let mut tx = state.pool.begin().await?;
+    apply_update(&mut tx, user.id, &update).await?;
+    tx.commit().await?;
+
+    let profile = publish_profile(&state, user.id).await?;
+    Ok(Json(profile))
+}
+
+// ---------------------------------------------------------------------------
+// PUT / DELETE /user/profile/photo
+// ---------------------------------------------------------------------------
+
+/// `PUT /user/profile/photo`
+///
+/// Replaces the primary photo with the raw request body, checked and
+/// re-encoded like the multipart `image` field, keeping its place and
+/// caption in the gallery. Returns the updated profile.
+pub async fn put_profile_photo(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    if body.is_empty() {
+        return Err(AppError::BadRequest("request body must be an image".into()));
+    }
+
+    let image = state
+        .config
+        .images
+        .clean(body)
+        .await
+        .map_err(|e| e.for_field("image"))?;
+    let key = store_photo(&state, user.id, &image).await?;
+    let mut tx = state.pool.begin().await?;
+    photos::replace_primary(&mut tx, user.id, &key, image.content_type).await?;
+    tx.commit().await?;
+
+    let profile = publish_profile(&state, user.id).await?;
+    Ok(Json(profile))
+}
+
+/// `DELETE /user/profile/photo`
+///
+/// Removes the primary photo; the next photo in the gallery takes its place.
+/// The stored object is kept for the image retention period. Returns
+/// `204 No Content`, or 404 if there was none.
+pub async fn delete_profile_photo(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let mut tx = state.pool.begin().await?;
+    photos::remove_primary(&mut tx, user.id).await?;
+    tx.commit().await?;
+
+    publish_profile(&state, user.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ---------------------------------------------------------------------------
+// /user/photos — photo gallery
+// ---------------------------------------------------------------------------
+
+/// `POST /user/photos`
+///
+/// Adds a photo to the end of the gallery from multipart/form-data with an
+/// `image` field (checked and re-encoded like the profile `image`) and an
+/// optional `caption`. The first photo becomes primary. Fails validation once
+/// the gallery holds the configured maximum. Returns `201 Created` with the
+/// updated profile.
+pub async fn add_photo(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let mut caption: Option<String> = None;
+    let mut image: Option<Bytes> = None;
+    while let Some(field) = multipart.next_field().await? {
+        match field.name().unwrap_or("") {
+            "caption" => caption = Some(field.text().await?),
+            "image" => image = Some(field.bytes().await?),
+            _ => {}
+        }
+    }
+
+    let mut errors = Vec::new();
+    let caption = caption
+        .and_then(|c| check(&mut errors, "caption", state.config.profile.caption(&c)))
+        .filter(|c| !c.is_empty());
+    let image = match image {
+        Some(data) => match state.config.images.clean(data).await {
+            Ok(image) => Some(image),
+            Err(e) => match e.for_field("image") {
+                AppError::Validation(more) => {
+                    errors.extend(more);
+                    None
+                }
+                e => return Err(e),
+            },
+        },
+        None => {
+            errors.push(FieldError::new("image", "is required"));
+            None
+        }
+    };
+    let Some(image) = image.filter(|_| errors.is_empty()) else {
+        return Err(AppError::Validation(errors));
+    };
+
+    let key = store_photo(&state, user.id, &image).await?;
+    let mut tx = state.pool.begin().await?;
+    photos::add(
+        &mut tx,
+        user.id,
+        state.config.profile.max_photos,
+        &key,
+        image.content_type,
+        caption.as_deref(),
+    )
+    .await?;
+    tx.commit().await?;
+
+    let profile = publish_profile(&state, user.id).await?;
+    Ok((StatusCode::CREATED, Json(profile)))
+}
+
+/// `PATCH /user/photos/:id`
+///
+/// Accepts a JSON object with `caption` (`null` clears it) and/or
+/// `is_primary: true`. Returns the updated profile, or 404 if the photo isn't
+/// the caller's.
+pub async fn update_photo(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path(photo_id): Path<i64>,
+    Json(patch): Json<PhotoPatch>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let update = state.config.profile.validate_photo_patch(patch)?;
+
+    let mut tx = state.pool.begin().await?;
+    if let Some(caption) = &update.caption {
+        photos::set_caption(&mut tx, user.id, photo_id, caption.as_deref()).await?;
+    }
+    if update.make_primary {
+        photos::set_primary(&mut tx, user.id, photo_id).await?;
+    }
+    tx.commit().await?;
+
+    let profile = publish_profile(&state, user.id).await?;
+    Ok(Json(profile))
+}
+
+/// `PUT /user/photos/order`
+///
+/// Accepts `{ "ids": [...] }` listing every photo of the caller in the new
+/// display order. Returns the updated profile.
+pub async fn reorder_photos(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Json(order): Json<PhotoOrder>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let mut tx = state.pool.begin().await?;
+    photos::reorder(&mut tx, user.id, &order.ids).await?;
+    tx.commit().await?;
+
+    let profile = publish_profile(&state, user.id).await?;
+    Ok(Json(profile))
+}
+
+/// `DELETE /user/photos/:id`
+///
+/// Removes a photo from the gallery; if it was primary, the next photo takes
+/// its place. The stored object is kept for the image retention period.
+/// Returns `204 No Content`, or 404 if the photo isn't the caller's.
+pub async fn delete_photo(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    Path(photo_id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
+
+    let mut tx = state.pool.begin().await?;
+    photos::remove(&mut tx, user.id, photo_id).await?;
+    tx.commit().await?;
+
+    publish_profile(&state, user.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ---------------------------------------------------------------------------
+// Helpers
+// ---------------------------------------------------------------------------
+
+/// Write a cleaned profile photo and its variants to the store under its
+/// content-addressed key and return the key.
+async fn store_photo(state: &AppState, user_id: i64, image: &CleanImage) -> Result<String, AppError> {
+    let key = photo_key(user_id, &image.data);
+    store_image(state.store.as_ref(), &state.config.images, &key, image.data.clone()).await?;
+    Ok(key)
+}
+
+/// Apply validated field changes inside `tx`.
+async fn apply_update(
+    tx: &mut Transaction<'_, Sqlite>,
+    user_id: i64,
+    update: &ProfileUpdate,
+) -> Result<(), AppError> {
     sqlx::query(
         r#"
         UPDATE users
-        SET bio       = COALESCE(?1, bio),
-            image_key = COALESCE(?2, image_key),
-            major     = COALESCE(?3, major),
-            age       = COALESCE(?4, age),
+        SET full_name  = CASE WHEN ?1 THEN ?2 ELSE full_name END,
+            bio        = CASE WHEN ?3 THEN ?4 ELSE bio END,
+            major      = CASE WHEN ?5 THEN ?6 ELSE major END,
+            age        = CASE WHEN ?7 THEN ?8 ELSE age END,
+            is_rso     = COALESCE(?9, is_rso),
             updated_at = datetime('now')
-        WHERE id = ?5
+        WHERE id = ?10
         "#,
     )
-    .bind(&bio)
-    .bind(&image_key)
-    .bind(&major)
-    .bind(age)
-    .bind(user.id)
-    .execute(&state.pool)
+    .bind(update.full_name.is_some())
+    .bind(update.full_name.clone().flatten())
+    .bind(update.bio.is_some())
+    .bind(update.bio.clone().flatten())
+    .bind(update.major.is_some())
+    .bind(update.major.clone().flatten())
+    .bind(update.age.is_some())
+    .bind(update.age.flatten())
+    .bind(update.is_rso)
+    .bind(user_id)
+    .execute(&mut **tx)
     .await?;
 
-    // Replace interests if provided
-    if let Some(ref interest_names) = interests {
-        sqlx::query("DELETE FROM user_interests WHERE user_id = ?1")
-            .bind(user.id)
-            .execute(&state.pool)
-            .await?;
-
-        for name in interest_names {
-            sqlx::query("INSERT OR IGNORE INTO interests (name) VALUES (?1)")
-                .bind(name)
-                .execute(&state.pool)
-                .await?;
-
-            let interest_id: i64 =
-                sqlx::query_scalar("SELECT id FROM interests WHERE name = ?1")
-                    .bind(name)
-                    .fetch_one(&state.pool)
-                    .await?;
-
-            sqlx::query(
-                "INSERT OR IGNORE INTO user_interests (user_id, interest_id) VALUES (?1, ?2)",
-            )
-            .bind(user.id)
-            .bind(interest_id)
-            .execute(&state.pool)
-            .await?;
-        }
+    if let Some(names) = &update.interests {
+        replace_interests(tx, user_id, names).await?;
     }
 
+    Ok(())
+}
+
+/// Load the user's current profile and send it as `profile.updated` to them
+/// and all of their matches. Returns it as the user sees it themselves.
+async fn publish_profile(state: &AppState, user_id: i64) -> Result<UserResponse, AppError> {
     let updated = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?1")
-        .bind(user.id)
+        .bind(user_id)
         .fetch_one(&state.pool)
         .await?;
 
@@ -180,6 +404,62 @@ pub async fn update_profile(
     .bind(updated.id)
     .fetch_all(&state.pool)
     .await?;
+    let photos = photos::for_user(&state.pool, updated.id).await?;
+
+    let own = UserResponse::own(updated.clone(), interests.clone(), photos.clone());
+    let profile = UserResponse::from_user(updated, interests, photos);
+
+    let matched_ids: Vec<i64> = sqlx::query_scalar(
+        r#"
+        SELECT CASE WHEN user1_id = ?1 THEN user2_id ELSE user1_id END
+        FROM matches
+        WHERE user1_id = ?1 OR user2_id = ?1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&state.pool)
+    .await?;
+
+    for recipient_id in std::iter::once(user_id).chain(matched_ids) {
+        state
+            .hub
+            .publish(&state.pool, recipient_id, ServerEvent::ProfileUpdated { profile: profile.clone() })
+            .await?;
+    }
+
+    Ok(own)
+}
+
+/// Replace the user's interests with `names` (already normalised), creating
+/// interest rows as needed.
+async fn replace_interests(
+    tx: &mut Transaction<'_, Sqlite>,
+    user_id: i64,
+    names: &[String],
+) -> Result<(), AppError> {
+    sqlx::query("DELETE FROM user_interests WHERE user_id = ?1")
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+
+    for name in names {
+        let interest_id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO interests (name) VALUES (?1)
+            ON CONFLICT(name) DO UPDATE SET name = excluded.name
+            RETURNING id
+            "#,
+        )
+        .bind(name)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        sqlx::query("INSERT OR IGNORE INTO user_interests (user_id, interest_id) VALUES (?1, ?2)")
+            .bind(user_id)
+            .bind(interest_id)
+            .execute(&mut **tx)
+            .await?;
+    }
 
-    Ok(Json(UserResponse::from_user(updated, interests)).into_response())
+    Ok(())
 }
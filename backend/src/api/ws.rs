@@ -0,0 +1,116 @@
+use axum::{
+    extract::{
+        State,
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+    },
+    response::Response,
+};
+use axum_login::AuthSession;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    AppState,
+    auth::backend::AuthBackend,
+    authz::ensure_matched,
+    error::AppError,
+    realtime::ServerEvent,
+};
+
+/// Frames a client may send over `/ws`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientEvent {
+    /// The user is typing a message to `recipient_id`.
+    Typing { recipient_id: i64 },
+    /// The user has read every message from `sender_id` up to `up_to`.
+    Read { sender_id: i64, up_to: i64 },
+}
+
+/// `GET /ws`
+///
+/// Upgrades to a WebSocket that receives new messages, typing indicators and
+/// read receipts for the authenticated user as JSON `ServerEvent` frames.
+pub async fn ws_handler(
+    auth_session: AuthSession<AuthBackend>,
+    State(state): State<AppState>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthorized)?;
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, state, user.id)))
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState, user_id: i64) {
+    let mut rx = state.hub.subscribe(user_id);
+    tracing::debug!(user_id, "websocket connected");
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(delivery) => {
+                    let Ok(text) = serde_json::to_string(&delivery.event) else { continue };
+                    if socket.send(WsMessage::Text(text.into())).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(user_id, skipped, "websocket client lagging, events dropped");
+                }
+                Err(RecvError::Closed) => break,
+            },
+            frame = socket.recv() => match frame {
+                Some(Ok(WsMessage::Text(text))) => {
+                    match serde_json::from_str::<ClientEvent>(&text) {
+                        Ok(event) => {
+                            if let Err(e) = handle_client_event(&state, user_id, event).await {
+                                tracing::warn!(user_id, error = %e, "failed to handle websocket frame");
+                            }
+                        }
+                        Err(e) => tracing::debug!(user_id, error = %e, "ignoring malformed websocket frame"),
+                    }
+                }
+                Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    drop(rx);
+    state.hub.prune(user_id);
+    tracing::debug!(user_id, "websocket disconnected");
+}
+
+async fn handle_client_event(
+    state: &AppState,
+    user_id: i64,
+    event: ClientEvent,
+) -> Result<(), AppError> {
+    match event {
+        ClientEvent::Typing { recipient_id } => {
+            ensure_matched(&state.pool, user_id, recipient_id).await?;
+            state.hub.send(recipient_id, ServerEvent::Typing { user_id });
+        }
+        ClientEvent::Read { sender_id, up_to } => {
+            ensure_matched(&state.pool, user_id, sender_id).await?;
+            let marked = sqlx::query(
+                r#"
+                UPDATE messages
+                SET read_at = datetime('now')
+                WHERE sender_id = ?1 AND recipient_id = ?2 AND id <= ?3 AND read_at IS NULL
+                "#,
+            )
+            .bind(sender_id)
+            .bind(user_id)
+            .bind(up_to)
+            .execute(&state.pool)
+            .await?
+            .rows_affected();
+
+            // Nothing new was read; don't tell the sender otherwise.
+            if marked > 0 {
+                state.hub.send(sender_id, ServerEvent::Read { user_id, up_to });
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,103 +1,355 @@
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
-use oauth2::{
-    basic::BasicClient, AuthType, AuthUrl, ClientId, ClientSecret, CsrfToken,
-    EndpointNotSet, EndpointSet, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
-};
-use serde::Deserialize;
+use std::sync::Arc;
+
 use sqlx::SqlitePool;
 
 use crate::{
-    auth::Credentials,
+    auth::{
+        Credentials,
+        policy::SignInPolicy,
+        provider::{Identity, IdentityProvider, ProviderTokens, Refreshed},
+        rotate_session_secret,
+    },
+    crypto::{TokenCipher, TokenColumn, TokenSlot},
     error::AppError,
-    models::{IdTokenClaims, User},
+    models::User,
 };
 use axum_login::{AuthnBackend, UserId};
 
-/// oauth2 client with only the auth endpoint configured (we do token exchange via reqwest).
-type MsOAuthClient = BasicClient<
-    EndpointSet,
-    EndpointNotSet,
-    EndpointNotSet,
-    EndpointNotSet,
-    EndpointNotSet,
->;
+/// Identities refreshed per background pass, to keep bursts at the token
+/// endpoints small.
+const REFRESH_BATCH: i64 = 100;
+
+/// Name of the Microsoft Entra provider. Accounts created before other
+/// providers existed use its subject (the `oid`) as `users.oid`.
+pub const MICROSOFT: &str = "microsoft";
 
+/// axum-login backend: signs users in through the configured identity
+/// providers and links them to local accounts by `(provider, subject)`.
 #[derive(Clone, Debug)]
-pub struct MicrosoftBackend {
+pub struct AuthBackend {
     pool: SqlitePool,
-    oauth_client: MsOAuthClient,
-    http: reqwest::Client,
-    client_id: String,
-    client_secret: String,
-    token_url: String,
-    redirect_url: String,
+    /// In login-page order; the first one is the default for `/auth/login`.
+    providers: Arc<Vec<Arc<dyn IdentityProvider>>>,
+    policy: SignInPolicy,
+    /// Encrypts tokens before they are written to `identities`.
+    cipher: TokenCipher,
 }
 
-impl MicrosoftBackend {
-    pub fn new(
-        pool: SqlitePool,
-        client_id: String,
-        client_secret: String,
-        tenant: &str,
-        redirect_url: String,
-    ) -> Result<Self, AppError> {
-        let auth_url = AuthUrl::new(format!(
-            "https://login.microsoftonline.com/{tenant}/oauth2/v2.0/authorize"
-        ))
-        .map_err(|e| AppError::OAuth(e.to_string()))?;
-
-        let token_url = format!(
-            "https://login.microsoftonline.com/{tenant}/oauth2/v2.0/token"
-        );
-
-        let redirect = RedirectUrl::new(redirect_url.clone())
-            .map_err(|e| AppError::OAuth(e.to_string()))?;
-
-        let oauth_client = BasicClient::new(ClientId::new(client_id.clone()))
-            .set_client_secret(ClientSecret::new(client_secret.clone()))
-            .set_auth_uri(auth_url)
-            .set_auth_type(AuthType::RequestBody)
-            .set_redirect_uri(redirect);
-
-        let http = reqwest::Client::builder()
-            .redirect(reqwest::redirect::Policy::none())
-            .build()
-            .map_err(|e| AppError::Internal(e.to_string()))?;
-
-        Ok(Self {
+impl AuthBackend {
+    pub fn new(pool: SqlitePool, cipher: TokenCipher) -> Self {
+        Self {
             pool,
-            oauth_client,
-            http,
-            client_id,
-            client_secret,
-            token_url,
-            redirect_url,
+            providers: Arc::new(Vec::new()),
+            policy: SignInPolicy::default(),
+            cipher,
+        }
+    }
+
+    /// Add an identity provider. Names must be unique.
+    pub fn with_provider(mut self, provider: impl IdentityProvider + 'static) -> Self {
+        Arc::make_mut(&mut self.providers).push(Arc::new(provider));
+        self
+    }
+
+    /// Restrict sign-in to the given tenants / email domains.
+    pub fn with_sign_in_policy(mut self, policy: SignInPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn providers(&self) -> &[Arc<dyn IdentityProvider>] {
+        &self.providers
+    }
+
+    pub fn provider(&self, name: &str) -> Option<&Arc<dyn IdentityProvider>> {
+        self.providers.iter().find(|p| p.name() == name)
+    }
+
+    /// Exchange the identity's refresh token and store the new tokens. An
+    /// `invalid_grant` answer means the account is gone or has been disabled
+    /// at the provider, so the user is deactivated.
+    pub async fn refresh_tokens(&self, provider: &str, subject: &str) -> Result<RefreshOutcome, AppError> {
+        let row = sqlx::query_as::<_, (i64, Option<String>, bool)>(
+            r#"
+            SELECT i.user_id, i.refresh_token, u.deactivated_at IS NOT NULL
+            FROM identities i JOIN users u ON u.id = i.user_id
+            WHERE i.provider = ?1 AND i.subject = ?2
+            "#,
+        )
+        .bind(provider)
+        .bind(subject)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((user_id, refresh_token, deactivated)) = row else {
+            return Ok(RefreshOutcome::NoRefreshToken);
+        };
+        if deactivated {
+            return Ok(RefreshOutcome::Deactivated);
+        }
+        let Some(refresh_token) = refresh_token else {
+            return Ok(RefreshOutcome::NoRefreshToken);
+        };
+        let Some(idp) = self.provider(provider) else {
+            return Err(AppError::Internal(format!("identity provider {provider} is not configured")));
+        };
+
+        let slot = TokenSlot::new(user_id, provider, subject, TokenColumn::RefreshToken);
+        let refresh_token = self.cipher.decrypt(&refresh_token, slot)?;
+        let tokens = match idp.refresh(&refresh_token, subject).await? {
+            Refreshed::Tokens(tokens) => tokens,
+            Refreshed::Revoked(description) => {
+                tracing::warn!(user_id, provider, ?description, "refresh token rejected, deactivating account");
+                self.deactivate(user_id, provider, subject).await?;
+                return Ok(RefreshOutcome::Deactivated);
+            }
+        };
+
+        let sealed = self.seal(user_id, provider, subject, &tokens)?;
+        sqlx::query(
+            r#"
+            UPDATE identities
+            SET access_token        = ?1,
+                refresh_token       = COALESCE(?2, refresh_token),
+                id_token            = COALESCE(?3, id_token),
+                token_expires_at    = datetime('now', ?4),
+                tokens_refreshed_at = datetime('now'),
+                updated_at          = datetime('now')
+            WHERE provider = ?5 AND subject = ?6
+            "#,
+        )
+        .bind(sealed.access_token)
+        .bind(sealed.refresh_token)
+        .bind(sealed.id_token)
+        .bind(tokens.expires_in.map(|s| format!("+{s} seconds")))
+        .bind(provider)
+        .bind(subject)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(RefreshOutcome::Refreshed)
+    }
+
+    /// Refresh up to [`REFRESH_BATCH`] identities whose tokens were last
+    /// renewed more than `max_age_hours` ago, oldest first.
+    pub async fn refresh_stale(&self, max_age_hours: i64) -> Result<(), AppError> {
+        let due: Vec<(String, String)> = sqlx::query_as(
+            r#"
+            SELECT i.provider, i.subject
+            FROM identities i JOIN users u ON u.id = i.user_id
+            WHERE i.refresh_token IS NOT NULL
+              AND u.deactivated_at IS NULL
+              AND u.banned_at IS NULL
+              AND (i.tokens_refreshed_at IS NULL OR i.tokens_refreshed_at <= datetime('now', ?1))
+            ORDER BY i.tokens_refreshed_at IS NOT NULL, i.tokens_refreshed_at
+            LIMIT ?2
+            "#,
+        )
+        .bind(format!("-{max_age_hours} hours"))
+        .bind(REFRESH_BATCH)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let (mut refreshed, mut deactivated) = (0, 0);
+        for (provider, subject) in due {
+            // Identities of a provider that has since been removed from the
+            // configuration are left alone.
+            if self.provider(&provider).is_none() {
+                continue;
+            }
+            match self.refresh_tokens(&provider, &subject).await {
+                Ok(RefreshOutcome::Refreshed) => refreshed += 1,
+                Ok(RefreshOutcome::Deactivated) => deactivated += 1,
+                Ok(RefreshOutcome::NoRefreshToken) => {}
+                // Transient failures (network, IdP outage) are retried on
+                // the next pass; only `invalid_grant` deactivates.
+                Err(e) => tracing::warn!(provider, error = %e, "token refresh failed"),
+            }
+        }
+        if refreshed + deactivated > 0 {
+            tracing::info!(refreshed, deactivated, "refreshed stored OAuth tokens");
+        }
+        Ok(())
+    }
+
+    /// Spawn a task that runs [`Self::refresh_stale`] every `period`.
+    pub fn spawn_token_refresh(&self, period: std::time::Duration, max_age_hours: i64) {
+        let backend = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = backend.refresh_stale(max_age_hours).await {
+                    tracing::warn!(error = %e, "failed to refresh stored tokens");
+                }
+            }
+        });
+    }
+
+    /// Mark the account deactivated, drop the revoked identity's tokens and
+    /// end the user's sessions.
+    async fn deactivate(&self, user_id: i64, provider: &str, subject: &str) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "UPDATE users SET deactivated_at = datetime('now'), updated_at = datetime('now') WHERE id = ?1",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+            UPDATE identities
+            SET access_token = NULL, refresh_token = NULL, id_token = NULL,
+                token_expires_at = NULL, updated_at = datetime('now')
+            WHERE provider = ?1 AND subject = ?2
+            "#,
+        )
+        .bind(provider)
+        .bind(subject)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        rotate_session_secret(&self.pool, user_id).await?;
+        Ok(())
+    }
+
+    /// Encrypt `tokens` for storage in the identity `provider`/`subject` of `user_id`.
+    fn seal(
+        &self,
+        user_id: i64,
+        provider: &str,
+        subject: &str,
+        tokens: &ProviderTokens,
+    ) -> Result<SealedTokens, AppError> {
+        let encrypt = |token: &str, column| {
+            self.cipher.encrypt(token, TokenSlot::new(user_id, provider, subject, column))
+        };
+        let refresh_token = tokens.refresh_token.as_deref();
+        let id_token = tokens.id_token.as_deref();
+        Ok(SealedTokens {
+            access_token: encrypt(&tokens.access_token, TokenColumn::AccessToken)?,
+            refresh_token: refresh_token.map(|t| encrypt(t, TokenColumn::RefreshToken)).transpose()?,
+            id_token: id_token.map(|t| encrypt(t, TokenColumn::IdToken)).transpose()?,
         })
     }
 
-    pub fn authorize_url(&self) -> (oauth2::url::Url, CsrfToken, PkceCodeVerifier) {
-        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
-        let (url, csrf) = self
-            .oauth_client
-            .authorize_url(CsrfToken::new_random)
-            .add_scope(Scope::new("openid".into()))
-            .add_scope(Scope::new("email".into()))
-            .add_scope(Scope::new("profile".into()))
-            .add_scope(Scope::new("offline_access".into()))
-            .set_pkce_challenge(pkce_challenge)
-            .url();
-        (url, csrf, pkce_verifier)
+    /// Find or create the local account for `identity` and store its tokens.
+    ///
+    /// Accounts are only ever linked through `identities`, never by email:
+    /// not every provider proves ownership of the address it reports.
+    async fn link(&self, provider: &str, identity: &Identity, tokens: &ProviderTokens) -> Result<User, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let linked: Option<i64> = sqlx::query_scalar(
+            "SELECT user_id FROM identities WHERE provider = ?1 AND subject = ?2",
+        )
+        .bind(provider)
+        .bind(&identity.subject)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let user = match linked {
+            Some(user_id) => {
+                sqlx::query_as::<_, User>(
+                    r#"
+                    UPDATE users
+                    SET email          = ?1,
+                        display_name   = ?2,
+                        tenant_id      = ?3,
+                        deactivated_at = NULL,
+                        updated_at     = datetime('now')
+                    WHERE id = ?4
+                    RETURNING *
+                    "#,
+                )
+                .bind(&identity.email)
+                .bind(&identity.name)
+                .bind(&identity.tenant_id)
+                .bind(user_id)
+                .fetch_one(&mut *tx)
+                .await?
+            }
+            // `users.oid` stays unique across providers: Microsoft accounts
+            // keep the bare oid, everyone else gets `provider:subject`.
+            None => {
+                let oid = if provider == MICROSOFT {
+                    identity.subject.clone()
+                } else {
+                    format!("{provider}:{}", identity.subject)
+                };
+                sqlx::query_as::<_, User>(
+                    r#"
+                    INSERT INTO users (oid, email, display_name, tenant_id, session_secret, updated_at)
+                    VALUES (?1, ?2, ?3, ?4, lower(hex(randomblob(32))), datetime('now'))
+                    ON CONFLICT(oid) DO UPDATE SET
+                        email          = excluded.email,
+                        display_name   = excluded.display_name,
+                        tenant_id      = excluded.tenant_id,
+                        deactivated_at = NULL,
+                        updated_at     = excluded.updated_at
+                    RETURNING *
+                    "#,
+                )
+                .bind(oid)
+                .bind(&identity.email)
+                .bind(&identity.name)
+                .bind(&identity.tenant_id)
+                .fetch_one(&mut *tx)
+                .await?
+            }
+        };
+
+        let sealed = self.seal(user.id, provider, &identity.subject, tokens)?;
+        sqlx::query(
+            r#"
+            INSERT INTO identities (provider, subject, user_id, email, access_token, refresh_token,
+                                    id_token, token_expires_at, tokens_refreshed_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, datetime('now', ?8), datetime('now'), datetime('now'))
+            ON CONFLICT(provider, subject) DO UPDATE SET
+                email               = excluded.email,
+                access_token        = excluded.access_token,
+                refresh_token       = excluded.refresh_token,
+                id_token            = excluded.id_token,
+                token_expires_at    = excluded.token_expires_at,
+                tokens_refreshed_at = excluded.tokens_refreshed_at,
+                updated_at          = excluded.updated_at
+            "#,
+        )
+        .bind(provider)
+        .bind(&identity.subject)
+        .bind(user.id)
+        .bind(&identity.email)
+        .bind(sealed.access_token)
+        .bind(sealed.refresh_token)
+        .bind(sealed.id_token)
+        .bind(tokens.expires_in.map(|s| format!("+{s} seconds")))
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(user)
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct MsTokenResponse {
+/// Result of [`AuthBackend::refresh_tokens`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefreshOutcome {
+    Refreshed,
+    /// Nothing to refresh with (no `offline_access` grant, seeded users).
+    NoRefreshToken,
+    /// The provider rejected the refresh token; the user is deactivated.
+    Deactivated,
+}
+
+/// Provider tokens encrypted for one identity row.
+struct SealedTokens {
     access_token: String,
     refresh_token: Option<String>,
     id_token: Option<String>,
 }
 
-impl AuthnBackend for MicrosoftBackend {
+impl AuthnBackend for AuthBackend {
     type User = User;
     type Credentials = Credentials;
     type Error = AppError;
@@ -106,73 +358,61 @@ impl AuthnBackend for MicrosoftBackend {
         &self,
         creds: Self::Credentials,
     ) -> Result<Option<Self::User>, Self::Error> {
-        let token: MsTokenResponse = self
-            .http
-            .post(&self.token_url)
-            .form(&[
-                ("client_id", self.client_id.as_str()),
-                ("client_secret", self.client_secret.as_str()),
-                ("code", creds.code.as_str()),
-                ("code_verifier", creds.pkce_verifier.as_str()),
-                ("redirect_uri", self.redirect_url.as_str()),
-                ("grant_type", "authorization_code"),
-            ])
-            .send()
-            .await
-            .map_err(|e| AppError::OAuth(e.to_string()))?
-            .json()
-            .await
-            .map_err(|e| AppError::OAuth(e.to_string()))?;
-
-        let id_token = token
-            .id_token
-            .as_deref()
-            .ok_or_else(|| AppError::OAuth("missing id_token".into()))?;
-
-        let claims: IdTokenClaims = {
-            let segment = id_token
-                .split('.')
-                .nth(1)
-                .ok_or_else(|| AppError::OAuth("malformed id_token".into()))?;
-            let bytes = URL_SAFE_NO_PAD
-                .decode(segment)
-                .map_err(|e| AppError::OAuth(e.to_string()))?;
-            serde_json::from_slice(&bytes).map_err(|e| AppError::OAuth(e.to_string()))?
-        };
+        let provider = self
+            .provider(&creds.provider)
+            .ok_or_else(|| AppError::OAuth(format!("unknown identity provider {}", creds.provider)))?;
 
-        let user = sqlx::query_as::<_, User>(
-            r#"
-            INSERT INTO users (oid, email, display_name, tenant_id, access_token, refresh_token, id_token, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, datetime('now'))
-            ON CONFLICT(oid) DO UPDATE SET
-                email         = excluded.email,
-                display_name  = excluded.display_name,
-                tenant_id     = excluded.tenant_id,
-                access_token  = excluded.access_token,
-                refresh_token = excluded.refresh_token,
-                id_token      = excluded.id_token,
-                updated_at    = excluded.updated_at
-            RETURNING *
-            "#,
-        )
-        .bind(&claims.oid)
-        .bind(&claims.email)
-        .bind(&claims.name)
-        .bind(&claims.tid)
-        .bind(&token.access_token)
-        .bind(&token.refresh_token)
-        .bind(id_token)
-        .fetch_one(&self.pool)
-        .await?;
+        let (identity, tokens) = provider
+            .exchange_code(&creds.code, &creds.pkce_verifier, &creds.nonce)
+            .await?;
+
+        // Checked before linking so denied accounts never get a row.
+        if let Err(e) = self.policy.check(&identity) {
+            tracing::warn!(
+                provider = provider.name(),
+                subject = %identity.subject,
+                tid = ?identity.tenant_id,
+                email = ?identity.email.as_ref().or(identity.preferred_username.as_ref()),
+                reason = %e,
+                "denied login",
+            );
+            return Err(e);
+        }
+
+        let user = self.link(provider.name(), &identity, &tokens).await?;
+
+        if user.banned_at.is_some() || is_suspended(&self.pool, user.id).await? {
+            tracing::info!(user_id = user.id, "rejected login for suspended or banned account");
+            return Err(AppError::Suspended);
+        }
 
         Ok(Some(user))
     }
 
+    /// Suspended, banned and deactivated accounts are filtered out here, so
+    /// axum-login drops their existing sessions on the very next request.
     async fn get_user(&self, user_id: &UserId<Self>) -> Result<Option<Self::User>, Self::Error> {
-        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?1")
-            .bind(user_id)
-            .fetch_optional(&self.pool)
-            .await?;
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            SELECT * FROM users
+            WHERE id = ?1
+              AND banned_at IS NULL
+              AND deactivated_at IS NULL
+              AND (suspended_until IS NULL OR suspended_until <= datetime('now'))
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
         Ok(user)
     }
 }
+
+async fn is_suspended(pool: &SqlitePool, user_id: i64) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE id = ?1 AND suspended_until > datetime('now'))",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+}
@@ -0,0 +1,56 @@
+//! Development-only sign-in as an existing user, for the accounts created by
+//! `bin/seed.rs`. The route is only mounted when `DEV_LOGIN` is set, and the
+//! server refuses to start with it unless bound to a loopback address.
+
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Redirect, Response},
+};
+use serde::Deserialize;
+
+use crate::{auth::routes::AuthSessionType, error::AppError, models::User, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct DevLoginParams {
+    pub oid: String,
+    pub next: Option<String>,
+}
+
+/// `GET /auth/dev-login?oid=seed-oid-001`
+///
+/// Logs in as the user with the given `oid` without going through an
+/// identity provider, then redirects to the frontend like `/auth/callback`.
+pub async fn dev_login(
+    State(state): State<AppState>,
+    mut auth_session: AuthSessionType,
+    Query(params): Query<DevLoginParams>,
+) -> Result<Response, AppError> {
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        SELECT * FROM users
+        WHERE oid = ?1
+          AND banned_at IS NULL
+          AND deactivated_at IS NULL
+          AND (suspended_until IS NULL OR suspended_until <= datetime('now'))
+        "#,
+    )
+    .bind(&params.oid)
+    .fetch_optional(&state.pool)
+    .await?;
+
+    let Some(user) = user else {
+        return Err(AppError::NotFound("user"));
+    };
+
+    auth_session
+        .login(&user)
+        .await
+        .map_err(|e| AppError::Internal(format!("dev login: {e}")))?;
+    tracing::warn!(user_id = user.id, oid = %user.oid, "user logged in via dev login");
+
+    let destination = match params.next {
+        Some(path) if path.starts_with('/') => format!("{}{}", state.config.frontend_url, path),
+        _ => state.config.frontend_url.clone(),
+    };
+    Ok(Redirect::to(&destination).into_response())
+}
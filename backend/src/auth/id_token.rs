@@ -0,0 +1,112 @@
+use jsonwebtoken::{Algorithm, Validation, decode, decode_header};
+
+use crate::{
+    auth::jwks::JwksCache,
+    error::AppError,
+    models::IdTokenClaims,
+};
+
+/// Clock skew tolerated on `exp` / `nbf`.
+const LEEWAY_SECS: u64 = 60;
+
+/// Tenant values that are routing aliases rather than real tenant ids. Tokens
+/// issued through them carry the user's home tenant in `iss`.
+const MULTI_TENANT_ALIASES: &[&str] = &["common", "organizations", "consumers"];
+
+/// How the `iss` claim is checked.
+#[derive(Clone, Debug)]
+pub enum IssuerCheck {
+    /// `iss` must equal this exactly (generic OIDC providers).
+    Exact(String),
+    /// Microsoft Entra: `iss` embeds the tenant the token was issued for.
+    Tenant {
+        /// Issuer with a `{tenantid}` placeholder, e.g.
+        /// `https://login.microsoftonline.com/{tenantid}/v2.0`.
+        template: String,
+        /// Configured tenant; `None` for the multi-tenant aliases, in which
+        /// case any tenant is accepted as long as `iss` agrees with `tid`.
+        tenant: Option<String>,
+    },
+}
+
+impl IssuerCheck {
+    pub fn tenant(template: String, tenant: &str) -> Self {
+        let tenant = (!MULTI_TENANT_ALIASES.contains(&tenant)).then(|| tenant.to_string());
+        Self::Tenant { template, tenant }
+    }
+}
+
+/// Verifies id_tokens: RS256 signature against the provider's JWKS, `aud`,
+/// `iss`, `exp`/`nbf` and the OIDC `nonce`.
+#[derive(Clone, Debug)]
+pub struct IdTokenVerifier {
+    jwks: JwksCache,
+    client_id: String,
+    issuer: IssuerCheck,
+}
+
+impl IdTokenVerifier {
+    pub fn new(jwks: JwksCache, client_id: String, issuer: IssuerCheck) -> Self {
+        Self {
+            jwks,
+            client_id,
+            issuer,
+        }
+    }
+
+    /// `expected_nonce` is `None` for tokens from a refresh, which carry no
+    /// nonce.
+    pub async fn verify(
+        &self,
+        token: &str,
+        expected_nonce: Option<&str>,
+    ) -> Result<IdTokenClaims, AppError> {
+        let header = decode_header(token).map_err(|e| AppError::OAuth(format!("id_token header: {e}")))?;
+        if header.alg != Algorithm::RS256 {
+            return Err(AppError::OAuth(format!("unexpected id_token alg {:?}", header.alg)));
+        }
+        let kid = header
+            .kid
+            .ok_or_else(|| AppError::OAuth("id_token has no kid".into()))?;
+        let key = self.jwks.key(&kid).await?;
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_required_spec_claims(&["exp", "aud", "iss", "sub"]);
+        validation.validate_nbf = true;
+        validation.leeway = LEEWAY_SECS;
+
+        let claims = decode::<IdTokenClaims>(token, &key, &validation)
+            .map_err(|e| AppError::OAuth(format!("id_token rejected: {e}")))?
+            .claims;
+
+        let expected_iss = match &self.issuer {
+            IssuerCheck::Exact(iss) => iss.clone(),
+            // With a fixed tenant it must be exactly that one; with `common`
+            // & co. `iss` must at least agree with the token's own `tid`.
+            IssuerCheck::Tenant { template, tenant } => {
+                let tid = claims
+                    .tid
+                    .as_deref()
+                    .ok_or_else(|| AppError::OAuth("id_token has no tid".into()))?;
+                if let Some(tenant) = tenant
+                    && !tenant.eq_ignore_ascii_case(tid)
+                {
+                    return Err(AppError::OAuth(format!("id_token tenant {tid} not accepted")));
+                }
+                template.replace("{tenantid}", tid)
+            }
+        };
+        if claims.iss != expected_iss {
+            return Err(AppError::OAuth(format!("unexpected id_token issuer {}", claims.iss)));
+        }
+
+        if let Some(expected) = expected_nonce
+            && claims.nonce.as_deref() != Some(expected)
+        {
+            return Err(AppError::OAuth("id_token nonce mismatch".into()));
+        }
+
+        Ok(claims)
+    }
+}
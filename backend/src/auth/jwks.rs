@@ -0,0 +1,109 @@
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{DecodingKey, jwk::JwkSet};
+use tokio::sync::RwLock;
+
+use crate::error::AppError;
+
+/// How long a fetched key set is trusted before it is refreshed.
+const JWKS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Minimum gap between refreshes triggered by an unknown `kid`, so a stream
+/// of tokens with a bogus `kid` can't turn into a stream of JWKS requests.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where signing keys come from.
+#[derive(Clone, Debug)]
+pub enum JwksSource {
+    /// The identity provider's JWKS endpoint.
+    Remote(String),
+    /// A JWKS document on disk (tests, air-gapped development).
+    File(PathBuf),
+}
+
+#[derive(Debug)]
+struct Cached {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// Cached JSON Web Key Set used to verify id_token signatures.
+///
+/// Keys are refreshed after [`JWKS_TTL`], and early when a token names a
+/// `kid` we don't have yet — which is how signing key rotation shows up.
+#[derive(Clone, Debug)]
+pub struct JwksCache {
+    source: JwksSource,
+    http: reqwest::Client,
+    cached: Arc<RwLock<Option<Cached>>>,
+}
+
+impl JwksCache {
+    pub fn new(source: JwksSource, http: reqwest::Client) -> Self {
+        Self {
+            source,
+            http,
+            cached: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Decoding key for `kid`, refreshing the key set if needed.
+    pub async fn key(&self, kid: &str) -> Result<DecodingKey, AppError> {
+        {
+            let cached = self.cached.read().await;
+            if let Some(c) = cached.as_ref()
+                && c.fetched_at.elapsed() < JWKS_TTL
+                && let Some(jwk) = c.keys.find(kid)
+            {
+                return DecodingKey::from_jwk(jwk).map_err(|e| AppError::OAuth(e.to_string()));
+            }
+        }
+
+        let mut cached = self.cached.write().await;
+        // Skip the fetch if the set was just refreshed (possibly by another
+        // request while we waited for the lock).
+        let recently_fetched = cached
+            .as_ref()
+            .is_some_and(|c| c.fetched_at.elapsed() < MIN_REFRESH_INTERVAL);
+        if !recently_fetched {
+            let keys = self.fetch().await?;
+            tracing::debug!(count = keys.keys.len(), "refreshed JWKS");
+            *cached = Some(Cached {
+                keys,
+                fetched_at: Instant::now(),
+            });
+        }
+
+        let jwk = cached
+            .as_ref()
+            .and_then(|c| c.keys.find(kid))
+            .ok_or_else(|| AppError::OAuth(format!("unknown signing key {kid}")))?;
+        DecodingKey::from_jwk(jwk).map_err(|e| AppError::OAuth(e.to_string()))
+    }
+
+    async fn fetch(&self) -> Result<JwkSet, AppError> {
+        match &self.source {
+            JwksSource::Remote(url) => self
+                .http
+                .get(url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| AppError::OAuth(format!("fetch JWKS: {e}")))?
+                .json()
+                .await
+                .map_err(|e| AppError::OAuth(format!("parse JWKS: {e}"))),
+            JwksSource::File(path) => {
+                let bytes = tokio::fs::read(path)
+                    .await
+                    .map_err(|e| AppError::Internal(format!("read {}: {e}", path.display())))?;
+                serde_json::from_slice(&bytes)
+                    .map_err(|e| AppError::OAuth(format!("parse JWKS: {e}")))
+            }
+        }
+    }
+}
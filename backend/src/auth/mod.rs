@@ -1,10 +1,32 @@
 pub mod backend;
+pub mod dev;
+pub mod id_token;
+pub mod jwks;
+pub mod oidc;
+pub mod policy;
+pub mod provider;
 pub mod routes;
 
-pub use backend::MicrosoftBackend;
+pub use backend::AuthBackend;
+
+use sqlx::SqlitePool;
 
 #[derive(Clone, Debug)]
 pub struct Credentials {
+    /// Name of the identity provider the code came from.
+    pub provider: String,
     pub code: String,
     pub pkce_verifier: String,
+    /// OIDC nonce stored in the session at login; must match the id_token.
+    pub nonce: String,
+}
+
+/// Replace the user's session secret, invalidating all of their sessions on
+/// every device. Used for "log out everywhere" and moderator suspensions.
+pub async fn rotate_session_secret(pool: &SqlitePool, user_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET session_secret = lower(hex(randomblob(32))) WHERE id = ?1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
 }
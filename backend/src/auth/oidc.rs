@@ -0,0 +1,422 @@
+use async_trait::async_trait;
+use oauth2::{
+    basic::BasicClient, AuthType, AuthUrl, ClientId, ClientSecret, CsrfToken,
+    EndpointNotSet, EndpointSet, PkceCodeChallenge, RedirectUrl, Scope,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::{
+        backend::MICROSOFT,
+        id_token::{IdTokenVerifier, IssuerCheck},
+        jwks::{JwksCache, JwksSource},
+        provider::{AuthorizeRequest, Identity, IdentityProvider, ProviderTokens, Refreshed},
+    },
+    error::AppError,
+    models::IdTokenClaims,
+};
+
+/// Scopes requested when none are configured.
+const DEFAULT_SCOPES: [&str; 4] = ["openid", "email", "profile", "offline_access"];
+
+/// oauth2 client with only the auth endpoint configured (we do token exchange via reqwest).
+type AuthOnlyClient = BasicClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+>;
+
+/// Public Microsoft identity platform.
+pub const MICROSOFT_AUTHORITY: &str = "https://login.microsoftonline.com";
+
+/// Where the Microsoft provider sends users and requests. Every URL is
+/// derived from `authority` the way Entra lays them out
+/// (`{authority}/{tenant}/oauth2/v2.0/authorize`, ...) unless overridden —
+/// for national clouds, or a local fake identity provider in tests.
+#[derive(Clone, Debug)]
+pub struct MicrosoftEndpoints {
+    pub authority: String,
+    pub authorize_url: Option<String>,
+    pub token_url: Option<String>,
+    pub jwks: Option<JwksSource>,
+}
+
+impl Default for MicrosoftEndpoints {
+    fn default() -> Self {
+        Self {
+            authority: MICROSOFT_AUTHORITY.into(),
+            authorize_url: None,
+            token_url: None,
+            jwks: None,
+        }
+    }
+}
+
+/// Which claim identifies the user.
+#[derive(Clone, Copy, Debug)]
+pub enum SubjectClaim {
+    Sub,
+    /// Microsoft's `oid`, which unlike `sub` is the same across apps.
+    Oid,
+}
+
+/// How the provider vouches for the `email` claim.
+#[derive(Clone, Copy, Debug)]
+pub enum EmailVerification {
+    /// OIDC `email_verified`. An address the provider says it has not
+    /// verified is dropped; one without the claim is kept but not trusted
+    /// for the email-domain policy.
+    EmailVerified,
+    /// Microsoft's optional `xms_edov` claim. Entra never sends
+    /// `email_verified`, and a tenant admin can set `email` to anything, so
+    /// without `xms_edov` the address is display-only.
+    Edov,
+}
+
+/// Endpoints and credentials for one OIDC provider.
+#[derive(Clone, Debug)]
+pub struct OidcSettings {
+    pub name: String,
+    pub label: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_url: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    pub jwks: JwksSource,
+    pub issuer: IssuerCheck,
+    pub subject: SubjectClaim,
+    pub email_verification: EmailVerification,
+    /// Empty means [`DEFAULT_SCOPES`].
+    pub scopes: Vec<String>,
+}
+
+/// Subset of `/.well-known/openid-configuration` we need.
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+/// Authorization-code + PKCE sign-in against an OpenID Connect provider.
+#[derive(Clone, Debug)]
+pub struct OidcProvider {
+    name: String,
+    label: String,
+    oauth_client: AuthOnlyClient,
+    http: reqwest::Client,
+    client_id: String,
+    client_secret: String,
+    token_url: String,
+    redirect_url: String,
+    scopes: Vec<String>,
+    subject: SubjectClaim,
+    email_verification: EmailVerification,
+    id_tokens: IdTokenVerifier,
+}
+
+impl OidcProvider {
+    pub fn new(settings: OidcSettings, http: reqwest::Client) -> Result<Self, AppError> {
+        let auth_url =
+            AuthUrl::new(settings.authorize_url).map_err(|e| AppError::OAuth(e.to_string()))?;
+        let redirect = RedirectUrl::new(settings.redirect_url.clone())
+            .map_err(|e| AppError::OAuth(e.to_string()))?;
+
+        let oauth_client = BasicClient::new(ClientId::new(settings.client_id.clone()))
+            .set_client_secret(ClientSecret::new(settings.client_secret.clone()))
+            .set_auth_uri(auth_url)
+            .set_auth_type(AuthType::RequestBody)
+            .set_redirect_uri(redirect);
+
+        let id_tokens = IdTokenVerifier::new(
+            JwksCache::new(settings.jwks, http.clone()),
+            settings.client_id.clone(),
+            settings.issuer,
+        );
+
+        let scopes = if settings.scopes.is_empty() {
+            DEFAULT_SCOPES.map(String::from).to_vec()
+        } else {
+            settings.scopes
+        };
+
+        Ok(Self {
+            name: settings.name,
+            label: settings.label,
+            oauth_client,
+            http,
+            client_id: settings.client_id,
+            client_secret: settings.client_secret,
+            token_url: settings.token_url,
+            redirect_url: settings.redirect_url,
+            scopes,
+            subject: settings.subject,
+            email_verification: settings.email_verification,
+            id_tokens,
+        })
+    }
+
+    /// Microsoft Entra ID for `tenant` (a GUID or `common`).
+    pub fn microsoft(
+        client_id: String,
+        client_secret: String,
+        tenant: &str,
+        redirect_url: String,
+        endpoints: MicrosoftEndpoints,
+        http: reqwest::Client,
+    ) -> Result<Self, AppError> {
+        let authority = endpoints.authority.trim_end_matches('/');
+        let base = format!("{authority}/{tenant}");
+        Self::new(
+            OidcSettings {
+                name: MICROSOFT.into(),
+                label: "Microsoft".into(),
+                client_id,
+                client_secret,
+                redirect_url,
+                authorize_url: endpoints
+                    .authorize_url
+                    .unwrap_or_else(|| format!("{base}/oauth2/v2.0/authorize")),
+                token_url: endpoints
+                    .token_url
+                    .unwrap_or_else(|| format!("{base}/oauth2/v2.0/token")),
+                jwks: endpoints
+                    .jwks
+                    .unwrap_or_else(|| JwksSource::Remote(format!("{base}/discovery/v2.0/keys"))),
+                // Tokens name the user's home tenant, whatever alias was used
+                // to sign in.
+                issuer: IssuerCheck::tenant(format!("{authority}/{{tenantid}}/v2.0"), tenant),
+                subject: SubjectClaim::Oid,
+                email_verification: EmailVerification::Edov,
+                scopes: Vec::new(),
+            },
+            http,
+        )
+    }
+
+    /// Configure a generic provider from its issuer's discovery document.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn discover(
+        name: String,
+        label: String,
+        issuer: &str,
+        client_id: String,
+        client_secret: String,
+        redirect_url: String,
+        scopes: Vec<String>,
+        http: reqwest::Client,
+    ) -> Result<Self, AppError> {
+        let issuer = issuer.trim_end_matches('/');
+        let doc: Discovery = http
+            .get(format!("{issuer}/.well-known/openid-configuration"))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AppError::OAuth(format!("{name}: fetch discovery document: {e}")))?
+            .json()
+            .await
+            .map_err(|e| AppError::OAuth(format!("{name}: parse discovery document: {e}")))?;
+
+        if doc.issuer.trim_end_matches('/') != issuer {
+            return Err(AppError::OAuth(format!(
+                "{name}: discovery document is for issuer {}",
+                doc.issuer
+            )));
+        }
+
+        Self::new(
+            OidcSettings {
+                name,
+                label,
+                client_id,
+                client_secret,
+                redirect_url,
+                authorize_url: doc.authorization_endpoint,
+                token_url: doc.token_endpoint,
+                jwks: JwksSource::Remote(doc.jwks_uri),
+                issuer: IssuerCheck::Exact(doc.issuer),
+                subject: SubjectClaim::Sub,
+                email_verification: EmailVerification::EmailVerified,
+                scopes,
+            },
+            http,
+        )
+    }
+
+    fn subject_of(&self, claims: &IdTokenClaims) -> Result<String, AppError> {
+        match self.subject {
+            SubjectClaim::Sub => Ok(claims.sub.clone()),
+            SubjectClaim::Oid => claims
+                .oid
+                .clone()
+                .ok_or_else(|| AppError::OAuth("id_token has no oid".into())),
+        }
+    }
+
+    async fn token_request(&self, form: &[(&str, &str)]) -> Result<TokenResult, AppError> {
+        let response = self
+            .http
+            .post(&self.token_url)
+            .form(form)
+            .send()
+            .await
+            .map_err(|e| AppError::OAuth(e.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            let token: TokenResponse = response
+                .json()
+                .await
+                .map_err(|e| AppError::OAuth(e.to_string()))?;
+            return Ok(TokenResult::Ok(token));
+        }
+
+        let err: TokenError = response
+            .json()
+            .await
+            .map_err(|e| AppError::OAuth(format!("token endpoint returned {status}: {e}")))?;
+        Ok(TokenResult::Err(status, err))
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for OidcProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    fn authorize_url(&self) -> AuthorizeRequest {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let nonce = CsrfToken::new_random().secret().clone();
+        let (url, csrf) = self
+            .oauth_client
+            .authorize_url(CsrfToken::new_random)
+            .add_scopes(self.scopes.iter().cloned().map(Scope::new))
+            .add_extra_param("nonce", &nonce)
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+        AuthorizeRequest {
+            url,
+            csrf_state: csrf.secret().clone(),
+            pkce_verifier: pkce_verifier.secret().clone(),
+            nonce,
+        }
+    }
+
+    async fn exchange_code(
+        &self,
+        code: &str,
+        pkce_verifier: &str,
+        nonce: &str,
+    ) -> Result<(Identity, ProviderTokens), AppError> {
+        let token = match self
+            .token_request(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("code", code),
+                ("code_verifier", pkce_verifier),
+                ("redirect_uri", self.redirect_url.as_str()),
+                ("grant_type", "authorization_code"),
+            ])
+            .await?
+        {
+            TokenResult::Ok(token) => token,
+            TokenResult::Err(status, err) => {
+                return Err(AppError::OAuth(format!("code exchange failed ({status}): {}", err.error)));
+            }
+        };
+
+        let id_token = token
+            .id_token
+            .as_deref()
+            .ok_or_else(|| AppError::OAuth("missing id_token".into()))?;
+        let claims = self.id_tokens.verify(id_token, Some(nonce)).await?;
+
+        let email_verified = match self.email_verification {
+            EmailVerification::EmailVerified => claims.email_verified == Some(true),
+            EmailVerification::Edov => claims.xms_edov == Some(true),
+        };
+        // An address the provider says it has not verified proves nothing.
+        let email = claims.email.clone().filter(|_| claims.email_verified != Some(false));
+        let identity = Identity {
+            subject: self.subject_of(&claims)?,
+            email_verified: email_verified && email.is_some(),
+            email,
+            preferred_username: claims.preferred_username,
+            name: claims.name,
+            tenant_id: claims.tid,
+        };
+        Ok((identity, token.into()))
+    }
+
+    async fn refresh(&self, refresh_token: &str, subject: &str) -> Result<Refreshed, AppError> {
+        let token = match self
+            .token_request(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("refresh_token", refresh_token),
+                ("grant_type", "refresh_token"),
+                ("scope", &self.scopes.join(" ")),
+            ])
+            .await?
+        {
+            TokenResult::Ok(token) => token,
+            TokenResult::Err(_, err) if err.error == "invalid_grant" => {
+                return Ok(Refreshed::Revoked(err.error_description));
+            }
+            TokenResult::Err(status, err) => {
+                return Err(AppError::OAuth(format!("token refresh failed ({status}): {}", err.error)));
+            }
+        };
+
+        // Refreshed id_tokens carry no nonce, but must still be for this user.
+        if let Some(id_token) = token.id_token.as_deref() {
+            let claims = self.id_tokens.verify(id_token, None).await?;
+            if self.subject_of(&claims)? != subject {
+                return Err(AppError::OAuth("refreshed id_token is for a different user".into()));
+            }
+        }
+
+        Ok(Refreshed::Tokens(token.into()))
+    }
+}
+
+enum TokenResult {
+    Ok(TokenResponse),
+    Err(reqwest::StatusCode, TokenError),
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    id_token: Option<String>,
+    /// Access token lifetime in seconds.
+    expires_in: Option<u64>,
+}
+
+impl From<TokenResponse> for ProviderTokens {
+    fn from(t: TokenResponse) -> Self {
+        Self {
+            access_token: t.access_token,
+            refresh_token: t.refresh_token,
+            id_token: t.id_token,
+            expires_in: t.expires_in,
+        }
+    }
+}
+
+/// OAuth error body from the token endpoint.
+#[derive(Debug, Deserialize)]
+struct TokenError {
+    error: String,
+    error_description: Option<String>,
+}
@@ -0,0 +1,91 @@
+use crate::{auth::provider::Identity, error::AppError};
+
+/// Who may sign in. Empty lists allow everything, so a bare `common` tenant
+/// setup keeps working; configure at least one to restrict sign-in to
+/// students.
+#[derive(Clone, Debug, Default)]
+pub struct SignInPolicy {
+    /// Allowed tenant ids (`tid`), lower-cased.
+    pub tenant_ids: Vec<String>,
+    /// Allowed email domains, lower-cased, without a leading `@`. Subdomains
+    /// of a listed domain are allowed too.
+    pub email_domains: Vec<String>,
+}
+
+impl SignInPolicy {
+    /// Build from allow-lists, ignoring blanks, case and leading `@`s.
+    pub fn new(tenant_ids: Vec<String>, email_domains: Vec<String>) -> Self {
+        fn normalize(list: Vec<String>) -> Vec<String> {
+            list.iter()
+                .map(|s| s.trim().trim_start_matches('@').to_ascii_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect()
+        }
+        Self {
+            tenant_ids: normalize(tenant_ids),
+            email_domains: normalize(email_domains),
+        }
+    }
+
+    /// Build from comma-separated lists.
+    pub fn from_lists(tenant_ids: &str, email_domains: &str) -> Self {
+        let split = |list: &str| list.split(',').map(String::from).collect();
+        Self::new(split(tenant_ids), split(email_domains))
+    }
+
+    /// Fails with [`AppError::LoginDenied`] unless the verified identity
+    /// passes every configured allow-list. The error carries the reason for
+    /// logging.
+    ///
+    /// A tenant list admits only identities from a listed tenant, so it
+    /// denies every account at providers without tenants (generic OIDC).
+    ///
+    /// The email domain is taken from an address the provider verified
+    /// ([`Identity::email_verified`]). Only an identity from an allow-listed
+    /// tenant may fall back to an unverified `email` or `preferred_username`,
+    /// since those are whatever its tenant admin set: without a tenant list,
+    /// anyone could create a tenant and claim an allowed domain.
+    pub fn check(&self, identity: &Identity) -> Result<(), AppError> {
+        let tenant_allowed = if self.tenant_ids.is_empty() {
+            false
+        } else {
+            let tid = identity.tenant_id.as_deref().map(str::to_ascii_lowercase);
+            match tid {
+                Some(tid) if self.tenant_ids.contains(&tid) => true,
+                Some(tid) => {
+                    return Err(AppError::LoginDenied(format!("tenant {tid:?} not allowed")));
+                }
+                // Providers without tenants can't satisfy a tenant list.
+                None => {
+                    return Err(AppError::LoginDenied(
+                        "identity has no tenant but tenants are restricted".into(),
+                    ));
+                }
+            }
+        };
+
+        if !self.email_domains.is_empty() {
+            let email = if identity.email_verified {
+                identity.email.as_deref()
+            } else if tenant_allowed {
+                identity.email.as_deref().or(identity.preferred_username.as_deref())
+            } else {
+                return Err(AppError::LoginDenied("email address not verified".into()));
+            }
+            .unwrap_or_default();
+            let domain = email
+                .rsplit_once('@')
+                .map(|(_, d)| d.to_ascii_lowercase())
+                .unwrap_or_default();
+            let allowed = self
+                .email_domains
+                .iter()
+                .any(|d| domain == *d || domain.ends_with(&format!(".{d}")));
+            if !allowed {
+                return Err(AppError::LoginDenied(format!("email domain {domain:?} not allowed")));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+//! Identity-provider abstraction.
+//!
+//! [`AuthBackend`](super::AuthBackend) drives sign-in and token renewal
+//! through any number of configured [`IdentityProvider`]s and links users to
+//! them by `(provider, subject)` in the `identities` table.
+
+use async_trait::async_trait;
+
+use crate::error::AppError;
+
+/// Everything the login route has to remember until the callback.
+#[derive(Debug)]
+pub struct AuthorizeRequest {
+    pub url: oauth2::url::Url,
+    pub csrf_state: String,
+    pub pkce_verifier: String,
+    pub nonce: String,
+}
+
+/// Verified identity of the person signing in.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    /// Stable per-user id at the provider (`oid` for Microsoft, `sub` for
+    /// generic OIDC).
+    pub subject: String,
+    /// Dropped when the provider says it is unverified.
+    pub email: Option<String>,
+    /// Whether the provider vouches that `email` belongs to the user; only
+    /// then does it count for the email-domain policy.
+    pub email_verified: bool,
+    /// Sign-in name; used by the email-domain policy when `email` is absent.
+    pub preferred_username: Option<String>,
+    pub name: Option<String>,
+    /// Directory tenant, for providers that have one.
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProviderTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub id_token: Option<String>,
+    /// Access token lifetime in seconds.
+    pub expires_in: Option<u64>,
+}
+
+/// Outcome of a refresh-token exchange at the provider.
+#[derive(Debug)]
+pub enum Refreshed {
+    Tokens(ProviderTokens),
+    /// The provider answered `invalid_grant`: the grant is gone for good,
+    /// typically because the account was removed or disabled.
+    Revoked(Option<String>),
+}
+
+#[async_trait]
+pub trait IdentityProvider: std::fmt::Debug + Send + Sync {
+    /// Short name used in `/auth/login/{provider}` and stored in
+    /// `identities.provider`, e.g. `microsoft`.
+    fn name(&self) -> &str;
+
+    /// Human-readable name for the login page.
+    fn label(&self) -> &str;
+
+    fn authorize_url(&self) -> AuthorizeRequest;
+
+    /// Exchange an authorization code and verify the resulting identity.
+    async fn exchange_code(
+        &self,
+        code: &str,
+        pkce_verifier: &str,
+        nonce: &str,
+    ) -> Result<(Identity, ProviderTokens), AppError>;
+
+    /// Exchange a refresh token. `subject` is the identity the token was
+    /// issued to; a returned id_token must match it.
+    async fn refresh(&self, refresh_token: &str, subject: &str) -> Result<Refreshed, AppError>;
+}
@@ -1,16 +1,18 @@
 use axum::{
-    extract::{Query, State},
-    response::Redirect,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    Json,
 };
 use axum_login::AuthSession;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    auth::{backend::MicrosoftBackend, Credentials},
+    auth::{backend::AuthBackend, rotate_session_secret, Credentials},
     AppState,
 };
 
-pub type AuthSessionType = AuthSession<MicrosoftBackend>;
+pub type AuthSessionType = AuthSession<AuthBackend>;
 
 /// Optional query param on `/auth/login` — frontend passes `?next=/some/path`
 /// so after OAuth completes the backend redirects back to that path.
@@ -25,25 +27,89 @@ pub struct CallbackParams {
     pub state: String,
 }
 
-/// Redirect the browser to the Microsoft authorization URL.
-/// Stores CSRF state, PKCE verifier, and optional `next` path in the session.
+#[derive(Debug, Serialize)]
+pub struct ProviderInfo {
+    pub name: String,
+    pub label: String,
+}
+
+/// `GET /auth/providers`
+///
+/// Configured identity providers in login-page order, for rendering one
+/// sign-in button each.
+pub async fn providers(State(state): State<AppState>) -> Json<Vec<ProviderInfo>> {
+    Json(
+        state
+            .backend
+            .providers()
+            .iter()
+            .map(|p| ProviderInfo {
+                name: p.name().to_string(),
+                label: p.label().to_string(),
+            })
+            .collect(),
+    )
+}
+
+/// `GET /auth/login` — sign in with the default (first configured) provider.
 pub async fn login(
     State(state): State<AppState>,
     auth_session: AuthSessionType,
     Query(params): Query<LoginParams>,
-) -> Result<Redirect, crate::error::AppError> {
-    let (url, csrf, pkce_verifier) = state.backend.authorize_url();
+) -> Result<Response, crate::error::AppError> {
+    let provider = state
+        .backend
+        .providers()
+        .first()
+        .map(|p| p.name().to_string())
+        .ok_or_else(|| crate::error::AppError::Internal("no identity providers configured".into()))?;
+    start_login(&state, &auth_session, &provider, params).await
+}
+
+/// `GET /auth/login/{provider}`
+pub async fn login_with(
+    State(state): State<AppState>,
+    auth_session: AuthSessionType,
+    Path(provider): Path<String>,
+    Query(params): Query<LoginParams>,
+) -> Result<Response, crate::error::AppError> {
+    start_login(&state, &auth_session, &provider, params).await
+}
 
+/// Redirect the browser to the provider's authorization URL.
+/// Stores the provider name, CSRF state, PKCE verifier, OIDC nonce and
+/// optional `next` path in the session.
+async fn start_login(
+    state: &AppState,
+    auth_session: &AuthSessionType,
+    provider: &str,
+    params: LoginParams,
+) -> Result<Response, crate::error::AppError> {
+    let Some(idp) = state.backend.provider(provider) else {
+        return Err(crate::error::AppError::NotFound("identity provider"));
+    };
+    let request = idp.authorize_url();
+
+    auth_session
+        .session
+        .insert("oidc_provider", provider)
+        .await
+        .map_err(|e| crate::error::AppError::OAuth(format!("session insert oidc_provider: {e}")))?;
     auth_session
         .session
-        .insert("csrf_state", csrf.secret())
+        .insert("csrf_state", &request.csrf_state)
         .await
         .map_err(|e| crate::error::AppError::OAuth(format!("session insert csrf_state: {e}")))?;
     auth_session
         .session
-        .insert("pkce_verifier", pkce_verifier.secret())
+        .insert("pkce_verifier", &request.pkce_verifier)
         .await
         .map_err(|e| crate::error::AppError::OAuth(format!("session insert pkce_verifier: {e}")))?;
+    auth_session
+        .session
+        .insert("oidc_nonce", &request.nonce)
+        .await
+        .map_err(|e| crate::error::AppError::OAuth(format!("session insert oidc_nonce: {e}")))?;
 
     // Persist the post-auth destination so callback can redirect back to it.
     if let Some(next) = &params.next {
@@ -54,11 +120,11 @@ pub async fn login(
             .map_err(|e| crate::error::AppError::OAuth(format!("session insert next: {e}")))?;
     }
 
-    tracing::debug!(csrf = %csrf.secret(), next = ?params.next, "initiating Microsoft SSO");
-    Ok(Redirect::to(url.as_str()))
+    tracing::debug!(provider, csrf = %request.csrf_state, next = ?params.next, "initiating SSO");
+    Ok(Redirect::to(request.url.as_str()).into_response())
 }
 
-/// Microsoft redirects here after the user authenticates.
+/// The identity provider redirects here after the user authenticates.
 /// Validates CSRF, exchanges the authorization code for tokens,
 /// upserts the user in the database, and establishes an axum-login session.
 /// Finally redirects the browser back to the frontend SPA.
@@ -77,6 +143,14 @@ pub async fn callback(
         )));
     }
 
+    let provider: String = auth_session
+        .session
+        .get("oidc_provider")
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| crate::error::AppError::OAuth("missing oidc_provider".into()))?;
+
     let pkce_verifier: String = auth_session
         .session
         .get("pkce_verifier")
@@ -85,27 +159,52 @@ pub async fn callback(
         .flatten()
         .ok_or_else(|| crate::error::AppError::OAuth("missing pkce_verifier".into()))?;
 
+    let nonce: String = auth_session
+        .session
+        .get("oidc_nonce")
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| crate::error::AppError::OAuth("missing oidc_nonce".into()))?;
+
     // Retrieve optional next-path before consuming the auth exchange.
     let next: Option<String> = auth_session.session.get("next").await.ok().flatten();
 
+    // The provider, state, verifier and nonce are single-use.
+    for key in ["oidc_provider", "csrf_state", "pkce_verifier", "oidc_nonce"] {
+        auth_session.session.remove::<String>(key).await.ok();
+    }
+
     let creds = Credentials {
+        provider: provider.clone(),
         code: params.code,
         pkce_verifier,
+        nonce,
     };
 
-    let user = auth_session
-        .authenticate(creds)
-        .await
-        .map_err(|e| crate::error::AppError::OAuth(e.to_string()))?
-        .ok_or_else(|| crate::error::AppError::OAuth("authentication returned no user".into()))?;
+    // Policy rejections go back to the login page with a reason the SPA can
+    // show; anything else is a genuine failure.
+    let user = match auth_session.authenticate(creds).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(crate::error::AppError::OAuth("authentication returned no user".into()));
+        }
+        Err(axum_login::Error::Backend(crate::error::AppError::LoginDenied(_))) => {
+            return Ok(Redirect::to(&format!("{}/#/login/not_allowed", state.config.frontend_url)));
+        }
+        Err(axum_login::Error::Backend(crate::error::AppError::Suspended)) => {
+            return Ok(Redirect::to(&format!("{}/#/login/suspended", state.config.frontend_url)));
+        }
+        Err(e) => return Err(crate::error::AppError::OAuth(e.to_string())),
+    };
 
     auth_session.login(&user).await.ok();
-    tracing::info!(user_id = user.id, email = ?user.email, "user logged in via Microsoft SSO");
+    tracing::info!(user_id = user.id, email = ?user.email, provider, "user logged in via SSO");
 
     // Redirect browser back to the frontend, honouring ?next= if present.
     let destination = match next {
-        Some(path) if path.starts_with('/') => format!("{}{}", state.frontend_url, path),
-        _ => state.frontend_url.clone(),
+        Some(path) if path.starts_with('/') => format!("{}{}", state.config.frontend_url, path),
+        _ => state.config.frontend_url.clone(),
     };
     Ok(Redirect::to(&destination))
 }
@@ -117,5 +216,24 @@ pub async fn logout(
 ) -> Redirect {
     auth_session.logout().await.ok();
     tracing::info!("user logged out");
-    Redirect::to(&state.frontend_url)
+    Redirect::to(&state.config.frontend_url)
+}
+
+/// `POST /auth/logout-all`
+///
+/// Rotates the user's session secret, signing them out on every device, and
+/// ends the current session. Returns `204 No Content`.
+pub async fn logout_all(
+    State(state): State<AppState>,
+    mut auth_session: AuthSessionType,
+) -> Result<StatusCode, crate::error::AppError> {
+    let user = auth_session
+        .user
+        .clone()
+        .ok_or(crate::error::AppError::Unauthorized)?;
+
+    rotate_session_secret(&state.pool, user.id).await?;
+    auth_session.logout().await.ok();
+    tracing::info!(user_id = user.id, "user logged out of all sessions");
+    Ok(StatusCode::NO_CONTENT)
 }
@@ -0,0 +1,52 @@
+//! Authorization checks shared by handlers that act on a pair of users.
+
+use sqlx::SqlitePool;
+
+use crate::error::AppError;
+
+/// Order a pair of user ids the way the `matches` table stores them
+/// (`user1_id < user2_id`).
+pub fn canonical_pair(a: i64, b: i64) -> (i64, i64) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+/// Whether either of `a` and `b` has blocked the other.
+pub async fn is_blocked(pool: &SqlitePool, a: i64, b: i64) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM blocks
+            WHERE (blocker_id = ?1 AND blocked_id = ?2)
+               OR (blocker_id = ?2 AND blocked_id = ?1)
+        )
+        "#,
+    )
+    .bind(a)
+    .bind(b)
+    .fetch_one(pool)
+    .await
+}
+
+/// Whether `a` and `b` currently have a match and neither has blocked the other.
+pub async fn are_matched(pool: &SqlitePool, a: i64, b: i64) -> Result<bool, sqlx::Error> {
+    let (user1_id, user2_id) = canonical_pair(a, b);
+    let matched: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM matches WHERE user1_id = ?1 AND user2_id = ?2)",
+    )
+    .bind(user1_id)
+    .bind(user2_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(matched && !is_blocked(pool, a, b).await?)
+}
+
+/// Fails with [`AppError::NotMatched`] unless `a` and `b` are matched.
+/// Blocks surface as the same error so a blocked user can't tell they were
+/// blocked rather than unmatched.
+pub async fn ensure_matched(pool: &SqlitePool, a: i64, b: i64) -> Result<(), AppError> {
+    if are_matched(pool, a, b).await? {
+        Ok(())
+    } else {
+        Err(AppError::NotMatched)
+    }
+}
@@ -6,6 +6,7 @@
 //! The DATABASE_URL env var (or .env file) is used to locate the database.
 //! The script is idempotent: re-running it will not insert duplicate rows.
 
+use backend::validation::normalize_interest;
 use sqlx::SqlitePool;
 
 // ---------------------------------------------------------------------------
@@ -167,6 +168,8 @@ const MOCK_MESSAGES: &[MockMessage] = &[
 // ---------------------------------------------------------------------------
 
 async fn ensure_interest(pool: &SqlitePool, name: &str) -> anyhow::Result<i64> {
+    // Stored normalised, as profile updates do.
+    let name = normalize_interest(name);
     // Insert if it doesn't exist yet, then return the id.
     sqlx::query!(
         "INSERT OR IGNORE INTO interests (name) VALUES (?)",
@@ -216,8 +219,21 @@ async fn upsert_user(pool: &SqlitePool, u: &MockUser) -> anyhow::Result<i64> {
     let row = sqlx::query!("SELECT id FROM users WHERE oid = ?", u.oid)
         .fetch_one(pool)
         .await?;
+    let id = row.id.expect("user id should not be null");
 
-    Ok(row.id.expect("user id should not be null"))
+    // The mock photo is the whole gallery.
+    sqlx::query!("DELETE FROM profile_photos WHERE user_id = ?", id)
+        .execute(pool)
+        .await?;
+    sqlx::query!(
+        "INSERT INTO profile_photos (user_id, image_key, position, is_primary) VALUES (?, ?, 0, 1)",
+        id,
+        u.image_key
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(id)
 }
 
 // ---------------------------------------------------------------------------
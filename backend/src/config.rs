@@ -0,0 +1,744 @@
+//! Server configuration.
+//!
+//! Values come from an optional TOML file (path in `CONFIG_FILE`) and the
+//! environment, with environment variables winning. Everything is validated
+//! up front and every problem is reported at once, so a misconfigured
+//! deployment fails on start instead of on the first request that needs the
+//! broken value.
+//!
+//! ```toml
+//! bind_addr = "0.0.0.0:48757"
+//! frontend_url = "https://huskr.example.edu"
+//! cors_origins = ["http://localhost:8080"]
+//! token_encryption_keys = "k1:..."
+//!
+//! [azure]
+//! client_id = "..."
+//! client_secret = "..."
+//! tenant_id = "..."
+//!
+//! [[oidc]]
+//! name = "google"
+//! issuer = "https://accounts.google.com"
+//! client_id = "..."
+//! client_secret = "..."
+//! ```
+
+use std::{fmt, net::SocketAddr, path::PathBuf, str::FromStr};
+
+use axum::http::HeaderValue;
+use serde::Deserialize;
+
+use crate::{
+    auth::{
+        jwks::JwksSource,
+        oidc::{MICROSOFT_AUTHORITY, MicrosoftEndpoints},
+        policy::SignInPolicy,
+    },
+    crypto::TokenCipher,
+    images::ImageRules,
+    validation::{ADULT_AGE, ProfileRules},
+};
+
+const DEFAULT_BIND_ADDR: &str = "0.0.0.0:48757";
+const DEFAULT_FRONTEND_URL: &str = "http://localhost:48757";
+/// Origins of the standalone frontend dev server.
+const DEFAULT_CORS_ORIGINS: [&str; 3] = [
+    "http://localhost:8080",
+    "http://0.0.0.0:8080",
+    "http://127.0.0.1:8080",
+];
+
+/// A credential that must not end up in logs.
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+/// Validated configuration.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub bind_addr: SocketAddr,
+    pub database_url: String,
+    pub db_pool_size: u32,
+    pub object_store_path: PathBuf,
+    /// Base URL of the SPA, used for post-auth redirects. Always an allowed
+    /// CORS origin.
+    pub frontend_url: String,
+    /// Directory the SPA is served from.
+    pub frontend_dir: PathBuf,
+    /// Allowed CORS origins, including `frontend_url`.
+    pub cors_origins: Vec<HeaderValue>,
+    /// Body limit for uploads (profile photos, message images).
+    pub max_upload_bytes: usize,
+    /// OAuth callback URL shared by all identity providers.
+    pub redirect_url: Option<String>,
+    /// Enables `/auth/dev-login`; only allowed with a loopback `bind_addr`.
+    pub dev_login: bool,
+    pub token_cipher: TokenCipher,
+    pub token_refresh_interval_hours: i64,
+    pub session_idle_timeout_hours: i64,
+    pub session_absolute_timeout_hours: i64,
+    /// How long the SSE replay log keeps events, i.e. the longest gap a
+    /// reconnecting client can resume across.
+    pub event_retention_days: i64,
+    /// How often old events are pruned from the replay log.
+    pub event_prune_interval_hours: i64,
+    pub sign_in_policy: SignInPolicy,
+    /// Limits for user-editable profile fields.
+    pub profile: ProfileRules,
+    /// Size limits for uploaded images.
+    pub images: ImageRules,
+    /// How long replaced or removed photos stay in the object store before
+    /// garbage collection may delete them.
+    pub image_retention_days: i64,
+    pub azure: Option<AzureConfig>,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AzureConfig {
+    pub client_id: String,
+    pub client_secret: Secret,
+    /// Tenant GUID, or `common` to accept any tenant.
+    pub tenant_id: String,
+    pub endpoints: MicrosoftEndpoints,
+}
+
+/// A generic OpenID Connect provider, configured through discovery.
+#[derive(Clone, Debug)]
+pub struct OidcProviderConfig {
+    pub name: String,
+    pub label: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Secret,
+    /// Empty means the provider default scopes.
+    pub scopes: Vec<String>,
+}
+
+/// Every problem found while loading the configuration.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for e in &self.0 {
+            write!(f, "\n  - {e}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// ---------------------------------------------------------------------------
+// Raw (unvalidated) layers
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    bind_addr: Option<SocketAddr>,
+    database_url: Option<String>,
+    db_pool_size: Option<u32>,
+    object_store_path: Option<PathBuf>,
+    frontend_url: Option<String>,
+    frontend_dir: Option<PathBuf>,
+    cors_origins: Option<Vec<String>>,
+    max_upload_bytes: Option<usize>,
+    redirect_url: Option<String>,
+    dev_login: Option<bool>,
+    token_encryption_keys: Option<Secret>,
+    token_encryption_active_key: Option<String>,
+    token_refresh_interval_hours: Option<i64>,
+    session_idle_timeout_hours: Option<i64>,
+    session_absolute_timeout_hours: Option<i64>,
+    event_retention_days: Option<i64>,
+    event_prune_interval_hours: Option<i64>,
+    allowed_tenant_ids: Option<Vec<String>>,
+    allowed_email_domains: Option<Vec<String>>,
+    profile: RawProfile,
+    images: RawImages,
+    azure: RawAzure,
+    oidc: Vec<RawOidc>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawProfile {
+    min_age: Option<i64>,
+    max_age: Option<i64>,
+    max_full_name_chars: Option<usize>,
+    max_bio_chars: Option<usize>,
+    majors: Option<Vec<String>>,
+    max_major_chars: Option<usize>,
+    max_interests: Option<usize>,
+    max_interest_chars: Option<usize>,
+    max_photos: Option<usize>,
+    max_caption_chars: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawImages {
+    max_dimension: Option<u32>,
+    max_pixels: Option<u64>,
+    retention_days: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawAzure {
+    client_id: Option<String>,
+    client_secret: Option<Secret>,
+    tenant_id: Option<String>,
+    authority: Option<String>,
+    authorize_url: Option<String>,
+    token_url: Option<String>,
+    jwks_url: Option<String>,
+    jwks_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawOidc {
+    name: String,
+    label: Option<String>,
+    issuer: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<Secret>,
+    scopes: Option<Vec<String>>,
+}
+
+/// Reads environment variables, recording unparsable ones.
+struct Env<'a> {
+    var: &'a dyn Fn(&str) -> Option<String>,
+    errors: &'a mut Vec<String>,
+}
+
+impl Env<'_> {
+    fn string(&self, name: &str) -> Option<String> {
+        (self.var)(name)
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str) -> Option<T>
+    where
+        T::Err: fmt::Display,
+    {
+        let value = (self.var)(name)?;
+        match value.trim().parse() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.errors.push(format!("{name}: {e} (got {value:?})"));
+                None
+            }
+        }
+    }
+
+    fn flag(&mut self, name: &str) -> Option<bool> {
+        let value = (self.var)(name)?;
+        match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" => Some(true),
+            "" | "0" | "false" | "no" => Some(false),
+            _ => {
+                self.errors
+                    .push(format!("{name} must be true or false, got {value:?}"));
+                None
+            }
+        }
+    }
+
+    /// Comma-separated list.
+    fn list(&self, name: &str) -> Option<Vec<String>> {
+        (self.var)(name).map(|v| {
+            v.split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+    }
+}
+
+/// `slot = value` when the environment provided one.
+fn set<T>(slot: &mut Option<T>, value: Option<T>) {
+    if value.is_some() {
+        *slot = value;
+    }
+}
+
+impl RawConfig {
+    fn apply_env(&mut self, env: &mut Env<'_>) {
+        set(&mut self.bind_addr, env.parse("BIND_ADDR"));
+        set(&mut self.database_url, env.string("DATABASE_URL"));
+        set(&mut self.db_pool_size, env.parse("DB_POOL_SIZE"));
+        set(
+            &mut self.object_store_path,
+            env.string("OBJECT_STORE_PATH").map(Into::into),
+        );
+        set(&mut self.frontend_url, env.string("FRONTEND_URL"));
+        set(
+            &mut self.frontend_dir,
+            env.string("FRONTEND_DIR").map(Into::into),
+        );
+        set(&mut self.cors_origins, env.list("CORS_ORIGINS"));
+        set(&mut self.max_upload_bytes, env.parse("MAX_UPLOAD_BYTES"));
+        set(&mut self.redirect_url, env.string("REDIRECT_URL"));
+        set(&mut self.dev_login, env.flag("DEV_LOGIN"));
+        set(
+            &mut self.token_encryption_keys,
+            env.string("TOKEN_ENCRYPTION_KEYS").map(Secret),
+        );
+        set(
+            &mut self.token_encryption_active_key,
+            env.string("TOKEN_ENCRYPTION_ACTIVE_KEY"),
+        );
+        set(
+            &mut self.token_refresh_interval_hours,
+            env.parse("TOKEN_REFRESH_INTERVAL_HOURS"),
+        );
+        set(
+            &mut self.session_idle_timeout_hours,
+            env.parse("SESSION_IDLE_TIMEOUT_HOURS"),
+        );
+        set(
+            &mut self.session_absolute_timeout_hours,
+            env.parse("SESSION_ABSOLUTE_TIMEOUT_HOURS"),
+        );
+        set(&mut self.event_retention_days, env.parse("EVENT_RETENTION_DAYS"));
+        set(
+            &mut self.event_prune_interval_hours,
+            env.parse("EVENT_PRUNE_INTERVAL_HOURS"),
+        );
+        set(&mut self.allowed_tenant_ids, env.list("ALLOWED_TENANT_IDS"));
+        set(
+            &mut self.allowed_email_domains,
+            env.list("ALLOWED_EMAIL_DOMAINS"),
+        );
+
+        let profile = &mut self.profile;
+        set(&mut profile.min_age, env.parse("PROFILE_MIN_AGE"));
+        set(&mut profile.max_age, env.parse("PROFILE_MAX_AGE"));
+        set(
+            &mut profile.max_full_name_chars,
+            env.parse("PROFILE_MAX_FULL_NAME_CHARS"),
+        );
+        set(&mut profile.max_bio_chars, env.parse("PROFILE_MAX_BIO_CHARS"));
+        set(&mut profile.majors, env.list("PROFILE_MAJORS"));
+        set(&mut profile.max_major_chars, env.parse("PROFILE_MAX_MAJOR_CHARS"));
+        set(&mut profile.max_interests, env.parse("PROFILE_MAX_INTERESTS"));
+        set(
+            &mut profile.max_interest_chars,
+            env.parse("PROFILE_MAX_INTEREST_CHARS"),
+        );
+        set(&mut profile.max_photos, env.parse("PROFILE_MAX_PHOTOS"));
+        set(
+            &mut profile.max_caption_chars,
+            env.parse("PROFILE_MAX_CAPTION_CHARS"),
+        );
+
+        let images = &mut self.images;
+        set(&mut images.max_dimension, env.parse("IMAGE_MAX_DIMENSION"));
+        set(&mut images.max_pixels, env.parse("IMAGE_MAX_PIXELS"));
+        set(&mut images.retention_days, env.parse("IMAGE_RETENTION_DAYS"));
+
+        let azure = &mut self.azure;
+        set(&mut azure.client_id, env.string("AZURE_CLIENT_ID"));
+        set(
+            &mut azure.client_secret,
+            env.string("AZURE_CLIENT_SECRET").map(Secret),
+        );
+        set(&mut azure.tenant_id, env.string("AZURE_TENANT_ID"));
+        set(&mut azure.authority, env.string("AZURE_AUTHORITY"));
+        set(&mut azure.authorize_url, env.string("AZURE_AUTHORIZE_URL"));
+        set(&mut azure.token_url, env.string("AZURE_TOKEN_URL"));
+        set(&mut azure.jwks_url, env.string("AZURE_JWKS_URL"));
+        set(
+            &mut azure.jwks_file,
+            env.string("AZURE_JWKS_FILE").map(Into::into),
+        );
+
+        // OIDC_PROVIDERS=google,unl adds providers on top of the file's;
+        // OIDC_<NAME>_* fills in or overrides settings for any of them.
+        for name in env.list("OIDC_PROVIDERS").unwrap_or_default() {
+            if !self.oidc.iter().any(|p| p.name == name) {
+                self.oidc.push(RawOidc {
+                    name,
+                    ..Default::default()
+                });
+            }
+        }
+        for provider in &mut self.oidc {
+            let prefix = format!(
+                "OIDC_{}_",
+                provider.name.to_ascii_uppercase().replace('-', "_")
+            );
+            let var = |key: &str| env.string(&format!("{prefix}{key}"));
+            set(&mut provider.label, var("LABEL"));
+            set(&mut provider.issuer, var("ISSUER"));
+            set(&mut provider.client_id, var("CLIENT_ID"));
+            set(
+                &mut provider.client_secret,
+                var("CLIENT_SECRET").map(Secret),
+            );
+            set(
+                &mut provider.scopes,
+                var("SCOPES").map(|s| s.split_whitespace().map(String::from).collect()),
+            );
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Loading & validation
+// ---------------------------------------------------------------------------
+
+impl Config {
+    /// Load from the process environment and, if `CONFIG_FILE` is set, that
+    /// TOML file.
+    pub fn load() -> Result<Self, ConfigError> {
+        let file = match std::env::var("CONFIG_FILE") {
+            Ok(path) => match std::fs::read_to_string(&path) {
+                Ok(contents) => Some(contents),
+                Err(e) => return Err(ConfigError(vec![format!("CONFIG_FILE {path}: {e}")])),
+            },
+            Err(_) => None,
+        };
+        Self::from_sources(file.as_deref(), &|name| std::env::var(name).ok())
+    }
+
+    /// Build from TOML text (if any) overlaid with variables from `env`.
+    pub fn from_sources(
+        toml: Option<&str>,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut errors = Vec::new();
+
+        let mut raw = match toml.map(toml::from_str::<RawConfig>) {
+            None => RawConfig::default(),
+            Some(Ok(raw)) => raw,
+            Some(Err(e)) => {
+                errors.push(format!("config file: {}", e.message()));
+                RawConfig::default()
+            }
+        };
+        raw.apply_env(&mut Env {
+            var: env,
+            errors: &mut errors,
+        });
+
+        let config = validate(raw, &mut errors);
+        match config {
+            Some(config) if errors.is_empty() => Ok(config),
+            _ => Err(ConfigError(errors)),
+        }
+    }
+}
+
+fn positive_hours(name: &str, value: Option<i64>, default: i64, errors: &mut Vec<String>) -> i64 {
+    match value {
+        None => default,
+        Some(h) if h > 0 => h,
+        Some(h) => {
+            errors.push(format!(
+                "{name} must be a positive number of hours, got {h}"
+            ));
+            default
+        }
+    }
+}
+
+fn profile_rules(raw: RawProfile, errors: &mut Vec<String>) -> ProfileRules {
+    let defaults = ProfileRules::default();
+    let rules = ProfileRules {
+        min_age: raw.min_age.unwrap_or(defaults.min_age),
+        max_age: raw.max_age.unwrap_or(defaults.max_age),
+        max_full_name_chars: raw
+            .max_full_name_chars
+            .unwrap_or(defaults.max_full_name_chars),
+        max_bio_chars: raw.max_bio_chars.unwrap_or(defaults.max_bio_chars),
+        majors: {
+            let mut majors: Vec<String> = Vec::new();
+            for major in raw.majors.unwrap_or_default() {
+                let major = major.trim().to_string();
+                if !major.is_empty() && !majors.iter().any(|m| m.eq_ignore_ascii_case(&major)) {
+                    majors.push(major);
+                }
+            }
+            majors
+        },
+        max_major_chars: raw.max_major_chars.unwrap_or(defaults.max_major_chars),
+        max_interests: raw.max_interests.unwrap_or(defaults.max_interests),
+        max_interest_chars: raw.max_interest_chars.unwrap_or(defaults.max_interest_chars),
+        max_photos: raw.max_photos.unwrap_or(defaults.max_photos),
+        max_caption_chars: raw.max_caption_chars.unwrap_or(defaults.max_caption_chars),
+    };
+    if rules.min_age < ADULT_AGE || rules.min_age > rules.max_age {
+        errors.push(format!(
+            "profile ages must satisfy {ADULT_AGE} <= min_age <= max_age, got {}..={}",
+            rules.min_age, rules.max_age
+        ));
+    }
+    for (name, value) in [
+        ("profile.max_full_name_chars", rules.max_full_name_chars),
+        ("profile.max_bio_chars", rules.max_bio_chars),
+        ("profile.max_major_chars", rules.max_major_chars),
+        ("profile.max_interests", rules.max_interests),
+        ("profile.max_interest_chars", rules.max_interest_chars),
+        ("profile.max_photos", rules.max_photos),
+        ("profile.max_caption_chars", rules.max_caption_chars),
+    ] {
+        if value == 0 {
+            errors.push(format!("{name} must be positive"));
+        }
+    }
+    rules
+}
+
+fn image_rules(raw: RawImages, errors: &mut Vec<String>) -> ImageRules {
+    let defaults = ImageRules::default();
+    let rules = ImageRules {
+        max_dimension: raw.max_dimension.unwrap_or(defaults.max_dimension),
+        max_pixels: raw.max_pixels.unwrap_or(defaults.max_pixels),
+    };
+    if rules.max_dimension == 0 {
+        errors.push("images.max_dimension must be positive".into());
+    }
+    if rules.max_pixels == 0 {
+        errors.push("images.max_pixels must be positive".into());
+    }
+    rules
+}
+
+/// Check everything, pushing one message per problem. Returns `None` only
+/// when a value every other part depends on is missing.
+fn validate(raw: RawConfig, errors: &mut Vec<String>) -> Option<Config> {
+    let bind_addr = raw
+        .bind_addr
+        .unwrap_or_else(|| DEFAULT_BIND_ADDR.parse().expect("valid default"));
+
+    let db_pool_size = raw.db_pool_size.unwrap_or(5);
+    if db_pool_size == 0 {
+        errors.push("db_pool_size must be at least 1".into());
+    }
+
+    let max_upload_bytes = raw.max_upload_bytes.unwrap_or(20 * 1024 * 1024);
+    if max_upload_bytes == 0 {
+        errors.push("max_upload_bytes must be positive".into());
+    }
+
+    let frontend_url = raw
+        .frontend_url
+        .unwrap_or_else(|| DEFAULT_FRONTEND_URL.into())
+        .trim_end_matches('/')
+        .to_string();
+    let mut cors_origins = Vec::new();
+    let origins = raw
+        .cors_origins
+        .unwrap_or_else(|| DEFAULT_CORS_ORIGINS.map(String::from).to_vec());
+    for origin in origins.iter().chain([&frontend_url]) {
+        if !(origin.starts_with("http://") || origin.starts_with("https://")) {
+            errors.push(format!("CORS origin {origin:?} must be an http(s) origin"));
+            continue;
+        }
+        match HeaderValue::from_str(origin) {
+            Ok(v) if !cors_origins.contains(&v) => cors_origins.push(v),
+            Ok(_) => {}
+            Err(_) => errors.push(format!(
+                "CORS origin {origin:?} is not a valid header value"
+            )),
+        }
+    }
+
+    let dev_login = raw.dev_login.unwrap_or(false);
+    if dev_login && !bind_addr.ip().is_loopback() {
+        errors.push(format!(
+            "dev_login requires a loopback bind_addr, got {bind_addr}"
+        ));
+    }
+
+    // Key-encryption keys for OAuth tokens at rest.
+    let token_cipher = match &raw.token_encryption_keys {
+        None => {
+            errors.push("token_encryption_keys (TOKEN_ENCRYPTION_KEYS) is required".into());
+            None
+        }
+        Some(keys) => {
+            TokenCipher::from_config(keys.expose(), raw.token_encryption_active_key.as_deref())
+                .map_err(|e| errors.push(format!("token_encryption_keys: {e}")))
+                .ok()
+        }
+    };
+
+    let token_refresh_interval_hours = positive_hours(
+        "token_refresh_interval_hours",
+        raw.token_refresh_interval_hours,
+        24,
+        errors,
+    );
+    let session_idle_timeout_hours = positive_hours(
+        "session_idle_timeout_hours",
+        raw.session_idle_timeout_hours,
+        24 * 14,
+        errors,
+    );
+    let session_absolute_timeout_hours = positive_hours(
+        "session_absolute_timeout_hours",
+        raw.session_absolute_timeout_hours,
+        24 * 30,
+        errors,
+    );
+
+    let event_retention_days = raw.event_retention_days.unwrap_or(7);
+    if event_retention_days <= 0 {
+        errors.push(format!(
+            "event_retention_days must be a positive number of days, got {event_retention_days}"
+        ));
+    }
+    let event_prune_interval_hours = positive_hours(
+        "event_prune_interval_hours",
+        raw.event_prune_interval_hours,
+        1,
+        errors,
+    );
+
+    let sign_in_policy = SignInPolicy::new(
+        raw.allowed_tenant_ids.unwrap_or_default(),
+        raw.allowed_email_domains.unwrap_or_default(),
+    );
+
+    let profile = profile_rules(raw.profile, errors);
+    let image_retention_days = raw.images.retention_days.unwrap_or(30);
+    if image_retention_days < 0 {
+        errors.push(format!(
+            "images.retention_days must not be negative, got {image_retention_days}"
+        ));
+    }
+    let images = image_rules(raw.images, errors);
+
+    let has_providers = raw.azure.client_id.is_some() || !raw.oidc.is_empty();
+    if !has_providers && !dev_login {
+        errors.push(
+            "no identity providers configured: set AZURE_CLIENT_ID and/or OIDC_PROVIDERS".into(),
+        );
+    }
+    if has_providers && raw.redirect_url.is_none() {
+        errors.push(
+            "redirect_url (REDIRECT_URL) is required when an identity provider is configured"
+                .into(),
+        );
+    }
+
+    let azure = raw.azure;
+    let azure = match azure.client_id {
+        None => None,
+        Some(client_id) => {
+            let client_secret = azure.client_secret.or_else(|| {
+                errors.push(
+                    "azure.client_secret (AZURE_CLIENT_SECRET) is required with a client id".into(),
+                );
+                None
+            });
+            let jwks = match (azure.jwks_file, azure.jwks_url) {
+                (Some(_), Some(_)) => {
+                    errors.push("set only one of azure.jwks_file and azure.jwks_url".into());
+                    None
+                }
+                (Some(path), None) => Some(JwksSource::File(path)),
+                (None, Some(url)) => Some(JwksSource::Remote(url)),
+                (None, None) => None,
+            };
+            client_secret.map(|client_secret| AzureConfig {
+                client_id,
+                client_secret,
+                tenant_id: azure.tenant_id.unwrap_or_else(|| "common".into()),
+                endpoints: MicrosoftEndpoints {
+                    authority: azure
+                        .authority
+                        .unwrap_or_else(|| MICROSOFT_AUTHORITY.into()),
+                    authorize_url: azure.authorize_url,
+                    token_url: azure.token_url,
+                    jwks,
+                },
+            })
+        }
+    };
+
+    let mut oidc_providers: Vec<OidcProviderConfig> = Vec::new();
+    for p in raw.oidc {
+        let name = p.name;
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            errors.push(format!(
+                "OIDC provider name {name:?} must be lower-case letters, digits or dashes"
+            ));
+            continue;
+        }
+        if name == crate::auth::backend::MICROSOFT || oidc_providers.iter().any(|o| o.name == name)
+        {
+            errors.push(format!("OIDC provider {name:?} is configured twice"));
+            continue;
+        }
+        let mut missing = |field: &str, value: Option<String>| {
+            if value.is_none() {
+                errors.push(format!("OIDC provider {name:?} needs {field}"));
+            }
+            value
+        };
+        let issuer = missing("issuer", p.issuer);
+        let client_id = missing("client_id", p.client_id);
+        let client_secret = missing("client_secret", p.client_secret.map(|s| s.0));
+        if let (Some(issuer), Some(client_id), Some(client_secret)) =
+            (issuer, client_id, client_secret)
+        {
+            oidc_providers.push(OidcProviderConfig {
+                label: p.label.unwrap_or_else(|| name.clone()),
+                name,
+                issuer,
+                client_id,
+                client_secret: Secret(client_secret),
+                scopes: p.scopes.unwrap_or_default(),
+            });
+        }
+    }
+
+    Some(Config {
+        bind_addr,
+        database_url: raw.database_url.unwrap_or_else(|| "sqlite:app.db".into()),
+        db_pool_size,
+        object_store_path: raw.object_store_path.unwrap_or_else(|| "./uploads".into()),
+        frontend_url,
+        frontend_dir: raw.frontend_dir.unwrap_or_else(|| "../frontend".into()),
+        cors_origins,
+        max_upload_bytes,
+        redirect_url: raw.redirect_url,
+        dev_login,
+        token_cipher: token_cipher?,
+        token_refresh_interval_hours,
+        session_idle_timeout_hours,
+        session_absolute_timeout_hours,
+        event_retention_days,
+        event_prune_interval_hours,
+        sign_in_policy,
+        profile,
+        images,
+        image_retention_days,
+        azure,
+        oidc_providers,
+    })
+}
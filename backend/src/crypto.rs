@@ -0,0 +1,298 @@
+//! Envelope encryption for OAuth tokens stored in `identities`.
+//!
+//! Each value gets a fresh random data key (DEK) which encrypts the token with
+//! AES-256-GCM; the DEK is in turn encrypted ("wrapped") with a key-encryption
+//! key (KEK) from configuration. Stored values look like
+//!
+//! ```text
+//! v2:<kid>:<base64(nonce | wrapped DEK)>:<base64(nonce | ciphertext)>
+//! ```
+//!
+//! `kid` names the KEK, so several can be configured at once. Rotating to a
+//! new KEK only re-wraps the DEKs ([`TokenCipher::rewrap`]) — tokens stay
+//! valid and nobody has to sign in again.
+//!
+//! The ciphertext is bound to the row and column it is stored in (see
+//! [`TokenSlot`]), so a value copied to another identity or column does not
+//! decrypt.
+
+use std::collections::HashMap;
+
+use aes_gcm::{
+    Aes256Gcm, Key, Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use sqlx::SqlitePool;
+
+use crate::error::AppError;
+
+const PREFIX: &str = "v2";
+const NONCE_LEN: usize = 12;
+
+/// A token column of `identities`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenColumn {
+    AccessToken,
+    RefreshToken,
+    IdToken,
+}
+
+impl TokenColumn {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::AccessToken => "access_token",
+            Self::RefreshToken => "refresh_token",
+            Self::IdToken => "id_token",
+        }
+    }
+}
+
+/// Where a token is stored: the identity row and the column. Used as
+/// AES-GCM associated data.
+#[derive(Clone, Copy, Debug)]
+pub struct TokenSlot<'a> {
+    pub user_id: i64,
+    pub provider: &'a str,
+    pub subject: &'a str,
+    pub column: TokenColumn,
+}
+
+impl<'a> TokenSlot<'a> {
+    pub fn new(user_id: i64, provider: &'a str, subject: &'a str, column: TokenColumn) -> Self {
+        Self {
+            user_id,
+            provider,
+            subject,
+            column,
+        }
+    }
+
+    fn aad(&self) -> Vec<u8> {
+        // Provider names cannot contain '\n'; subjects come last so their
+        // contents cannot shift the other fields.
+        format!(
+            "{}\n{}\n{}\n{}",
+            self.column.as_str(),
+            self.user_id,
+            self.provider,
+            self.subject
+        )
+        .into_bytes()
+    }
+}
+
+/// Encrypts and decrypts token columns with a set of named KEKs, one of
+/// which is active for new writes.
+#[derive(Clone)]
+pub struct TokenCipher {
+    active_kid: String,
+    keys: HashMap<String, Aes256Gcm>,
+}
+
+impl std::fmt::Debug for TokenCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenCipher")
+            .field("active_kid", &self.active_kid)
+            .field("kids", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl TokenCipher {
+    /// Parse `kid:base64key` pairs separated by commas. Each key must decode
+    /// to 32 bytes. `active_kid` picks the key for new writes and defaults to
+    /// the first one listed.
+    pub fn from_config(keys: &str, active_kid: Option<&str>) -> Result<Self, String> {
+        let mut parsed = HashMap::new();
+        let mut first = None;
+
+        for entry in keys.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (kid, b64) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("key entry {entry:?} is not kid:base64"))?;
+            if kid.is_empty() || kid.contains(':') {
+                return Err(format!("invalid key id {kid:?}"));
+            }
+            let bytes = STANDARD
+                .decode(b64)
+                .map_err(|e| format!("key {kid}: {e}"))?;
+            if bytes.len() != 32 {
+                return Err(format!("key {kid} must be 32 bytes, got {}", bytes.len()));
+            }
+            let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes));
+            if parsed.insert(kid.to_string(), cipher).is_some() {
+                return Err(format!("duplicate key id {kid}"));
+            }
+            first.get_or_insert_with(|| kid.to_string());
+        }
+
+        let active_kid = match active_kid {
+            Some(kid) => kid.to_string(),
+            None => first.ok_or("no encryption keys configured")?,
+        };
+        if !parsed.contains_key(&active_kid) {
+            return Err(format!("active key id {active_kid} is not configured"));
+        }
+
+        Ok(Self {
+            active_kid,
+            keys: parsed,
+        })
+    }
+
+    /// Encrypt `plaintext` for `slot` under a fresh DEK wrapped with the
+    /// active KEK.
+    pub fn encrypt(&self, plaintext: &str, slot: TokenSlot<'_>) -> Result<String, AppError> {
+        let dek = Aes256Gcm::generate_key(OsRng);
+        let body = seal(&Aes256Gcm::new(&dek), plaintext.as_bytes(), &slot.aad())?;
+        let wrapped = seal(&self.keys[&self.active_kid], dek.as_slice(), &[])?;
+        Ok(format!("{PREFIX}:{}:{wrapped}:{body}", self.active_kid))
+    }
+
+    /// Decrypt a value read from `slot`. Fails if it was encrypted for a
+    /// different slot, under an unknown key, or has been tampered with.
+    pub fn decrypt(&self, stored: &str, slot: TokenSlot<'_>) -> Result<String, AppError> {
+        let (kek, wrapped, body) = self.split(stored)?;
+        let plaintext = open(&data_key(kek, wrapped)?, body, &slot.aad())?;
+        String::from_utf8(plaintext).map_err(|e| AppError::Internal(e.to_string()))
+    }
+
+    /// Whether `stored` still has to be encrypted (legacy plaintext) or
+    /// re-wrapped (encrypted under a key other than the active one).
+    pub fn needs_migration(&self, stored: &str) -> bool {
+        match stored
+            .strip_prefix(PREFIX)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .and_then(|rest| rest.split_once(':'))
+        {
+            Some((kid, _)) => kid != self.active_kid,
+            None => true,
+        }
+    }
+
+    /// Bring a stored value up to date: encrypt legacy plaintext for `slot`,
+    /// or re-wrap the DEK under the active KEK without touching the
+    /// ciphertext.
+    pub fn rewrap(&self, stored: &str, slot: TokenSlot<'_>) -> Result<String, AppError> {
+        if !is_encrypted(stored) {
+            return self.encrypt(stored, slot);
+        }
+        let (kek, wrapped, body) = self.split(stored)?;
+        let dek = open(kek, wrapped, &[])?;
+        let rewrapped = seal(&self.keys[&self.active_kid], &dek, &[])?;
+        Ok(format!("{PREFIX}:{}:{rewrapped}:{body}", self.active_kid))
+    }
+
+    fn split<'a>(&self, stored: &'a str) -> Result<(&Aes256Gcm, &'a str, &'a str), AppError> {
+        let mut parts = stored.splitn(4, ':');
+        let (Some(PREFIX), Some(kid), Some(wrapped), Some(body)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(AppError::Internal("malformed encrypted token".into()));
+        };
+        let kek = self
+            .keys
+            .get(kid)
+            .ok_or_else(|| AppError::Internal(format!("unknown encryption key id {kid}")))?;
+        Ok((kek, wrapped, body))
+    }
+}
+
+/// Whether `stored` is one of our envelopes rather than legacy plaintext.
+fn is_encrypted(stored: &str) -> bool {
+    stored.strip_prefix(PREFIX).is_some_and(|rest| rest.starts_with(':'))
+}
+
+/// Unwrap a DEK with `kek`.
+fn data_key(kek: &Aes256Gcm, wrapped: &str) -> Result<Aes256Gcm, AppError> {
+    let dek = open(kek, wrapped, &[])?;
+    if dek.len() != 32 {
+        return Err(AppError::Internal("corrupt data key".into()));
+    }
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&dek)))
+}
+
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8], aad: &[u8]) -> Result<String, AppError> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad })
+        .map_err(|e| AppError::Internal(format!("encrypt: {e}")))?;
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&ciphertext);
+    Ok(STANDARD.encode(out))
+}
+
+fn open(cipher: &Aes256Gcm, encoded: &str, aad: &[u8]) -> Result<Vec<u8>, AppError> {
+    let bytes = STANDARD
+        .decode(encoded)
+        .map_err(|e| AppError::Internal(format!("decode: {e}")))?;
+    if bytes.len() < NONCE_LEN {
+        return Err(AppError::Internal("encrypted value too short".into()));
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|e| AppError::Internal(format!("decrypt: {e}")))
+}
+
+#[derive(sqlx::FromRow)]
+struct TokenRow {
+    user_id: i64,
+    provider: String,
+    subject: String,
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    id_token: Option<String>,
+}
+
+/// Encrypt any plaintext token columns left from before encryption existed
+/// and re-wrap values sealed under retired keys. Run at startup; returns the
+/// number of identities updated.
+pub async fn migrate_tokens(pool: &SqlitePool, cipher: &TokenCipher) -> Result<u64, AppError> {
+    let rows = sqlx::query_as::<_, TokenRow>(
+        r#"
+        SELECT user_id, provider, subject, access_token, refresh_token, id_token FROM identities
+        WHERE access_token IS NOT NULL OR refresh_token IS NOT NULL OR id_token IS NOT NULL
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let migrate = |row: &TokenRow, column, v: Option<String>| -> Result<Option<String>, AppError> {
+        let slot = TokenSlot::new(row.user_id, &row.provider, &row.subject, column);
+        v.map(|v| if cipher.needs_migration(&v) { cipher.rewrap(&v, slot) } else { Ok(v) })
+            .transpose()
+    };
+
+    let mut updated = 0;
+    for row in rows {
+        let stale = [&row.access_token, &row.refresh_token, &row.id_token]
+            .into_iter()
+            .flatten()
+            .any(|v| cipher.needs_migration(v));
+        if !stale {
+            continue;
+        }
+
+        let access_token = migrate(&row, TokenColumn::AccessToken, row.access_token.clone())?;
+        let refresh_token = migrate(&row, TokenColumn::RefreshToken, row.refresh_token.clone())?;
+        let id_token = migrate(&row, TokenColumn::IdToken, row.id_token.clone())?;
+        sqlx::query(
+            r#"
+            UPDATE identities SET access_token = ?1, refresh_token = ?2, id_token = ?3
+            WHERE provider = ?4 AND subject = ?5
+            "#,
+        )
+        .bind(access_token)
+        .bind(refresh_token)
+        .bind(id_token)
+        .bind(&row.provider)
+        .bind(&row.subject)
+        .execute(pool)
+        .await?;
+        updated += 1;
+    }
+
+    Ok(updated)
+}
@@ -1,12 +1,106 @@
-use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+use std::collections::HashMap;
 
-pub async fn init_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
+use sqlx::{sqlite::SqlitePoolOptions, SqliteConnection, SqlitePool};
+
+use crate::validation::normalize_interest;
+
+pub async fn init_pool(database_url: &str, max_connections: u32) -> Result<SqlitePool, sqlx::Error> {
     let pool = SqlitePoolOptions::new()
-        .max_connections(5)
+        .max_connections(max_connections)
         .connect(database_url)
         .await?;
 
     sqlx::migrate!("./migrations").run(&pool).await?;
+    run_data_migrations(&pool).await?;
 
     Ok(pool)
 }
+
+/// Apply the data fixes not yet recorded in `data_migrations`, each in one
+/// transaction with its marker.
+async fn run_data_migrations(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    const INTEREST_NAMES: &str = "interest_names";
+
+    let mut tx = pool.begin().await?;
+    let applied: bool =
+        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM data_migrations WHERE name = ?1)")
+            .bind(INTEREST_NAMES)
+            .fetch_one(&mut *tx)
+            .await?;
+    if applied {
+        return Ok(());
+    }
+
+    let folded = normalize_interests(&mut tx).await?;
+    sqlx::query("INSERT INTO data_migrations (name) VALUES (?1)")
+        .bind(INTEREST_NAMES)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    tracing::info!(interests = folded, "normalised stored interest names");
+    Ok(())
+}
+
+/// Bring stored interest names in line with [`normalize_interest`]. Names
+/// that now collide are merged onto the oldest row, moving their users with
+/// them, and blank names are dropped. Returns the number of interests
+/// renamed, merged or dropped.
+pub async fn normalize_interests(conn: &mut SqliteConnection) -> Result<u64, sqlx::Error> {
+    let rows: Vec<(i64, String)> = sqlx::query_as("SELECT id, name FROM interests ORDER BY id")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut keep: HashMap<String, i64> = HashMap::new();
+    let mut renames = Vec::new();
+    let mut changed = 0;
+    for (id, name) in rows {
+        let folded = normalize_interest(&name);
+        let target = if folded.is_empty() {
+            None
+        } else if let Some(&target) = keep.get(&folded) {
+            Some(target)
+        } else {
+            keep.insert(folded.clone(), id);
+            if folded != name {
+                renames.push((id, folded));
+            }
+            continue;
+        };
+
+        if let Some(target) = target {
+            sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO user_interests (user_id, interest_id)
+                SELECT user_id, ?1 FROM user_interests WHERE interest_id = ?2
+                "#,
+            )
+            .bind(target)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+        }
+        sqlx::query("DELETE FROM user_interests WHERE interest_id = ?1")
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("DELETE FROM interests WHERE id = ?1")
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+        changed += 1;
+    }
+
+    // Every row that folds to a kept name is gone by now, so the renames
+    // cannot hit the unique constraint.
+    for (id, name) in renames {
+        sqlx::query("UPDATE interests SET name = ?1 WHERE id = ?2")
+            .bind(name)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+        changed += 1;
+    }
+
+    Ok(changed)
+}
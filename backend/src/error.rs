@@ -1,6 +1,14 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{
+    Json,
+    extract::multipart::MultipartError,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
 use thiserror::Error;
 
+use crate::middleware::current_request_id;
+
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("database error: {0}")]
@@ -12,18 +20,188 @@ pub enum AppError {
     #[error("unauthorized")]
     Unauthorized,
 
+    /// The two users are not matched, so they may not message each other.
+    #[error("forbidden: users are not matched")]
+    NotMatched,
+
+    /// Sign-in refused by policy (tenant or email domain not allow-listed).
+    #[error("login denied: {0}")]
+    LoginDenied(String),
+
+    /// Sign-in refused because the account is suspended or banned.
+    #[error("account suspended")]
+    Suspended,
+
+    /// The named resource doesn't exist (or isn't visible to the caller).
+    #[error("{0} not found")]
+    NotFound(&'static str),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    BadRequest(String),
+
+    /// One or more fields failed validation.
+    #[error("invalid request")]
+    Validation(Vec<FieldError>),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("request body too large")]
+    PayloadTooLarge,
+
+    /// Retry after the given number of seconds, if known.
+    #[error("too many requests")]
+    RateLimited(Option<u64>),
+
     #[error("internal error: {0}")]
     Internal(String),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> axum::response::Response {
-        let status = match &self {
+/// A validation failure for one request field.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// JSON body of every error response.
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    /// Stable machine-readable code, e.g. `not_found`.
+    pub code: &'static str,
+    /// Human-readable summary, safe to show to users.
+    pub message: String,
+    /// Extra structured data, e.g. the failing fields of a validation error.
+    pub details: Option<serde_json::Value>,
+    /// Matches the `x-request-id` response header and the server logs.
+    pub request_id: Option<String>,
+}
+
+impl ErrorBody {
+    /// Body for `status` with the code clients would see for it from
+    /// [`AppError`]; used for responses not produced by a handler, such as
+    /// extractor rejections.
+    pub fn for_status(status: StatusCode, message: String) -> Self {
+        let code = match status {
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => "bad_request",
+            StatusCode::UNAUTHORIZED => "unauthorized",
+            StatusCode::FORBIDDEN => "forbidden",
+            StatusCode::NOT_FOUND => "not_found",
+            StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+            StatusCode::CONFLICT => "conflict",
+            StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+            StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+            s if s.is_client_error() => "bad_request",
+            _ => "internal",
+        };
+        Self {
+            code,
+            message,
+            details: None,
+            request_id: current_request_id(),
+        }
+    }
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
             AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::NotMatched
+            | AppError::LoginDenied(_)
+            | AppError::Suspended
+            | AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) | AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
             AppError::Db(_) | AppError::OAuth(_) | AppError::Internal(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            AppError::Unauthorized => "unauthorized",
+            AppError::NotMatched => "not_matched",
+            AppError::LoginDenied(_) => "login_denied",
+            AppError::Suspended => "account_suspended",
+            AppError::NotFound(_) => "not_found",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Conflict(_) => "conflict",
+            AppError::PayloadTooLarge => "payload_too_large",
+            AppError::RateLimited(_) => "rate_limited",
+            AppError::Db(_) | AppError::OAuth(_) | AppError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let request_id = current_request_id();
+
+        // Internal details go to the log only; the request id ties the two
+        // together.
+        let message = match &self {
+            AppError::Db(_) | AppError::OAuth(_) | AppError::Internal(_) => {
+                tracing::error!(request_id = request_id.as_deref(), error = %self, "request failed");
+                "internal server error".to_string()
+            }
+            AppError::LoginDenied(reason) => {
+                tracing::info!(request_id = request_id.as_deref(), reason, "sign-in denied");
+                "sign-in is not allowed for this account".to_string()
+            }
+            other => other.to_string(),
+        };
+        let details = match &self {
+            AppError::Validation(errors) => Some(serde_json::json!({ "fields": errors })),
+            _ => None,
         };
-        (status, self.to_string()).into_response()
+
+        let mut response = (
+            status,
+            Json(ErrorBody {
+                code: self.code(),
+                message,
+                details,
+                request_id,
+            }),
+        )
+            .into_response();
+        if let AppError::RateLimited(Some(secs)) = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+/// Oversized uploads surface as multipart errors once the body limit is hit.
+impl From<MultipartError> for AppError {
+    fn from(e: MultipartError) -> Self {
+        match e.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge,
+            s if s.is_client_error() => AppError::BadRequest(e.body_text()),
+            _ => AppError::Internal(e.to_string()),
+        }
     }
 }
@@ -0,0 +1,486 @@
+//! Uploaded image handling.
+//!
+//! Uploads are identified by their magic bytes, never by the client's
+//! `Content-Type` or file name. Accepted images are decoded and re-encoded,
+//! which drops EXIF/GPS and other metadata as well as anything appended
+//! after the image data; the EXIF orientation is applied to the pixels first
+//! so photos keep displaying the right way up.
+//!
+//! HEIC (iPhone photos) needs the `heic` cargo feature and the system
+//! libheif; it is re-encoded as JPEG because few browsers can display it.
+//!
+//! Profile photos also get downscaled WebP variants (see [`VARIANT_SIZES`])
+//! stored next to the original under [`variant_key`].
+//!
+//! Photo keys are content-addressed ([`photo_key`]), so an object is never
+//! rewritten once stored. A replaced or removed photo is recorded in
+//! `retired_images` and stays in the store for the configured retention
+//! period; [`collect_garbage`] then deletes objects nothing references.
+
+use std::{collections::HashSet, io::Cursor};
+
+use bytes::Bytes;
+use image::{
+    DynamicImage, ImageDecoder, ImageEncoder, ImageReader,
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    imageops::FilterType,
+};
+use object_store::{ObjectStore, ObjectStoreExt, PutPayload, path::Path as StorePath};
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteConnection, SqlitePool};
+use thiserror::Error;
+use tokio_stream::StreamExt;
+
+use crate::error::{AppError, FieldError};
+
+/// JPEG quality for re-encoded photos.
+const JPEG_QUALITY: u8 = 85;
+
+/// WebP quality for re-encoded photos.
+const WEBP_QUALITY: f32 = 85.0;
+
+/// Longest side, in pixels, of each derived variant of a profile photo.
+pub const VARIANT_SIZES: [u32; 3] = [128, 512, 1080];
+
+/// WebP quality for derived variants.
+const VARIANT_QUALITY: f32 = 80.0;
+
+/// Size limits for uploaded images, from [`Config::images`](crate::config::Config).
+#[derive(Clone, Debug)]
+pub struct ImageRules {
+    /// Longest allowed side, in pixels.
+    pub max_dimension: u32,
+    /// Largest allowed `width * height`.
+    pub max_pixels: u64,
+}
+
+impl Default for ImageRules {
+    fn default() -> Self {
+        Self {
+            max_dimension: 8192,
+            max_pixels: 40_000_000,
+        }
+    }
+}
+
+/// Image formats accepted for upload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    Webp,
+    Heic,
+}
+
+impl Format {
+    /// Identify `data` by its leading bytes.
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            Some(Self::Webp)
+        } else if data.len() >= 12
+            && &data[4..8] == b"ftyp"
+            && matches!(
+                &data[8..12],
+                b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"mif1" | b"msf1"
+            )
+        {
+            Some(Self::Heic)
+        } else {
+            None
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+            Self::Heic => "image/heic",
+        }
+    }
+}
+
+/// Prefixes of everything [`collect_garbage`] manages. `profiles/{user_id}`
+/// holds photos uploaded before keys were content-addressed.
+const MANAGED_PREFIXES: [&str; 3] = ["photos", "profiles", "messages"];
+
+/// Content-addressed key for a profile photo of `user_id`, e.g.
+/// `photos/7/3f2a…`. Identical uploads share a key.
+pub fn photo_key(user_id: i64, data: &[u8]) -> String {
+    let digest = format!("{:x}", Sha256::digest(data));
+    format!("photos/{user_id}/{}", &digest[..32])
+}
+
+/// Object key of the `size` variant of the image stored at `key`, e.g.
+/// `profiles/7_512.webp`.
+pub fn variant_key(key: &str, size: u32) -> String {
+    format!("{key}_{size}.webp")
+}
+
+/// The key a variant was derived from, or `key` itself for an original.
+fn base_key(key: &str) -> &str {
+    VARIANT_SIZES
+        .iter()
+        .find_map(|size| key.strip_suffix(&format!("_{size}.webp")))
+        .unwrap_or(key)
+}
+
+/// The smallest variant at least `requested` pixels on its longest side, or
+/// the largest one.
+pub fn variant_size(requested: u32) -> u32 {
+    VARIANT_SIZES
+        .into_iter()
+        .find(|&s| s >= requested)
+        .unwrap_or(VARIANT_SIZES[VARIANT_SIZES.len() - 1])
+}
+
+/// Write `data` (already cleaned) to `key` along with all of its variants.
+pub async fn store_image(
+    store: &dyn ObjectStore,
+    rules: &ImageRules,
+    key: &str,
+    data: Bytes,
+) -> Result<(), AppError> {
+    let variants = rules
+        .variants(data.clone(), &VARIANT_SIZES)
+        .await
+        .map_err(|e| AppError::Internal(format!("image variants: {e}")))?;
+    put(store, key, data).await?;
+    for (size, variant) in variants {
+        put(store, &variant_key(key, size), variant).await?;
+    }
+    Ok(())
+}
+
+/// Record that `user_id` stopped using the photo at `key`, so its object is
+/// kept for the retention period before [`collect_garbage`] may delete it.
+/// Keys still used by another photo are kept regardless.
+pub async fn retire_image(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    key: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO retired_images (key, user_id) VALUES (?1, ?2)
+        ON CONFLICT(key) DO UPDATE SET user_id = excluded.user_id, retired_at = datetime('now')
+        "#,
+    )
+    .bind(key)
+    .bind(user_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Delete stored images, variants included, that no user, profile photo or
+/// message references, unless they were retired or written within the last
+/// `retention_days`. Returns the number of objects deleted.
+pub async fn collect_garbage(
+    pool: &SqlitePool,
+    store: &dyn ObjectStore,
+    retention_days: i64,
+) -> Result<u64, AppError> {
+    let window = format!("-{retention_days} days");
+    let keep: HashSet<String> = sqlx::query_scalar(
+        r#"
+        SELECT image_key FROM users WHERE image_key IS NOT NULL
+        UNION
+        SELECT image_key FROM profile_photos
+        UNION
+        SELECT image_key FROM messages WHERE image_key IS NOT NULL
+        UNION
+        SELECT key FROM retired_images WHERE retired_at > datetime('now', ?1)
+        "#,
+    )
+    .bind(&window)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    let cutoff = cutoff_timestamp(retention_days);
+
+    let mut deleted = 0;
+    for prefix in MANAGED_PREFIXES {
+        let mut objects = store.list(Some(&StorePath::from(prefix)));
+        while let Some(meta) = objects.next().await {
+            let meta = meta.map_err(|e| AppError::Internal(e.to_string()))?;
+            let key = meta.location.as_ref();
+            if keep.contains(base_key(key)) || meta.last_modified.timestamp() > cutoff {
+                continue;
+            }
+            match store.delete(&meta.location).await {
+                Ok(()) => deleted += 1,
+                Err(object_store::Error::NotFound { .. }) => {}
+                Err(e) => return Err(AppError::Internal(e.to_string())),
+            }
+        }
+    }
+
+    sqlx::query("DELETE FROM retired_images WHERE retired_at <= datetime('now', ?1)")
+        .bind(&window)
+        .execute(pool)
+        .await?;
+    Ok(deleted)
+}
+
+/// Unix time `days` ago.
+fn cutoff_timestamp(days: i64) -> i64 {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    now - days * 24 * 60 * 60
+}
+
+async fn put(store: &dyn ObjectStore, key: &str, data: Bytes) -> Result<(), AppError> {
+    store
+        .put(&StorePath::from(key), PutPayload::from(data))
+        .await
+        .map(|_| ())
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Key of the `size` variant of the image at `key` (see [`variant_size`]).
+/// Images stored before variants existed get them generated from the
+/// original and stored first.
+pub async fn ensure_variant(
+    store: &dyn ObjectStore,
+    rules: &ImageRules,
+    key: &str,
+    size: u32,
+) -> Result<String, AppError> {
+    let variant = variant_key(key, variant_size(size));
+    match store.head(&StorePath::from(variant.as_str())).await {
+        Ok(_) => return Ok(variant),
+        Err(object_store::Error::NotFound { .. }) => {}
+        Err(e) => return Err(AppError::Internal(e.to_string())),
+    }
+
+    let original = store
+        .get(&StorePath::from(key))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .bytes()
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let variants = rules
+        .variants(original, &VARIANT_SIZES)
+        .await
+        .map_err(|e| AppError::Internal(format!("image variants for {key}: {e}")))?;
+    for (size, data) in variants {
+        put(store, &variant_key(key, size), data).await?;
+    }
+    Ok(variant)
+}
+
+/// Why an upload was rejected.
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("must be a JPEG, PNG or WebP image")]
+    Unsupported,
+
+    #[error("HEIC images are not supported by this server")]
+    HeicUnsupported,
+
+    #[error(
+        "is {width}x{height}; images may be at most {max_dimension} pixels on a side and {max_pixels} pixels in total"
+    )]
+    TooLarge {
+        width: u32,
+        height: u32,
+        max_dimension: u32,
+        max_pixels: u64,
+    },
+
+    #[error("could not be read as an image: {0}")]
+    Corrupt(String),
+
+    #[error("re-encoding failed: {0}")]
+    Encode(String),
+}
+
+impl ImageError {
+    /// The error for an upload sent as the form field `field`.
+    pub fn for_field(self, field: &str) -> AppError {
+        match self {
+            ImageError::Encode(e) => AppError::Internal(format!("image encoding: {e}")),
+            other => AppError::Validation(vec![FieldError::new(field, other.to_string())]),
+        }
+    }
+}
+
+/// An upload after sniffing, validation and re-encoding.
+#[derive(Debug)]
+pub struct CleanImage {
+    pub data: Bytes,
+    pub content_type: &'static str,
+}
+
+impl ImageRules {
+    /// Sniff, check and re-encode `data` off the async runtime; decoding a
+    /// large photo takes long enough to stall other requests.
+    pub async fn clean(&self, data: Bytes) -> Result<CleanImage, ImageError> {
+        let rules = self.clone();
+        tokio::task::spawn_blocking(move || rules.clean_blocking(&data))
+            .await
+            .map_err(|e| ImageError::Encode(e.to_string()))?
+    }
+
+    pub fn clean_blocking(&self, data: &[u8]) -> Result<CleanImage, ImageError> {
+        let (image, format) = self.decode_any(data)?;
+        encode(&image, format)
+    }
+
+    /// WebP variants of `data` no larger than each of `sizes` on their
+    /// longest side, decoded once off the async runtime.
+    pub async fn variants(
+        &self,
+        data: Bytes,
+        sizes: &[u32],
+    ) -> Result<Vec<(u32, Bytes)>, ImageError> {
+        let rules = self.clone();
+        let sizes = sizes.to_vec();
+        tokio::task::spawn_blocking(move || {
+            let (image, _) = rules.decode_any(&data)?;
+            Ok(sizes
+                .into_iter()
+                .map(|size| (size, encode_variant(&image, size)))
+                .collect())
+        })
+        .await
+        .map_err(|e| ImageError::Encode(e.to_string()))?
+    }
+
+    fn decode_any(&self, data: &[u8]) -> Result<(DynamicImage, Format), ImageError> {
+        let format = Format::sniff(data).ok_or(ImageError::Unsupported)?;
+        let image = match format {
+            Format::Jpeg => self.decode(data, image::ImageFormat::Jpeg)?,
+            Format::Png => self.decode(data, image::ImageFormat::Png)?,
+            Format::Webp => self.decode(data, image::ImageFormat::WebP)?,
+            Format::Heic => self.decode_heic(data)?,
+        };
+        Ok((image, format))
+    }
+
+    fn check_size(&self, width: u32, height: u32) -> Result<(), ImageError> {
+        if width.max(height) > self.max_dimension
+            || u64::from(width) * u64::from(height) > self.max_pixels
+        {
+            return Err(ImageError::TooLarge {
+                width,
+                height,
+                max_dimension: self.max_dimension,
+                max_pixels: self.max_pixels,
+            });
+        }
+        Ok(())
+    }
+
+    /// Decode with the size checked from the header before any pixels are
+    /// allocated, then apply the EXIF orientation.
+    fn decode(&self, data: &[u8], format: image::ImageFormat) -> Result<DynamicImage, ImageError> {
+        let corrupt = |e: image::ImageError| ImageError::Corrupt(e.to_string());
+        let mut decoder = ImageReader::with_format(Cursor::new(data), format)
+            .into_decoder()
+            .map_err(corrupt)?;
+        let (width, height) = decoder.dimensions();
+        self.check_size(width, height)?;
+        let orientation = decoder.orientation().map_err(corrupt)?;
+        let mut image = DynamicImage::from_decoder(decoder).map_err(corrupt)?;
+        image.apply_orientation(orientation);
+        Ok(image)
+    }
+
+    #[cfg(feature = "heic")]
+    fn decode_heic(&self, data: &[u8]) -> Result<DynamicImage, ImageError> {
+        use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+        let corrupt = |e: libheif_rs::HeifError| ImageError::Corrupt(e.to_string());
+        let context = HeifContext::read_from_bytes(data).map_err(corrupt)?;
+        let handle = context.primary_image_handle().map_err(corrupt)?;
+        self.check_size(handle.width(), handle.height())?;
+
+        // libheif applies the container's rotation and mirroring itself.
+        let decoded = LibHeif::new()
+            .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)
+            .map_err(corrupt)?;
+        let plane = decoded
+            .planes()
+            .interleaved
+            .ok_or_else(|| ImageError::Corrupt("no interleaved RGB plane".into()))?;
+        let row = plane.width as usize * 3;
+        let pixels: Vec<u8> = plane
+            .data
+            .chunks(plane.stride)
+            .take(plane.height as usize)
+            .flat_map(|line| &line[..row])
+            .copied()
+            .collect();
+        image::RgbImage::from_raw(plane.width, plane.height, pixels)
+            .map(DynamicImage::ImageRgb8)
+            .ok_or_else(|| ImageError::Corrupt("truncated HEIC image".into()))
+    }
+
+    #[cfg(not(feature = "heic"))]
+    fn decode_heic(&self, _data: &[u8]) -> Result<DynamicImage, ImageError> {
+        Err(ImageError::HeicUnsupported)
+    }
+}
+
+/// Encode `image` as `format` (HEIC as JPEG), converting pixels to what the
+/// encoder takes.
+fn encode(image: &DynamicImage, format: Format) -> Result<CleanImage, ImageError> {
+    let mut out = Vec::new();
+    let result = match format {
+        Format::Jpeg => {
+            let rgb = image.to_rgb8();
+            JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY).write_image(
+                &rgb,
+                rgb.width(),
+                rgb.height(),
+                image::ExtendedColorType::Rgb8,
+            )
+        }
+        Format::Png => image.write_with_encoder(PngEncoder::new(&mut out)),
+        // Lossy, like the uploads themselves usually are: a lossless re-encode
+        // of a camera photo can be several times the size of the original.
+        Format::Webp => {
+            out = lossy_webp(image, WEBP_QUALITY);
+            Ok(())
+        }
+        Format::Heic => return encode(image, Format::Jpeg),
+    };
+    result.map_err(|e| ImageError::Encode(e.to_string()))?;
+    Ok(CleanImage {
+        data: out.into(),
+        content_type: format.content_type(),
+    })
+}
+
+/// Lossy WebP of `image` fitted within `size` x `size`; smaller images are
+/// not enlarged.
+fn encode_variant(image: &DynamicImage, size: u32) -> Bytes {
+    let resized;
+    let image = if image.width().max(image.height()) > size {
+        resized = image.resize(size, size, FilterType::CatmullRom);
+        &resized
+    } else {
+        image
+    };
+    lossy_webp(image, VARIANT_QUALITY).into()
+}
+
+/// `image` as lossy WebP at `quality` (0–100).
+fn lossy_webp(image: &DynamicImage, quality: f32) -> Vec<u8> {
+    let (width, height) = (image.width(), image.height());
+    let encoded = if image.color().has_alpha() {
+        webp::Encoder::from_rgba(&image.to_rgba8(), width, height).encode(quality)
+    } else {
+        webp::Encoder::from_rgb(&image.to_rgb8(), width, height).encode(quality)
+    };
+    encoded.to_vec()
+}
@@ -1,27 +1,97 @@
 pub mod api;
 pub mod auth;
+pub mod authz;
+pub mod config;
+pub mod crypto;
 pub mod db;
 pub mod error;
+pub mod images;
+pub mod media;
 pub mod middleware;
 pub mod models;
+pub mod photos;
+pub mod realtime;
+pub mod router;
+pub mod session_store;
+pub mod validation;
+
+pub use router::app;
 
 use std::sync::Arc;
 
 use axum::extract::FromRef;
-use object_store::ObjectStore;
+use object_store::{ObjectStore, memory::InMemory};
 use sqlx::SqlitePool;
 
-use crate::auth::backend::MicrosoftBackend;
+use crate::{auth::backend::AuthBackend, config::Config, realtime::Hub};
 
 /// Shared application state threaded through Axum handlers.
 #[derive(Clone)]
 pub struct AppState {
     pub pool: SqlitePool,
-    pub backend: MicrosoftBackend,
-    /// Base URL of the Leptos frontend (e.g. `http://localhost:3001`).
-    /// Used for post-auth redirects and CORS allow-origin.
-    pub frontend_url: String,
+    pub backend: AuthBackend,
+    /// Validated startup configuration.
+    pub config: Arc<Config>,
     pub store: Arc<dyn ObjectStore>,
+    /// Fan-out of real-time events to connected `/ws` clients.
+    pub hub: Hub,
+}
+
+impl AppState {
+    /// Start building state around `pool` and `config`. The auth backend
+    /// defaults to one with no identity providers, using the configured token
+    /// cipher and sign-in policy.
+    pub fn builder(pool: SqlitePool, config: Config) -> AppStateBuilder {
+        AppStateBuilder {
+            pool,
+            config: Arc::new(config),
+            backend: None,
+            store: None,
+            hub: None,
+        }
+    }
+}
+
+/// Builder for [`AppState`]; see [`AppState::builder`].
+pub struct AppStateBuilder {
+    pool: SqlitePool,
+    config: Arc<Config>,
+    backend: Option<AuthBackend>,
+    store: Option<Arc<dyn ObjectStore>>,
+    hub: Option<Hub>,
+}
+
+impl AppStateBuilder {
+    pub fn backend(mut self, backend: AuthBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Where uploads are stored. Defaults to an in-memory store, which is
+    /// only suitable for tests.
+    pub fn store(mut self, store: Arc<dyn ObjectStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    pub fn hub(mut self, hub: Hub) -> Self {
+        self.hub = Some(hub);
+        self
+    }
+
+    pub fn build(self) -> AppState {
+        let backend = self.backend.unwrap_or_else(|| {
+            AuthBackend::new(self.pool.clone(), self.config.token_cipher.clone())
+                .with_sign_in_policy(self.config.sign_in_policy.clone())
+        });
+        AppState {
+            pool: self.pool,
+            backend,
+            config: self.config,
+            store: self.store.unwrap_or_else(|| Arc::new(InMemory::new())),
+            hub: self.hub.unwrap_or_default(),
+        }
+    }
 }
 
 impl FromRef<AppState> for SqlitePool {
@@ -30,7 +100,7 @@ impl FromRef<AppState> for SqlitePool {
     }
 }
 
-impl FromRef<AppState> for MicrosoftBackend {
+impl FromRef<AppState> for AuthBackend {
     fn from_ref(state: &AppState) -> Self {
         state.backend.clone()
     }
@@ -1,35 +1,17 @@
-use axum::{
-    extract::DefaultBodyLimit,
-    http::{HeaderValue, Method},
-    middleware,
-    routing::{get, post},
-    Router,
-};
-use axum_login::AuthManagerLayerBuilder;
-use reqwest::header::ACCEPT_ENCODING;
-use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
-use tower_sessions::{MemoryStore, SessionManagerLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
-
 use std::sync::Arc;
 
 use object_store::local::LocalFileSystem;
-use axum::http::header::{ACCEPT, ACCEPT_CHARSET, ACCESS_CONTROL_ALLOW_CREDENTIALS, CONTENT_ENCODING, CONTENT_TYPE, ORIGIN, REFERER, SET_COOKIE};
+use tower_sessions::cookie::time::Duration;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 use backend::{
-    api::{
-        likes::submit_like,
-        matches::get_matches,
-        messages::{get_message_image, get_messages, send_message},
-        profiles::{compatible_profiles, get_profile, get_profile_image},
-        user::{me, update_profile},
-    },
-    auth::{
-        backend::MicrosoftBackend,
-        routes::{callback, login, logout},
-    },
+    auth::{backend::AuthBackend, oidc::OidcProvider},
+    config::Config,
+    crypto,
     db::init_pool,
-    middleware::require_user,
+    images,
+    realtime,
+    session_store::SqliteSessionStore,
     AppState,
 };
 
@@ -42,86 +24,116 @@ async fn main() -> anyhow::Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let database_url = std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:app.db".into());
-    let pool = init_pool(&database_url).await?;
+    let config = Config::load()?;
+
+    let pool = init_pool(&config.database_url, config.db_pool_size).await?;
 
-    let store_path = std::env::var("OBJECT_STORE_PATH").unwrap_or_else(|_| "./uploads".into());
-    std::fs::create_dir_all(&store_path)?;
+    std::fs::create_dir_all(&config.object_store_path)?;
     let store: Arc<dyn object_store::ObjectStore> =
-        Arc::new(LocalFileSystem::new_with_prefix(&store_path)?);
-
-    let client_id = std::env::var("AZURE_CLIENT_ID")?;
-    let client_secret = std::env::var("AZURE_CLIENT_SECRET")?;
-    let tenant = std::env::var("AZURE_TENANT_ID").unwrap_or_else(|_| "common".into());
-    let redirect_url = std::env::var("REDIRECT_URL")?;
-    // Base URL of the SPA (same origin since we serve it with ServeDir).
-    let frontend_url =
-        std::env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:48757".into());
-
-    let backend =
-        MicrosoftBackend::new(pool.clone(), client_id, client_secret, &tenant, redirect_url)?;
-
-    // Session layer — swap MemoryStore for a persistent store in production
-    // (e.g. tower-sessions-sqlx-store).
-    let session_store = MemoryStore::default();
-    let session_layer = SessionManagerLayer::new(session_store)
-        .with_same_site(tower_sessions::cookie::SameSite::Lax);
-
-    let auth_layer = AuthManagerLayerBuilder::new(backend.clone(), session_layer).build();
-
-    let state = AppState {
-        pool,
-        backend,
-        frontend_url: frontend_url.clone(),
-        store,
-    };
-
-    // CORS — must allow credentials so the browser sends the session cookie
-    // on cross-origin requests from the Leptos frontend.
-    let frontend_origin: HeaderValue = frontend_url
-        .parse()
-        .expect("FRONTEND_URL is not a valid HTTP origin");
-
-    let origins = [
-        "http://localhost:8080".parse().unwrap(),     
-        "http://0.0.0.0:8080".parse().unwrap(),
-        "http://127.0.0.1:8080".parse().unwrap(),
-        frontend_origin
-    ];
-
-    let cors = CorsLayer::new()
-        .allow_origin(origins)
-        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
-        .allow_headers([ACCEPT_ENCODING,CONTENT_ENCODING, REFERER, ORIGIN, ACCEPT, CONTENT_TYPE, ACCEPT_CHARSET, REFERER, ACCESS_CONTROL_ALLOW_CREDENTIALS])
-        .expose_headers([SET_COOKIE, CONTENT_ENCODING, ACCEPT_ENCODING])
-        .allow_credentials(true);
-
-    let protected = Router::new()
-        .route("/user/me", get(me))
-        .route("/user/profile", post(update_profile).layer(DefaultBodyLimit::max(20 * 1024 * 1024)))
-        .route("/like", post(submit_like))
-        .route("/matches", get(get_matches))
-        .route("/message", post(send_message).layer(DefaultBodyLimit::max(20 * 1024 * 1024)))
-        .route("/messages/{user_id}", get(get_messages))
-        .route("/messages/{message_id}/image", get(get_message_image))
-        // static segment must be declared before the dynamic :id capture
-        .route("/profiles/compatible", get(compatible_profiles))
-        .route("/profiles/{id}", get(get_profile))
-        .route("/profiles/{id}/image", get(get_profile_image))
-        .layer(middleware::from_fn_with_state(state.clone(), require_user));
-
-    let app = Router::new()
-        .route("/auth/login", get(login))
-        .route("/auth/callback", get(callback))
-        .route("/auth/logout", get(logout))
-        .merge(protected)
-        .layer(auth_layer)
-        .layer(cors)
-        .layer(TraceLayer::new_for_http())
-        .with_state(state)
-        .fallback_service(ServeDir::new("../frontend").append_index_html_on_directories(true));
-
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:48757").await?;
+        Arc::new(LocalFileSystem::new_with_prefix(&config.object_store_path)?);
+
+    // Trim the SSE replay log to the configured reconnect window.
+    let prune_pool = pool.clone();
+    let event_retention_days = config.event_retention_days;
+    let prune_every =
+        std::time::Duration::from_secs(config.event_prune_interval_hours as u64 * 60 * 60);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(prune_every);
+        loop {
+            interval.tick().await;
+            match realtime::prune_events(&prune_pool, event_retention_days).await {
+                Ok(n) if n > 0 => tracing::info!(removed = n, "pruned old events"),
+                Ok(_) => {}
+                Err(e) => tracing::warn!(error = %e, "failed to prune events"),
+            }
+        }
+    });
+
+    // Delete unreferenced images once a day, once they are past retention.
+    let gc_pool = pool.clone();
+    let gc_store = store.clone();
+    let retention_days = config.image_retention_days;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(24 * 60 * 60));
+        loop {
+            interval.tick().await;
+            match images::collect_garbage(&gc_pool, gc_store.as_ref(), retention_days).await {
+                Ok(n) if n > 0 => tracing::info!(removed = n, "deleted unreferenced images"),
+                Ok(_) => {}
+                Err(e) => tracing::warn!(error = %e, "image garbage collection failed"),
+            }
+        }
+    });
+
+    // Stored tokens under an older key id are re-wrapped under the active one.
+    let migrated = crypto::migrate_tokens(&pool, &config.token_cipher).await?;
+    if migrated > 0 {
+        tracing::info!(identities = migrated, "encrypted / re-wrapped stored OAuth tokens");
+    }
+
+    let mut backend = AuthBackend::new(pool.clone(), config.token_cipher.clone())
+        .with_sign_in_policy(config.sign_in_policy.clone());
+
+    let http = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    // Validation guarantees a redirect URL whenever a provider is configured.
+    let redirect_url = || config.redirect_url.clone().unwrap_or_default();
+
+    // Microsoft Entra ID, when configured, is the default provider.
+    if let Some(azure) = &config.azure {
+        backend = backend.with_provider(OidcProvider::microsoft(
+            azure.client_id.clone(),
+            azure.client_secret.expose().to_string(),
+            &azure.tenant_id,
+            redirect_url(),
+            azure.endpoints.clone(),
+            http.clone(),
+        )?);
+    }
+
+    // Generic OIDC providers; endpoints come from the issuer's discovery
+    // document.
+    for p in &config.oidc_providers {
+        let provider = OidcProvider::discover(
+            p.name.clone(),
+            p.label.clone(),
+            &p.issuer,
+            p.client_id.clone(),
+            p.client_secret.expose().to_string(),
+            redirect_url(),
+            p.scopes.clone(),
+            http.clone(),
+        )
+        .await?;
+        backend = backend.with_provider(provider);
+    }
+
+    // Local sign-in as any seeded user, without an identity provider.
+    // Validation only allows it on a loopback address.
+    if config.dev_login {
+        tracing::warn!("dev login enabled: /auth/dev-login signs in as any user");
+    }
+
+    // Renew stored tokens in the background. A refresh that fails with
+    // `invalid_grant` deactivates the account (e.g. removed from the tenant).
+    backend.spawn_token_refresh(
+        std::time::Duration::from_secs(60 * 60),
+        config.token_refresh_interval_hours,
+    );
+
+    // Expired sessions are deleted in the background; the session layer
+    // itself is part of the router.
+    SqliteSessionStore::new(pool.clone(), Duration::hours(config.session_absolute_timeout_hours))
+        .spawn_cleanup(std::time::Duration::from_secs(15 * 60));
+
+    let state = AppState::builder(pool, config.clone())
+        .backend(backend)
+        .store(store)
+        .build();
+    let app = backend::app(state, &config);
+
+    let listener = tokio::net::TcpListener::bind(config.bind_addr).await?;
     tracing::info!("listening on {}", listener.local_addr()?);
     axum::serve(listener, app).await?;
 
@@ -0,0 +1,226 @@
+//! Serving stored objects over HTTP.
+//!
+//! Responses carry an `ETag` taken from the store's object metadata and a
+//! `Last-Modified`, answer `If-None-Match` / `If-Modified-Since` with
+//! `304 Not Modified`, honour a single `Range` (guarded by `If-Range`), and
+//! stream the body from the object store instead of buffering it.
+
+use std::{
+    ops::Range,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use object_store::{
+    GetOptions, GetRange, ObjectMeta, ObjectStore, ObjectStoreExt, path::Path as StorePath,
+};
+
+use crate::{error::AppError, images::Format};
+
+/// For objects replaced in place under the same key: cache, but check the
+/// `ETag` before every reuse.
+pub const REVALIDATE: &str = "private, no-cache";
+
+/// For objects never rewritten once stored.
+pub const IMMUTABLE: &str = "private, max-age=31536000, immutable";
+
+/// Respond to a `GET`/`HEAD` for the object at `key`, given the request's
+/// `headers`. `content_type` is the one recorded at upload; objects stored
+/// before types were recorded are sniffed from their first bytes.
+pub async fn object_response(
+    store: &dyn ObjectStore,
+    key: &str,
+    content_type: Option<&str>,
+    cache_control: &'static str,
+    request: &HeaderMap,
+) -> Result<Response, AppError> {
+    let path = StorePath::from(key);
+    let meta = store.head(&path).await.map_err(store_error)?;
+    let etag = etag(&meta);
+    let modified = modified(&meta);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ETAG, header_value(&etag)?);
+    headers.insert(
+        header::LAST_MODIFIED,
+        header_value(&httpdate::fmt_http_date(modified))?,
+    );
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(cache_control),
+    );
+
+    if not_modified(request, &etag, modified) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    let size = meta.size;
+    let range = match requested_range(request, &etag, modified, size) {
+        Ok(range) => range,
+        Err(Unsatisfiable) => {
+            headers.insert(
+                header::CONTENT_RANGE,
+                header_value(&format!("bytes */{size}"))?,
+            );
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+        }
+    };
+
+    let content_type = match content_type {
+        Some(t) => t.to_string(),
+        None if size == 0 => "application/octet-stream".to_string(),
+        None => {
+            let head = store
+                .get_range(&path, 0..size.min(16))
+                .await
+                .map_err(store_error)?;
+            Format::sniff(&head)
+                .map_or("application/octet-stream", Format::content_type)
+                .to_string()
+        }
+    };
+    headers.insert(header::CONTENT_TYPE, header_value(&content_type)?);
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    let status = match &range {
+        Some(r) => {
+            headers.insert(
+                header::CONTENT_RANGE,
+                header_value(&format!("bytes {}-{}/{size}", r.start, r.end - 1))?,
+            );
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(r.end - r.start));
+            StatusCode::PARTIAL_CONTENT
+        }
+        None => {
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(size));
+            StatusCode::OK
+        }
+    };
+
+    // An empty object can't be fetched with a range; there is nothing to
+    // stream anyway.
+    let body = if size == 0 {
+        Body::empty()
+    } else {
+        let options = GetOptions {
+            range: range.map(GetRange::Bounded),
+            ..Default::default()
+        };
+        Body::from_stream(
+            store
+                .get_opts(&path, options)
+                .await
+                .map_err(store_error)?
+                .into_stream(),
+        )
+    };
+
+    Ok((status, headers, body).into_response())
+}
+
+fn store_error(e: object_store::Error) -> AppError {
+    match e {
+        object_store::Error::NotFound { .. } => AppError::NotFound("image"),
+        e => AppError::Internal(e.to_string()),
+    }
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, AppError> {
+    HeaderValue::from_str(value).map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Strong entity tag from the store's own, or from the modification time and
+/// size for stores that don't provide one.
+fn etag(meta: &ObjectMeta) -> String {
+    match &meta.e_tag {
+        Some(tag) => format!("\"{}\"", tag.trim_matches('"')),
+        None => format!(
+            "\"{:x}-{:x}\"",
+            meta.last_modified.timestamp_micros(),
+            meta.size
+        ),
+    }
+}
+
+/// Modification time at the one-second resolution of HTTP dates.
+fn modified(meta: &ObjectMeta) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(meta.last_modified.timestamp().max(0) as u64)
+}
+
+/// `If-None-Match` takes precedence; `If-Modified-Since` is only consulted
+/// without it (RFC 9110 §13.2.2).
+fn not_modified(request: &HeaderMap, etag: &str, modified: SystemTime) -> bool {
+    if let Some(value) = request.get(header::IF_NONE_MATCH) {
+        return value.to_str().is_ok_and(|list| {
+            list.split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        });
+    }
+    request
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| httpdate::parse_http_date(v.to_str().ok()?).ok())
+        .is_some_and(|since| modified <= since)
+}
+
+struct Unsatisfiable;
+
+/// The byte range to send, if the request asked for a single one this
+/// object can satisfy. Multiple ranges, malformed headers and a stale
+/// `If-Range` all get the full object.
+fn requested_range(
+    request: &HeaderMap,
+    etag: &str,
+    modified: SystemTime,
+    size: u64,
+) -> Result<Option<Range<u64>>, Unsatisfiable> {
+    let Some(spec) = request
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("bytes="))
+    else {
+        return Ok(None);
+    };
+    if let Some(validator) = request.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) {
+        let fresh = if validator.starts_with('"') {
+            validator == etag
+        } else {
+            httpdate::parse_http_date(validator).is_ok_and(|date| date == modified)
+        };
+        if !fresh {
+            return Ok(None);
+        }
+    }
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // bytes=-N: the last N bytes.
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 || size == 0 {
+                return Err(Unsatisfiable);
+            }
+            size.saturating_sub(suffix)..size
+        }
+        // bytes=N-
+        (Ok(start), Err(_)) if end.is_empty() => start..size,
+        (Ok(start), Ok(end)) if start <= end => start..(end + 1).min(size),
+        _ => return Ok(None),
+    };
+    if range.start >= size {
+        return Err(Unsatisfiable);
+    }
+    Ok(Some(range))
+}
@@ -1,15 +1,118 @@
-use axum::{extract::Request, middleware::Next, response::Response, http::StatusCode};
+use axum::{
+    Json,
+    body::{self, Body},
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
 use axum_login::AuthSession;
 
-use crate::auth::backend::MicrosoftBackend;
+use crate::{
+    auth::backend::AuthBackend,
+    error::{AppError, ErrorBody},
+    models::Role,
+};
 
 pub async fn require_user(
-    auth_session: AuthSession<MicrosoftBackend>,
+    auth_session: AuthSession<AuthBackend>,
     request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, AppError> {
     if auth_session.user.is_none() {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(AppError::Unauthorized);
     }
     Ok(next.run(request).await)
 }
+
+/// Rejects requests from users below the role given as middleware state:
+/// `middleware::from_fn_with_state(Role::Moderator, require_role)`.
+pub async fn require_role(
+    State(min_role): State<Role>,
+    auth_session: AuthSession<AuthBackend>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    match &auth_session.user {
+        None => Err(AppError::Unauthorized),
+        Some(user) if user.role < min_role => Err(AppError::Forbidden("insufficient role".into())),
+        Some(_) => Ok(next.run(request).await),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Request ids & error bodies
+// ---------------------------------------------------------------------------
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Largest non-JSON error body that is carried over as the message.
+const MAX_ERROR_TEXT: usize = 1024;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, if called from within [`request_id`].
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Tags every request with an id — the caller's `x-request-id` if it looks
+/// sane, otherwise a fresh UUID — that is echoed in the response header and
+/// in error bodies.
+///
+/// Error responses that didn't come from [`AppError`] (extractor rejections,
+/// body limits, bare status codes) are rewritten into the same JSON shape.
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| {
+            (1..=128).contains(&v.len())
+                && v.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+        })
+        .map(String::from)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let mut response = REQUEST_ID
+        .scope(id.clone(), async move { json_error_body(next.run(request).await).await })
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(X_REQUEST_ID, value);
+    }
+    response
+}
+
+async fn json_error_body(response: Response) -> Response {
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if !(status.is_client_error() || status.is_server_error()) || is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    // Rejection texts describe what was wrong with the request; anything
+    // from a server error stays out of the body.
+    let text = body::to_bytes(body, MAX_ERROR_TEXT)
+        .await
+        .ok()
+        .and_then(|b| String::from_utf8(b.to_vec()).ok())
+        .filter(|t| !t.trim().is_empty());
+    let message = match text {
+        Some(text) if status.is_client_error() => text,
+        _ => status.canonical_reason().unwrap_or("error").to_ascii_lowercase(),
+    };
+
+    let json = Json(ErrorBody::for_status(status, message)).into_response();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Response::from_parts(parts, Body::new(json.into_body()))
+}
@@ -1,6 +1,20 @@
 use axum_login::AuthUser;
 use serde::{Deserialize, Serialize};
 
+/// Access level of an account. Ordered so `role >= Role::Moderator` means
+/// "moderator or above".
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
     pub id: i64,
@@ -8,9 +22,6 @@ pub struct User {
     pub email: Option<String>,
     pub display_name: Option<String>,
     pub tenant_id: Option<String>,
-    pub access_token: Option<String>,
-    pub refresh_token: Option<String>,
-    pub id_token: Option<String>,
     // Profile fields
     pub full_name: Option<String>,
     pub age: Option<i64>,
@@ -21,11 +32,21 @@ pub struct User {
     pub image_key: Option<String>,
     pub created_at: String,
     pub updated_at: String,
+    // Moderation
+    pub role: Role,
+    pub suspended_until: Option<String>,
+    pub banned_at: Option<String>,
+    /// Set when the identity provider rejected the refresh token, e.g. the
+    /// account was removed from the tenant. Cleared by the next sign-in.
+    pub deactivated_at: Option<String>,
+    /// Session auth hash; rotating it invalidates every session of the user.
+    #[serde(skip_serializing)]
+    pub session_secret: String,
 }
 
 /// Public-facing user representation sent to the frontend.
 /// Deliberately omits all OAuth tokens.
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 pub struct UserResponse {
     pub id: i64,
     pub oid: String,
@@ -39,13 +60,18 @@ pub struct UserResponse {
     pub major: Option<String>,
     pub bio: Option<String>,
     pub image_key: Option<String>,
+    /// Photo gallery in display order; the primary photo is `image_key`.
+    pub photos: Vec<ProfilePhoto>,
     pub interests: Vec<String>,
+    /// Only on the caller's own profile; see [`UserResponse::own`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
     pub created_at: String,
     pub updated_at: String,
 }
 
 impl UserResponse {
-    pub fn from_user(u: User, interests: Vec<String>) -> Self {
+    pub fn from_user(u: User, interests: Vec<String>, photos: Vec<ProfilePhoto>) -> Self {
         Self {
             id: u.id,
             oid: u.oid,
@@ -58,11 +84,22 @@ impl UserResponse {
             major: u.major,
             bio: u.bio,
             image_key: u.image_key,
+            photos,
             interests,
+            role: None,
             created_at: u.created_at,
             updated_at: u.updated_at,
         }
     }
+
+    /// The profile as its owner sees it, including their role.
+    pub fn own(u: User, interests: Vec<String>, photos: Vec<ProfilePhoto>) -> Self {
+        let role = u.role;
+        Self {
+            role: Some(role),
+            ..Self::from_user(u, interests, photos)
+        }
+    }
 }
 
 impl AuthUser for User {
@@ -73,23 +110,61 @@ impl AuthUser for User {
     }
 
     fn session_auth_hash(&self) -> &[u8] {
-        match &self.access_token {
-            Some(token) => token.as_bytes(),
-            None => &[],
-        }
+        self.session_secret.as_bytes()
     }
 }
 
-/// Claims decoded from the Microsoft id_token (JWT middle segment).
+/// Claims from a verified id_token. `aud`, `exp` and `nbf` are checked during
+/// signature validation and not kept here.
 #[derive(Debug, Deserialize)]
 pub struct IdTokenClaims {
-    /// Object ID — stable per-user identifier in AAD
-    pub oid: String,
+    /// Subject — stable per-user identifier at the provider
+    pub sub: String,
+    /// Object ID — stable per-user identifier in AAD (Microsoft only)
+    pub oid: Option<String>,
     pub email: Option<String>,
+    /// Whether the provider verified `email` (not sent by Microsoft)
+    pub email_verified: Option<bool>,
+    /// Microsoft optional claim: the domain of `email` is verified by the
+    /// user's tenant. Sent only when configured on the app registration.
+    pub xms_edov: Option<bool>,
+    /// UPN / sign-in name; used when `email` is absent
+    pub preferred_username: Option<String>,
     /// Display name
     pub name: Option<String>,
-    /// Tenant ID
+    /// Tenant ID (Microsoft only)
     pub tid: Option<String>,
+    /// Issuer, e.g. `https://login.microsoftonline.com/{tid}/v2.0`
+    pub iss: String,
+    /// Echo of the nonce sent on the authorize request
+    pub nonce: Option<String>,
+}
+
+// ---------------------------------------------------------------------------
+// Profile photos
+// ---------------------------------------------------------------------------
+
+/// One photo of a user's gallery (see [`crate::photos`]).
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ProfilePhoto {
+    pub id: i64,
+    pub user_id: i64,
+    pub image_key: String,
+    #[serde(skip)]
+    pub content_type: Option<String>,
+    /// Gallery order, counting from 0.
+    pub position: i64,
+    /// Whether this is the user's avatar, mirrored to `User::image_key`.
+    pub is_primary: bool,
+    pub caption: Option<String>,
+    pub created_at: String,
+}
+
+/// Payload of `PUT /user/photos/order`: every photo id of the user, in the
+/// new order.
+#[derive(Debug, Deserialize)]
+pub struct PhotoOrder {
+    pub ids: Vec<i64>,
 }
 
 // ---------------------------------------------------------------------------
@@ -135,6 +210,8 @@ pub struct Message {
     pub content: String,
     pub image_key: Option<String>,
     pub created_at: String,
+    /// Set once the recipient has read the message (see `/ws` read receipts).
+    pub read_at: Option<String>,
 }
 
 // ---------------------------------------------------------------------------
@@ -159,3 +236,51 @@ pub struct NewLike {
     pub liked_id: i64,
     pub is_like: bool,
 }
+
+// ---------------------------------------------------------------------------
+// Reports
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum ReportReason {
+    Harassment,
+    Spam,
+    InappropriateContent,
+    FakeProfile,
+    Underage,
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum ReportStatus {
+    Open,
+    Resolved,
+    Dismissed,
+}
+
+/// A user report awaiting (or finished) moderator review.
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Report {
+    pub id: i64,
+    pub reporter_id: i64,
+    pub reported_id: i64,
+    pub reason: ReportReason,
+    pub details: Option<String>,
+    pub status: ReportStatus,
+    pub created_at: String,
+    pub resolved_at: Option<String>,
+}
+
+/// Payload used when a user reports another user.
+#[derive(Debug, Deserialize)]
+pub struct NewReport {
+    pub reason: ReportReason,
+    pub details: Option<String>,
+    /// Messages from the conversation to attach as evidence.
+    #[serde(default)]
+    pub message_ids: Vec<i64>,
+}
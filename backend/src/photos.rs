@@ -0,0 +1,301 @@
+//! Profile photo galleries.
+//!
+//! A user has up to [`ProfileRules::max_photos`](crate::validation::ProfileRules)
+//! rows in `profile_photos`, with positions `0..n` in display order. While
+//! any exist exactly one is primary, and its key and type are mirrored to
+//! `users.image_key` / `image_content_type`, which avatars and
+//! `GET /profiles/:id/image` read. Changes go through the functions here,
+//! inside a transaction, to keep both true.
+//!
+//! Photos that are replaced or removed are retired (see
+//! [`retire_image`]) rather than deleted from the store.
+
+use std::collections::HashMap;
+
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::{
+    error::{AppError, FieldError},
+    images::retire_image,
+    models::ProfilePhoto,
+};
+
+/// The gallery of `user_id`, in display order.
+pub async fn for_user(pool: &SqlitePool, user_id: i64) -> Result<Vec<ProfilePhoto>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM profile_photos WHERE user_id = ?1 ORDER BY position")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Galleries for a slice of user IDs in a single query, returning a map of
+/// user_id → photos in display order. Users without photos are absent.
+pub async fn for_users(
+    pool: &SqlitePool,
+    user_ids: &[i64],
+) -> Result<HashMap<i64, Vec<ProfilePhoto>>, sqlx::Error> {
+    if user_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let placeholders: String = (1..=user_ids.len())
+        .map(|i| format!("?{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!(
+        "SELECT * FROM profile_photos WHERE user_id IN ({placeholders}) ORDER BY user_id, position"
+    );
+
+    let mut q = sqlx::query_as::<_, ProfilePhoto>(&sql);
+    for id in user_ids {
+        q = q.bind(id);
+    }
+
+    let mut map: HashMap<i64, Vec<ProfilePhoto>> = HashMap::new();
+    for photo in q.fetch_all(pool).await? {
+        map.entry(photo.user_id).or_default().push(photo);
+    }
+    Ok(map)
+}
+
+/// Append a photo to the end of the gallery; the first photo becomes
+/// primary. Fails with a validation error on `image` when the gallery
+/// already has `max_photos`.
+pub async fn add(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    max_photos: usize,
+    key: &str,
+    content_type: &str,
+    caption: Option<&str>,
+) -> Result<(), AppError> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM profile_photos WHERE user_id = ?1")
+        .bind(user_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    if count as usize >= max_photos {
+        return Err(AppError::Validation(vec![FieldError::new(
+            "image",
+            format!("at most {max_photos} photos are allowed"),
+        )]));
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO profile_photos (user_id, image_key, content_type, position, is_primary, caption)
+        VALUES (?1, ?2, ?3, ?4, ?4 = 0, ?5)
+        "#,
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(content_type)
+    .bind(count)
+    .bind(caption)
+    .execute(&mut *conn)
+    .await?;
+
+    sync_primary(conn, user_id).await
+}
+
+/// Swap the image of the primary photo for `key`, keeping its position and
+/// caption, or add it as the first photo of an empty gallery.
+pub async fn replace_primary(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    key: &str,
+    content_type: &str,
+) -> Result<(), AppError> {
+    let primary: Option<(i64, String)> = sqlx::query_as(
+        "SELECT id, image_key FROM profile_photos WHERE user_id = ?1 AND is_primary",
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match primary {
+        Some((id, previous)) => {
+            if previous != key {
+                retire_image(conn, user_id, &previous).await?;
+            }
+            sqlx::query(
+                "UPDATE profile_photos SET image_key = ?1, content_type = ?2 WHERE id = ?3",
+            )
+            .bind(key)
+            .bind(content_type)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+        }
+        None => {
+            sqlx::query(
+                r#"
+                INSERT INTO profile_photos (user_id, image_key, content_type, position, is_primary)
+                VALUES (?1, ?2, ?3, 0, 1)
+                "#,
+            )
+            .bind(user_id)
+            .bind(key)
+            .bind(content_type)
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    sync_primary(conn, user_id).await
+}
+
+/// Remove photo `photo_id` of `user_id` and close the gap it leaves. If it
+/// was primary, the new first photo takes over. Returns the removed photo.
+pub async fn remove(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    photo_id: i64,
+) -> Result<ProfilePhoto, AppError> {
+    let photo = find(conn, user_id, photo_id).await?;
+
+    sqlx::query("DELETE FROM profile_photos WHERE id = ?1")
+        .bind(photo.id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        "UPDATE profile_photos SET position = position - 1 WHERE user_id = ?1 AND position > ?2",
+    )
+    .bind(user_id)
+    .bind(photo.position)
+    .execute(&mut *conn)
+    .await?;
+    if photo.is_primary {
+        sqlx::query(
+            r#"
+            UPDATE profile_photos SET is_primary = 1
+            WHERE id = (SELECT id FROM profile_photos WHERE user_id = ?1 ORDER BY position LIMIT 1)
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+    retire_image(conn, user_id, &photo.image_key).await?;
+
+    sync_primary(conn, user_id).await?;
+    Ok(photo)
+}
+
+/// Remove the primary photo of `user_id`; see [`remove`]. 404 if the user
+/// has no photos.
+pub async fn remove_primary(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+) -> Result<ProfilePhoto, AppError> {
+    let photo_id: Option<i64> =
+        sqlx::query_scalar("SELECT id FROM profile_photos WHERE user_id = ?1 AND is_primary")
+            .bind(user_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+    let photo_id = photo_id.ok_or(AppError::NotFound("profile photo"))?;
+    remove(conn, user_id, photo_id).await
+}
+
+/// Make photo `photo_id` the primary one.
+pub async fn set_primary(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    photo_id: i64,
+) -> Result<(), AppError> {
+    find(conn, user_id, photo_id).await?;
+
+    // Two statements: the unique index on the primary flag is checked row by
+    // row, so the old primary has to be cleared first.
+    sqlx::query("UPDATE profile_photos SET is_primary = 0 WHERE user_id = ?1 AND is_primary")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE profile_photos SET is_primary = 1 WHERE id = ?1")
+        .bind(photo_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sync_primary(conn, user_id).await
+}
+
+/// Set or clear the caption of photo `photo_id`.
+pub async fn set_caption(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    photo_id: i64,
+    caption: Option<&str>,
+) -> Result<(), AppError> {
+    find(conn, user_id, photo_id).await?;
+
+    sqlx::query("UPDATE profile_photos SET caption = ?1 WHERE id = ?2")
+        .bind(caption)
+        .bind(photo_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sync_primary(conn, user_id).await
+}
+
+/// Put the gallery in the order of `ids`, which must list every photo of
+/// `user_id` exactly once.
+pub async fn reorder(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    ids: &[i64],
+) -> Result<(), AppError> {
+    let mut current: Vec<i64> =
+        sqlx::query_scalar("SELECT id FROM profile_photos WHERE user_id = ?1")
+            .bind(user_id)
+            .fetch_all(&mut *conn)
+            .await?;
+    let mut requested = ids.to_vec();
+    current.sort_unstable();
+    requested.sort_unstable();
+    if current != requested {
+        return Err(AppError::Validation(vec![FieldError::new(
+            "ids",
+            "must list each of your photos exactly once",
+        )]));
+    }
+
+    for (position, id) in ids.iter().enumerate() {
+        sqlx::query("UPDATE profile_photos SET position = ?1 WHERE id = ?2")
+            .bind(position as i64)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    sync_primary(conn, user_id).await
+}
+
+/// Photo `photo_id` if it belongs to `user_id`.
+async fn find(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    photo_id: i64,
+) -> Result<ProfilePhoto, AppError> {
+    sqlx::query_as("SELECT * FROM profile_photos WHERE id = ?1 AND user_id = ?2")
+        .bind(photo_id)
+        .bind(user_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::NotFound("photo"))
+}
+
+/// Mirror the primary photo to the user row and bump `updated_at`.
+async fn sync_primary(conn: &mut SqliteConnection, user_id: i64) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET image_key          = (SELECT image_key FROM profile_photos WHERE user_id = ?1 AND is_primary),
+            image_content_type = (SELECT content_type FROM profile_photos WHERE user_id = ?1 AND is_primary),
+            updated_at         = datetime('now')
+        WHERE id = ?1
+        "#,
+    )
+    .bind(user_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::sync::broadcast;
+
+use crate::models::{Match, Message, UserResponse};
+
+/// Buffered events per user before slow connections start lagging.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Events pushed to a user's open `/ws` and `/events` connections.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// A message was sent to (or by) this user.
+    Message { message: Message },
+    /// This user has a new match.
+    Match {
+        #[serde(rename = "match")]
+        new_match: Match,
+    },
+    /// Match `match_id` with `user_id` was undone by either side.
+    Unmatched { match_id: i64, user_id: i64 },
+    /// This user, or one of their matches, updated their profile.
+    ProfileUpdated { profile: UserResponse },
+    /// `user_id` is typing in their conversation with this user.
+    Typing { user_id: i64 },
+    /// `user_id` has read every message from this user up to `up_to`.
+    Read { user_id: i64, up_to: i64 },
+}
+
+impl ServerEvent {
+    /// SSE event name for durable events, or `None` for ephemeral ones
+    /// (typing, read receipts) which are never written to the event log.
+    pub fn kind(&self) -> Option<&'static str> {
+        match self {
+            ServerEvent::Message { .. } => Some("message.created"),
+            ServerEvent::Match { .. } => Some("match.created"),
+            ServerEvent::Unmatched { .. } => Some("match.deleted"),
+            ServerEvent::ProfileUpdated { .. } => Some("profile.updated"),
+            ServerEvent::Typing { .. } | ServerEvent::Read { .. } => None,
+        }
+    }
+}
+
+/// An event as seen by subscribers. `seq` is the recipient's sequence
+/// number for durable events (see [`Hub::publish`]) and `None` for
+/// ephemeral ones.
+#[derive(Clone, Debug)]
+pub struct Delivery {
+    pub seq: Option<i64>,
+    pub event: ServerEvent,
+}
+
+/// Row from the `events` log.
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct StoredEvent {
+    pub seq: i64,
+    pub kind: String,
+    pub payload: String,
+}
+
+/// In-process broadcast hub keyed by user id.
+///
+/// Each user gets one `broadcast` channel shared by all of their open
+/// connections (multiple tabs/devices). Channels are created lazily on
+/// subscribe and dropped once the last receiver goes away.
+#[derive(Clone, Default)]
+pub struct Hub {
+    channels: Arc<Mutex<HashMap<i64, broadcast::Sender<Delivery>>>>,
+}
+
+impl Hub {
+    pub fn subscribe(&self, user_id: i64) -> broadcast::Receiver<Delivery> {
+        let mut channels = self.channels.lock().unwrap();
+        channels
+            .entry(user_id)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Push an ephemeral event to every open connection of `user_id`.
+    /// Silently does nothing if the user has no connections.
+    pub fn send(&self, user_id: i64, event: ServerEvent) {
+        self.deliver(user_id, Delivery { seq: None, event });
+    }
+
+    /// Record a durable event in the `events` log for `user_id`, numbered by
+    /// their next sequence number, then push it to their open connections.
+    /// Ephemeral events are only pushed.
+    pub async fn publish(
+        &self,
+        pool: &SqlitePool,
+        user_id: i64,
+        event: ServerEvent,
+    ) -> Result<(), sqlx::Error> {
+        let Some(kind) = event.kind() else {
+            self.send(user_id, event);
+            return Ok(());
+        };
+        let payload = serde_json::to_string(&event)
+            .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+
+        let mut tx = pool.begin().await?;
+        let seq: i64 = sqlx::query_scalar(
+            "UPDATE users SET event_seq = event_seq + 1 WHERE id = ?1 RETURNING event_seq",
+        )
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query("INSERT INTO events (user_id, seq, kind, payload) VALUES (?1, ?2, ?3, ?4)")
+            .bind(user_id)
+            .bind(seq)
+            .bind(kind)
+            .bind(&payload)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        self.deliver(user_id, Delivery { seq: Some(seq), event });
+        Ok(())
+    }
+
+    fn deliver(&self, user_id: i64, delivery: Delivery) {
+        let channels = self.channels.lock().unwrap();
+        if let Some(tx) = channels.get(&user_id) {
+            let _ = tx.send(delivery);
+        }
+    }
+
+    /// Drop the user's channel if no connections remain. Called when a
+    /// connection closes so the map doesn't grow without bound.
+    pub fn prune(&self, user_id: i64) {
+        let mut channels = self.channels.lock().unwrap();
+        if channels
+            .get(&user_id)
+            .is_some_and(|tx| tx.receiver_count() == 0)
+        {
+            channels.remove(&user_id);
+        }
+    }
+}
+
+/// Durable events for `user_id` with a sequence number greater than `after`,
+/// oldest first.
+pub async fn events_since(
+    pool: &SqlitePool,
+    user_id: i64,
+    after: i64,
+) -> Result<Vec<StoredEvent>, sqlx::Error> {
+    sqlx::query_as::<_, StoredEvent>(
+        "SELECT seq, kind, payload FROM events WHERE user_id = ?1 AND seq > ?2 ORDER BY seq ASC",
+    )
+    .bind(user_id)
+    .bind(after)
+    .fetch_all(pool)
+    .await
+}
+
+/// Delete logged events older than `max_age_days`. Clients offline for longer
+/// than this resume from the live stream and refetch state over REST.
+pub async fn prune_events(pool: &SqlitePool, max_age_days: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM events WHERE created_at < datetime('now', ?1)")
+        .bind(format!("-{max_age_days} days"))
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,157 @@
+use axum::{
+    Router,
+    extract::DefaultBodyLimit,
+    http::{
+        Method,
+        header::{
+            ACCEPT, ACCEPT_CHARSET, ACCEPT_ENCODING, ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            CONTENT_ENCODING, CONTENT_TYPE, ORIGIN, REFERER, SET_COOKIE,
+        },
+    },
+    middleware,
+    routing::{delete, get, patch, post, put},
+};
+use axum_login::AuthManagerLayerBuilder;
+use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
+use tower_sessions::{Expiry, SessionManagerLayer, cookie::time::Duration};
+
+use crate::{
+    AppState,
+    api::{
+        admin,
+        blocks::block_user,
+        events::event_stream,
+        likes::submit_like,
+        matches::{delete_match, get_matches},
+        messages::{get_message_image, get_messages, send_message},
+        profiles::{compatible_profiles, get_profile, get_profile_image, get_profile_photo_image},
+        reports::report_user,
+        user::{
+            add_photo, delete_photo, delete_profile_photo, me, patch_profile, put_profile_photo,
+            reorder_photos, update_photo, update_profile,
+        },
+        ws::ws_handler,
+    },
+    auth::{
+        dev::dev_login,
+        routes::{callback, login, login_with, logout, logout_all, providers},
+    },
+    config::Config,
+    middleware::{X_REQUEST_ID, request_id, require_role, require_user},
+    models::Role,
+    session_store::SqliteSessionStore,
+};
+
+/// The complete HTTP application: API, auth and session layers, CORS and the
+/// static frontend.
+///
+/// Only builds the router — background jobs (session cleanup, token refresh,
+/// event pruning) are started by the binary, so tests can serve the app
+/// without them, e.g. through `tower::ServiceExt::oneshot`.
+pub fn app(state: AppState, config: &Config) -> Router {
+    // Session layer — persisted in SQLite. Sessions expire after the idle
+    // timeout without activity, and unconditionally after the absolute one.
+    let session_store = SqliteSessionStore::new(
+        state.pool.clone(),
+        Duration::hours(config.session_absolute_timeout_hours),
+    );
+    let session_layer = SessionManagerLayer::new(session_store)
+        .with_same_site(tower_sessions::cookie::SameSite::Lax)
+        .with_expiry(Expiry::OnInactivity(Duration::hours(config.session_idle_timeout_hours)));
+
+    let auth_layer = AuthManagerLayerBuilder::new(state.backend.clone(), session_layer).build();
+
+    // CORS — must allow credentials so the browser sends the session cookie
+    // on cross-origin requests from the frontend dev server.
+    let cors = CorsLayer::new()
+        .allow_origin(config.cors_origins.clone())
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers([
+            ACCEPT_ENCODING,
+            CONTENT_ENCODING,
+            REFERER,
+            ORIGIN,
+            ACCEPT,
+            CONTENT_TYPE,
+            ACCEPT_CHARSET,
+            ACCESS_CONTROL_ALLOW_CREDENTIALS,
+        ])
+        .expose_headers([SET_COOKIE, CONTENT_ENCODING, ACCEPT_ENCODING, X_REQUEST_ID])
+        .allow_credentials(true);
+
+    let upload_limit = || DefaultBodyLimit::max(config.max_upload_bytes);
+    let protected = Router::new()
+        .route("/user/me", get(me))
+        .route(
+            "/user/profile",
+            post(update_profile).layer(upload_limit()).patch(patch_profile),
+        )
+        .route(
+            "/user/profile/photo",
+            put(put_profile_photo).layer(upload_limit()).delete(delete_profile_photo),
+        )
+        .route("/user/photos", post(add_photo).layer(upload_limit()))
+        .route("/user/photos/order", put(reorder_photos))
+        .route("/user/photos/{id}", patch(update_photo).delete(delete_photo))
+        .route("/like", post(submit_like))
+        .route("/matches", get(get_matches))
+        .route("/matches/{id}", delete(delete_match))
+        .route("/message", post(send_message).layer(upload_limit()))
+        .route("/messages/{user_id}", get(get_messages))
+        .route("/messages/{message_id}/image", get(get_message_image))
+        // static segment must be declared before the dynamic :id capture
+        .route("/profiles/compatible", get(compatible_profiles))
+        .route("/profiles/{id}", get(get_profile))
+        .route("/profiles/{id}/image", get(get_profile_image))
+        .route("/profiles/{id}/photos/{photo_id}/image", get(get_profile_photo_image))
+        .route("/users/{id}/block", post(block_user))
+        .route("/users/{id}/report", post(report_user))
+        .route("/ws", get(ws_handler))
+        .route("/events", get(event_stream))
+        .layer(middleware::from_fn(require_user));
+
+    // Moderation API. Everything requires at least the moderator role; bans
+    // and role changes additionally require admin.
+    let admin_only = || middleware::from_fn_with_state(Role::Admin, require_role);
+    let admin = Router::new()
+        .route("/admin/reports", get(admin::list_reports))
+        .route("/admin/reports/{id}", get(admin::get_report))
+        .route("/admin/reports/{id}/resolve", post(admin::resolve_report))
+        .route("/admin/messages/{id}/image", get(admin::get_message_image))
+        .route("/admin/users/{id}/suspend", post(admin::suspend_user))
+        .route("/admin/users/{id}/ban", post(admin::ban_user).layer(admin_only()))
+        .route("/admin/users/{id}/reinstate", post(admin::reinstate_user))
+        .route("/admin/users/{id}/role", put(admin::set_role).layer(admin_only()))
+        .route("/admin/users/{id}/photo", delete(admin::remove_profile_photo))
+        .route("/admin/users/{id}/photos/{photo_id}", delete(admin::remove_photo))
+        .layer(middleware::from_fn_with_state(Role::Moderator, require_role));
+
+    let mut auth = Router::new()
+        .route("/auth/providers", get(providers))
+        .route("/auth/login", get(login))
+        .route("/auth/login/{provider}", get(login_with))
+        .route("/auth/callback", get(callback))
+        .route("/auth/logout", get(logout))
+        .route("/auth/logout-all", post(logout_all));
+    if config.dev_login {
+        auth = auth.route("/auth/dev-login", get(dev_login));
+    }
+
+    Router::new()
+        .merge(auth)
+        .merge(protected)
+        .merge(admin)
+        .layer(auth_layer)
+        .layer(cors)
+        .layer(middleware::from_fn(request_id))
+        .layer(TraceLayer::new_for_http())
+        .with_state(state)
+        .fallback_service(ServeDir::new(&config.frontend_dir).append_index_html_on_directories(true))
+}
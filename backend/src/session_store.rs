@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+use tower_sessions::{
+    cookie::time::{Duration, OffsetDateTime},
+    session::{Id, Record},
+    session_store::{self, ExpiredDeletion, SessionStore},
+};
+
+/// `tower_sessions` store backed by the `sessions` table, so logins and
+/// in-flight OAuth state survive restarts and deploys.
+///
+/// Idle expiry is handled by tower-sessions itself (`Record::expiry_date`,
+/// pushed forward whenever the session is saved). On top of that every
+/// session has a hard lifetime counted from when it was created, after which
+/// it is treated as missing regardless of activity.
+#[derive(Clone, Debug)]
+pub struct SqliteSessionStore {
+    pool: SqlitePool,
+    absolute_timeout: Duration,
+}
+
+impl SqliteSessionStore {
+    pub fn new(pool: SqlitePool, absolute_timeout: Duration) -> Self {
+        Self {
+            pool,
+            absolute_timeout,
+        }
+    }
+
+    /// Oldest `created_at` (unix seconds) a live session may have.
+    fn created_cutoff(&self) -> i64 {
+        (OffsetDateTime::now_utc() - self.absolute_timeout).unix_timestamp()
+    }
+
+    /// Spawn a task that deletes expired sessions every `period`.
+    pub fn spawn_cleanup(&self, period: std::time::Duration) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = store.delete_expired().await {
+                    tracing::warn!(error = %e, "failed to delete expired sessions");
+                }
+            }
+        });
+    }
+}
+
+fn backend_err(e: sqlx::Error) -> session_store::Error {
+    session_store::Error::Backend(e.to_string())
+}
+
+fn encode(record: &Record) -> session_store::Result<String> {
+    serde_json::to_string(&record.data).map_err(|e| session_store::Error::Encode(e.to_string()))
+}
+
+#[async_trait]
+impl SessionStore for SqliteSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        let data = encode(record)?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        // Regenerate the id on the (astronomically unlikely) collision.
+        loop {
+            let inserted = sqlx::query(
+                r#"
+                INSERT INTO sessions (id, data, expiry_date, created_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT(id) DO NOTHING
+                "#,
+            )
+            .bind(record.id.to_string())
+            .bind(&data)
+            .bind(record.expiry_date.unix_timestamp())
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(backend_err)?;
+
+            if inserted.rows_affected() == 1 {
+                return Ok(());
+            }
+            record.id = Id::default();
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO sessions (id, data, expiry_date, created_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(id) DO UPDATE SET
+                data        = excluded.data,
+                expiry_date = excluded.expiry_date
+            "#,
+        )
+        .bind(record.id.to_string())
+        .bind(encode(record)?)
+        .bind(record.expiry_date.unix_timestamp())
+        .bind(OffsetDateTime::now_utc().unix_timestamp())
+        .execute(&self.pool)
+        .await
+        .map_err(backend_err)?;
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let row: Option<(String, i64)> = sqlx::query_as(
+            r#"
+            SELECT data, expiry_date FROM sessions
+            WHERE id = ?1 AND expiry_date > ?2 AND created_at > ?3
+            "#,
+        )
+        .bind(session_id.to_string())
+        .bind(OffsetDateTime::now_utc().unix_timestamp())
+        .bind(self.created_cutoff())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(backend_err)?;
+
+        let Some((data, expiry_date)) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(Record {
+            id: *session_id,
+            data: serde_json::from_str(&data)
+                .map_err(|e| session_store::Error::Decode(e.to_string()))?,
+            expiry_date: OffsetDateTime::from_unix_timestamp(expiry_date)
+                .map_err(|e| session_store::Error::Decode(e.to_string()))?,
+        }))
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        sqlx::query("DELETE FROM sessions WHERE id = ?1")
+            .bind(session_id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(backend_err)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for SqliteSessionStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        let removed = sqlx::query("DELETE FROM sessions WHERE expiry_date <= ?1 OR created_at <= ?2")
+            .bind(OffsetDateTime::now_utc().unix_timestamp())
+            .bind(self.created_cutoff())
+            .execute(&self.pool)
+            .await
+            .map_err(backend_err)?
+            .rows_affected();
+
+        if removed > 0 {
+            tracing::debug!(removed, "deleted expired sessions");
+        }
+        Ok(())
+    }
+}
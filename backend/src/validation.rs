@@ -0,0 +1,303 @@
+//! Rules for user-editable profile fields.
+//!
+//! Each check returns the normalised value or a message for that field, so
+//! endpoints can collect every problem into one
+//! [`AppError::Validation`](crate::error::AppError::Validation).
+
+use serde::{Deserialize, Deserializer};
+
+use crate::error::{AppError, FieldError};
+
+/// Youngest age a profile may have; configuration can only raise it.
+pub const ADULT_AGE: i64 = 18;
+
+/// Limits for profile fields, from [`Config::profile`](crate::config::Config).
+#[derive(Clone, Debug)]
+pub struct ProfileRules {
+    pub min_age: i64,
+    pub max_age: i64,
+    pub max_full_name_chars: usize,
+    pub max_bio_chars: usize,
+    /// Allowed majors. Empty accepts free text up to `max_major_chars`.
+    pub majors: Vec<String>,
+    pub max_major_chars: usize,
+    pub max_interests: usize,
+    pub max_interest_chars: usize,
+    pub max_photos: usize,
+    pub max_caption_chars: usize,
+}
+
+impl Default for ProfileRules {
+    fn default() -> Self {
+        Self {
+            min_age: ADULT_AGE,
+            max_age: 99,
+            max_full_name_chars: 100,
+            max_bio_chars: 500,
+            majors: Vec::new(),
+            max_major_chars: 80,
+            max_interests: 10,
+            max_interest_chars: 30,
+            max_photos: 6,
+            max_caption_chars: 200,
+        }
+    }
+}
+
+/// Profile fields from the multipart form; `None` leaves a field unchanged.
+#[derive(Debug, Default)]
+pub struct ProfileInput {
+    pub bio: Option<String>,
+    pub major: Option<String>,
+    /// Unparsed, as multipart sends it. Blank means "not provided".
+    pub age: Option<String>,
+    pub interests: Option<Vec<String>>,
+}
+
+/// JSON body of `PATCH /user/profile`. A missing field is left unchanged and
+/// an explicit `null` clears it (`interests: null` removes them all).
+/// `is_rso` can only be set to `true` or `false`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfilePatch {
+    #[serde(default, deserialize_with = "nullable")]
+    pub full_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub bio: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub major: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub age: Option<Option<i64>>,
+    pub is_rso: Option<bool>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub interests: Option<Option<Vec<String>>>,
+}
+
+/// JSON body of `PATCH /user/photos/:id`. `caption: null` clears the
+/// caption. `is_primary` can only be set to `true`: clearing it would leave
+/// the gallery without a primary photo.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhotoPatch {
+    #[serde(default, deserialize_with = "nullable")]
+    pub caption: Option<Option<String>>,
+    pub is_primary: Option<bool>,
+}
+
+/// Tells an explicit `null` (`Some(None)`) apart from a missing field, which
+/// `#[serde(default)]` turns into `None`.
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Validated, normalised profile changes. `None` leaves a field unchanged,
+/// `Some(None)` clears it; blank text counts as clearing.
+#[derive(Debug, Default)]
+pub struct ProfileUpdate {
+    pub full_name: Option<Option<String>>,
+    pub bio: Option<Option<String>>,
+    pub major: Option<Option<String>>,
+    pub age: Option<Option<i64>>,
+    pub is_rso: Option<bool>,
+    pub interests: Option<Vec<String>>,
+}
+
+/// Validated photo changes; `caption` as in [`ProfileUpdate`].
+#[derive(Debug, Default)]
+pub struct PhotoUpdate {
+    pub caption: Option<Option<String>>,
+    pub make_primary: bool,
+}
+
+impl ProfileRules {
+    /// Check every provided form field, failing with all field errors at once.
+    pub fn validate(&self, input: ProfileInput) -> Result<ProfileUpdate, AppError> {
+        let mut errors = Vec::new();
+        let age = input
+            .age
+            .filter(|a| !a.trim().is_empty())
+            .and_then(|a| check(&mut errors, "age", parse_age(&a)));
+        let patch = ProfilePatch {
+            bio: input.bio.map(Some),
+            major: input.major.map(Some),
+            age: age.map(Some),
+            interests: input.interests.map(Some),
+            ..Default::default()
+        };
+        self.check_patch(patch, errors)
+    }
+
+    /// Check every provided `PATCH` field, failing with all field errors at
+    /// once.
+    pub fn validate_patch(&self, patch: ProfilePatch) -> Result<ProfileUpdate, AppError> {
+        self.check_patch(patch, Vec::new())
+    }
+
+    fn check_patch(
+        &self,
+        patch: ProfilePatch,
+        mut errors: Vec<FieldError>,
+    ) -> Result<ProfileUpdate, AppError> {
+        let e = &mut errors;
+        let update = ProfileUpdate {
+            full_name: text(e, "full_name", patch.full_name, |v| self.full_name(v)),
+            bio: text(e, "bio", patch.bio, |v| self.bio(v)),
+            major: text(e, "major", patch.major, |v| self.major(v)),
+            age: patch
+                .age
+                .map(|v| v.and_then(|a| check(e, "age", self.age(a)))),
+            is_rso: patch.is_rso,
+            interests: patch.interests.map(|v| {
+                check(e, "interests", self.interests(v.unwrap_or_default())).unwrap_or_default()
+            }),
+        };
+
+        if errors.is_empty() {
+            Ok(update)
+        } else {
+            Err(AppError::Validation(errors))
+        }
+    }
+
+    /// Check a `PATCH /user/photos/:id` body, failing with all field errors
+    /// at once.
+    pub fn validate_photo_patch(&self, patch: PhotoPatch) -> Result<PhotoUpdate, AppError> {
+        let mut errors = Vec::new();
+        let e = &mut errors;
+        let update = PhotoUpdate {
+            caption: text(e, "caption", patch.caption, |v| self.caption(v)),
+            make_primary: match patch.is_primary {
+                Some(false) => {
+                    e.push(FieldError::new(
+                        "is_primary",
+                        "can only be set to true; make another photo primary instead",
+                    ));
+                    false
+                }
+                other => other.unwrap_or(false),
+            },
+        };
+
+        if errors.is_empty() {
+            Ok(update)
+        } else {
+            Err(AppError::Validation(errors))
+        }
+    }
+
+    pub fn full_name(&self, name: &str) -> Result<String, String> {
+        let name = collapse_whitespace(name);
+        if name.chars().count() > self.max_full_name_chars {
+            return Err(format!("must be at most {} characters", self.max_full_name_chars));
+        }
+        Ok(name)
+    }
+
+    pub fn bio(&self, bio: &str) -> Result<String, String> {
+        let bio = bio.trim();
+        if bio.chars().count() > self.max_bio_chars {
+            return Err(format!("must be at most {} characters", self.max_bio_chars));
+        }
+        Ok(bio.to_string())
+    }
+
+    /// With a configured list, the major must match an entry (ignoring case)
+    /// and is stored with the list's spelling.
+    pub fn major(&self, major: &str) -> Result<String, String> {
+        let major = collapse_whitespace(major);
+        if major.is_empty() {
+            return Ok(major);
+        }
+        if !self.majors.is_empty() {
+            let folded = major.to_lowercase();
+            return self
+                .majors
+                .iter()
+                .find(|m| m.to_lowercase() == folded)
+                .cloned()
+                .ok_or_else(|| "is not a recognised major".to_string());
+        }
+        if major.chars().count() > self.max_major_chars {
+            return Err(format!("must be at most {} characters", self.max_major_chars));
+        }
+        Ok(major)
+    }
+
+    pub fn caption(&self, caption: &str) -> Result<String, String> {
+        let caption = collapse_whitespace(caption);
+        if caption.chars().count() > self.max_caption_chars {
+            return Err(format!("must be at most {} characters", self.max_caption_chars));
+        }
+        Ok(caption)
+    }
+
+    pub fn age(&self, age: i64) -> Result<i64, String> {
+        if !(self.min_age..=self.max_age).contains(&age) {
+            return Err(format!("must be between {} and {}", self.min_age, self.max_age));
+        }
+        Ok(age)
+    }
+
+    /// Normalises names (see [`normalize_interest`]), drops blanks and
+    /// duplicates keeping the first occurrence, then checks the limits.
+    pub fn interests(&self, names: Vec<String>) -> Result<Vec<String>, String> {
+        let mut interests: Vec<String> = Vec::new();
+        for name in names.iter().map(|n| normalize_interest(n)) {
+            if name.is_empty() || interests.contains(&name) {
+                continue;
+            }
+            if name.chars().count() > self.max_interest_chars {
+                return Err(format!(
+                    "{name:?} is longer than {} characters",
+                    self.max_interest_chars
+                ));
+            }
+            interests.push(name);
+        }
+        if interests.len() > self.max_interests {
+            return Err(format!("at most {} interests are allowed", self.max_interests));
+        }
+        Ok(interests)
+    }
+}
+
+/// The value of a successful check; a failed one is recorded for `field`.
+pub fn check<T>(errors: &mut Vec<FieldError>, field: &str, result: Result<T, String>) -> Option<T> {
+    result
+        .map_err(|message| errors.push(FieldError::new(field, message)))
+        .ok()
+}
+
+/// A text field change: `Some(None)` clears; a value is checked, and clears
+/// the field when blank.
+fn text(
+    errors: &mut Vec<FieldError>,
+    field: &str,
+    value: Option<Option<String>>,
+    rule: impl Fn(&str) -> Result<String, String>,
+) -> Option<Option<String>> {
+    value.map(|v| {
+        v.and_then(|v| check(errors, field, rule(&v)))
+            .filter(|v| !v.is_empty())
+    })
+}
+
+fn parse_age(age: &str) -> Result<i64, String> {
+    age.trim()
+        .parse()
+        .map_err(|_| "must be a whole number".to_string())
+}
+
+/// Trim, collapse inner whitespace and case-fold, so `" Video  Games"` and
+/// `"video games"` are the same interest.
+pub fn normalize_interest(name: &str) -> String {
+    collapse_whitespace(name).to_lowercase()
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
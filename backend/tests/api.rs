@@ -0,0 +1,1141 @@
+mod support;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use object_store::{ObjectStoreExt, PutPayload, path::Path as StorePath};
+use serde_json::json;
+use backend::{db::normalize_interests, images::collect_garbage, realtime::ServerEvent};
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::BodyExt;
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream,
+    tungstenite::{Message as WsMessage, client::IntoClientRequest},
+};
+use support::{Api, body_bytes, body_json, jpeg, png, test_config};
+
+#[tokio::test]
+async fn protected_routes_require_a_session() {
+    let api = Api::new().await;
+    api.create_user("oid-alice", "Alice").await;
+
+    let response = api.get("/user/me", "").await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let cookie = api.sign_in("oid-alice").await;
+    let response = api.get("/user/me", &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let me = body_json(response).await;
+    assert_eq!(me["oid"], "oid-alice");
+    assert_eq!(me["display_name"], "Alice");
+    assert_eq!(me["role"], "user");
+}
+
+#[tokio::test]
+async fn dev_login_is_only_mounted_when_enabled() {
+    let mut config = test_config("");
+    config.dev_login = false;
+    let api = Api::from_config(config).await;
+    api.create_user("seed-oid-001", "Alice").await;
+    let response = api.get("/auth/dev-login?oid=seed-oid-001", "").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+
+    let api = Api::new().await;
+    let alice = api.create_user("seed-oid-001", "Alice").await;
+    let response = api.get("/auth/dev-login?oid=seed-oid-unknown", "").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = api.get("/auth/dev-login?oid=seed-oid-001&next=/matches", "").await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let location = response.headers()[header::LOCATION].to_str().unwrap();
+    assert_eq!(location, format!("{}/matches", api.state.config.frontend_url));
+    let cookie = response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .find_map(|v| v.to_str().unwrap().split(';').next().filter(|c| c.starts_with("id=")))
+        .expect("dev login sets a session cookie")
+        .to_string();
+    let me = body_json(api.get("/user/me", &cookie).await).await;
+    assert_eq!(me["id"], alice);
+}
+
+#[tokio::test]
+async fn mutual_likes_create_a_match() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let bob = api.create_user("oid-bob", "Bob").await;
+    let alice_cookie = api.sign_in("oid-alice").await;
+    let bob_cookie = api.sign_in("oid-bob").await;
+
+    let response = api
+        .post_json("/like", &alice_cookie, json!({ "liked_id": bob, "is_like": true }))
+        .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = api
+        .post_json("/like", &bob_cookie, json!({ "liked_id": alice, "is_like": true }))
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let new_match = body_json(response).await;
+    assert_eq!(new_match["user1_id"], alice);
+    assert_eq!(new_match["user2_id"], bob);
+
+    let matches = body_json(api.get("/matches", &alice_cookie).await).await;
+    assert_eq!(matches.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn banned_and_suspended_users_are_hidden() {
+    let api = Api::new().await;
+    api.create_user("oid-alice", "Alice").await;
+    let bob = api.create_user("oid-bob", "Bob").await;
+    let carol = api.create_user("oid-carol", "Carol").await;
+    let dave = api.create_user("oid-dave", "Dave").await;
+    let cookie = api.sign_in("oid-alice").await;
+
+    for (sql, id) in [
+        ("UPDATE users SET banned_at = datetime('now') WHERE id = ?1", bob),
+        ("UPDATE users SET suspended_until = datetime('now', '+1 day') WHERE id = ?1", carol),
+        // A suspension that has run out.
+        ("UPDATE users SET suspended_until = datetime('now', '-1 day') WHERE id = ?1", dave),
+    ] {
+        sqlx::query(sql).bind(id).execute(api.pool()).await.unwrap();
+    }
+
+    let feed = body_json(api.get("/profiles/compatible", &cookie).await).await;
+    let ids: Vec<i64> = feed
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(ids, [dave]);
+
+    for id in [bob, carol] {
+        let response = api.get(&format!("/profiles/{id}"), &cookie).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+    let response = api.get(&format!("/profiles/{dave}"), &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn moderation_requires_a_role_above_the_target() {
+    let api = Api::new().await;
+    let user = api.create_user("oid-alice", "Alice").await;
+    let moderator = api.create_user("oid-mod", "Mod").await;
+    let admin = api.create_user("oid-admin", "Admin").await;
+    for (id, role) in [(moderator, "moderator"), (admin, "admin")] {
+        sqlx::query("UPDATE users SET role = ?1 WHERE id = ?2")
+            .bind(role)
+            .bind(id)
+            .execute(api.pool())
+            .await
+            .unwrap();
+    }
+    let user_cookie = api.sign_in("oid-alice").await;
+    let mod_cookie = api.sign_in("oid-mod").await;
+
+    let response = api.get("/admin/reports", &user_cookie).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(api.get("/admin/reports", &mod_cookie).await.status(), StatusCode::OK);
+
+    // Moderators cannot act on an admin, nor use admin-only routes.
+    let suspend = json!({ "days": 3 });
+    let response = api
+        .post_json(&format!("/admin/users/{admin}/suspend"), &mod_cookie, suspend.clone())
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = api.post_json(&format!("/admin/users/{user}/ban"), &mod_cookie, json!({})).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Suspending ends the target's existing session at once.
+    assert_eq!(api.get("/user/me", &user_cookie).await.status(), StatusCode::OK);
+    let response = api
+        .post_json(&format!("/admin/users/{user}/suspend"), &mod_cookie, suspend)
+        .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(api.get("/user/me", &user_cookie).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn deleting_a_match_archives_the_conversation() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let bob = api.create_user("oid-bob", "Bob").await;
+    let alice_cookie = api.sign_in("oid-alice").await;
+    let bob_cookie = api.sign_in("oid-bob").await;
+
+    for (cookie, liked_id) in [(&alice_cookie, bob), (&bob_cookie, alice)] {
+        let response = api
+            .post_json("/like", cookie, json!({ "liked_id": liked_id, "is_like": true }))
+            .await;
+        assert!(response.status().is_success());
+    }
+    let match_id = body_json(api.get("/matches", &alice_cookie).await).await[0]["id"]
+        .as_i64()
+        .unwrap();
+    for (cookie, other) in [(&alice_cookie, bob), (&bob_cookie, alice)] {
+        let response = api
+            .post_form("/message", cookie, &[("recipient_id", &other.to_string()), ("content", "hi")])
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+    let mut bob_events = api.get("/events", &bob_cookie).await.into_body();
+
+    let response = api
+        .send(
+            Request::delete(format!("/matches/{match_id}"))
+                .header(header::COOKIE, &alice_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    for cookie in [&alice_cookie, &bob_cookie] {
+        let matches = body_json(api.get("/matches", cookie).await).await;
+        assert_eq!(matches.as_array().unwrap().len(), 0);
+    }
+
+    // Alice's like is now a pass, so Bob stays out of her feed.
+    let is_like: bool = sqlx::query_scalar("SELECT is_like FROM likes WHERE liker_id = ?1 AND liked_id = ?2")
+        .bind(alice)
+        .bind(bob)
+        .fetch_one(api.pool())
+        .await
+        .unwrap();
+    assert!(!is_like);
+    let feed = body_json(api.get("/profiles/compatible", &alice_cookie).await).await;
+    assert!(feed.as_array().unwrap().iter().all(|p| p["id"] != bob));
+
+    // The conversation is hidden from both sides but kept for moderation.
+    for (cookie, other) in [(&alice_cookie, bob), (&bob_cookie, alice)] {
+        let response = api.get(&format!("/messages/{other}"), cookie).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+    let archived: Vec<bool> = sqlx::query_scalar("SELECT archived_at IS NOT NULL FROM messages ORDER BY id")
+        .fetch_all(api.pool())
+        .await
+        .unwrap();
+    assert_eq!(archived, [true, true]);
+
+    let (kind, data) = next_event(&mut bob_events).await;
+    assert_eq!(kind, "match.deleted");
+    assert_eq!(data["match_id"], match_id);
+    assert_eq!(data["user_id"], alice);
+}
+
+#[tokio::test]
+async fn messaging_requires_an_unblocked_match() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let bob = api.create_user("oid-bob", "Bob").await;
+    api.create_user("oid-carol", "Carol").await;
+    let alice_cookie = api.sign_in("oid-alice").await;
+    let bob_cookie = api.sign_in("oid-bob").await;
+    let carol_cookie = api.sign_in("oid-carol").await;
+
+    for (cookie, liked_id) in [(&alice_cookie, bob), (&bob_cookie, alice)] {
+        let response = api
+            .post_json("/like", cookie, json!({ "liked_id": liked_id, "is_like": true }))
+            .await;
+        assert!(response.status().is_success());
+    }
+    let response = api
+        .post_image("/message", &alice_cookie, png(8, 8), &[("recipient_id", &bob.to_string())])
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let message_id = body_json(response).await["id"].as_i64().unwrap();
+    let image = format!("/messages/{message_id}/image");
+    assert_eq!(api.get(&image, &bob_cookie).await.status(), StatusCode::OK);
+
+    // Reading and sending messages between `cookie`'s user and `other`.
+    let expect_forbidden = async |cookie: &str, other: i64| {
+        let response = api.get(&format!("/messages/{other}"), cookie).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = api
+            .post_form("/message", cookie, &[("recipient_id", &other.to_string()), ("content", "hi")])
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    };
+
+    // Carol never matched either of them.
+    expect_forbidden(&carol_cookie, alice).await;
+    assert_eq!(api.get(&image, &carol_cookie).await.status(), StatusCode::FORBIDDEN);
+
+    // Once Bob blocks Alice, neither side can reach the other.
+    let response = api.post_json(&format!("/users/{alice}/block"), &bob_cookie, json!({})).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    expect_forbidden(&alice_cookie, bob).await;
+    expect_forbidden(&bob_cookie, alice).await;
+    for cookie in [&alice_cookie, &bob_cookie] {
+        assert_eq!(api.get(&image, cookie).await.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[tokio::test]
+async fn uploaded_profile_photo_is_served_from_the_store() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let cookie = api.sign_in("oid-alice").await;
+
+    let boundary = "test-boundary";
+    let mut body = format!(
+        "--{boundary}\r\n\
+         Content-Disposition: form-data; name=\"bio\"\r\n\r\n\
+         Hello\r\n\
+         --{boundary}\r\n\
+         Content-Disposition: form-data; name=\"image\"; filename=\"me.jpg\"\r\n\
+         Content-Type: image/jpeg\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend(png(40, 30));
+    body.extend(format!("\r\n--{boundary}--\r\n").into_bytes());
+    let response = api
+        .send(
+            Request::post("/user/profile")
+                .header(header::COOKIE, &cookie)
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_json(response).await["bio"], "Hello");
+
+    // Served with the sniffed type, not the one the client claimed.
+    let response = api.get(&format!("/profiles/{alice}/image"), &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+    let served = image::load_from_memory(&body_bytes(response).await).unwrap();
+    assert_eq!((served.width(), served.height()), (40, 30));
+}
+
+#[tokio::test]
+async fn errors_are_json_with_a_request_id() {
+    let api = Api::new().await;
+    api.create_user("oid-alice", "Alice").await;
+    let cookie = api.sign_in("oid-alice").await;
+
+    let response = api.get("/profiles/999", &cookie).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let request_id = response.headers()["x-request-id"].to_str().unwrap().to_string();
+    let body = body_json(response).await;
+    assert_eq!(body["code"], "not_found");
+    assert_eq!(body["message"], "profile not found");
+    assert_eq!(body["request_id"], request_id);
+
+    // Extractor rejections get the same shape.
+    let response = api
+        .post_json("/like", &cookie, json!({ "liked_id": "nobody" }))
+        .await;
+    assert!(response.status().is_client_error());
+    let body = body_json(response).await;
+    assert_eq!(body["code"], "bad_request");
+    assert!(body["request_id"].is_string());
+
+    let response = api.get("/user/me", "").await;
+    assert_eq!(body_json(response).await["code"], "unauthorized");
+}
+
+#[tokio::test]
+async fn invalid_profile_updates_are_rejected_with_field_errors() {
+    let api = Api::new().await;
+    api.create_user("oid-alice", "Alice").await;
+    let cookie = api.sign_in("oid-alice").await;
+
+    let too_many = (0..11).map(|i| format!("tag{i}")).collect::<Vec<_>>().join(",");
+    let response = api
+        .post_form(
+            "/user/profile",
+            &cookie,
+            &[("bio", "Should not be saved"), ("age", "twelve"), ("interests", &too_many)],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = body_json(response).await;
+    assert_eq!(body["code"], "validation_failed");
+    let fields: Vec<&str> = body["details"]["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["age", "interests"]);
+
+    // Nothing was written, not even the valid bio.
+    let me = body_json(api.get("/user/me", &cookie).await).await;
+    assert!(me["bio"].is_null());
+
+    // Minors are turned away by the default rules.
+    let response = api.post_form("/user/profile", &cookie, &[("age", "17")]).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(body_json(response).await["details"]["fields"][0]["field"], "age");
+
+    let response = api
+        .post_form(
+            "/user/profile",
+            &cookie,
+            &[
+                ("age", "20"),
+                ("interests", " Video  Games,hiking"),
+                ("interests", "video games"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let me = body_json(response).await;
+    assert_eq!(me["age"], 20);
+    assert_eq!(me["interests"], json!(["hiking", "video games"]));
+}
+
+#[tokio::test]
+async fn stored_interest_names_are_normalised_and_merged() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let bob = api.create_user("oid-bob", "Bob").await;
+    let cookie = api.sign_in("oid-alice").await;
+
+    // Startup already ran the one-time pass over the (empty) table.
+    let applied: Vec<String> = sqlx::query_scalar("SELECT name FROM data_migrations")
+        .fetch_all(api.pool())
+        .await
+        .unwrap();
+    assert_eq!(applied, ["interest_names"]);
+
+    // Rows written before names were normalised in Rust.
+    for (user, name) in [
+        (alice, "Café"),
+        (bob, "CAFÉ"),
+        (alice, "Video  Games"),
+        (alice, "video games"),
+        (bob, "  "),
+    ] {
+        sqlx::query("INSERT OR IGNORE INTO interests (name) VALUES (?1)")
+            .bind(name)
+            .execute(api.pool())
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO user_interests (user_id, interest_id) SELECT ?1, id FROM interests WHERE name = ?2",
+        )
+        .bind(user)
+        .bind(name)
+        .execute(api.pool())
+        .await
+        .unwrap();
+    }
+
+    let mut conn = api.pool().acquire().await.unwrap();
+    assert_eq!(normalize_interests(&mut conn).await.unwrap(), 5);
+    assert_eq!(normalize_interests(&mut conn).await.unwrap(), 0);
+    drop(conn);
+
+    let names: Vec<String> = sqlx::query_scalar("SELECT name FROM interests ORDER BY name")
+        .fetch_all(api.pool())
+        .await
+        .unwrap();
+    assert_eq!(names, ["café", "video games"]);
+
+    let me = body_json(api.get("/user/me", &cookie).await).await;
+    assert_eq!(me["interests"], json!(["café", "video games"]));
+    let shared: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM user_interests ui JOIN interests i ON i.id = ui.interest_id WHERE i.name = 'café'",
+    )
+    .fetch_one(api.pool())
+    .await
+    .unwrap();
+    assert_eq!(shared, 2);
+}
+
+#[tokio::test]
+async fn patch_updates_and_clears_profile_fields() {
+    let api = Api::new().await;
+    api.create_user("oid-alice", "Alice").await;
+    let cookie = api.sign_in("oid-alice").await;
+
+    let response = api
+        .patch_json(
+            "/user/profile",
+            &cookie,
+            json!({
+                "full_name": "Alice Liddell",
+                "bio": "Curiouser",
+                "is_rso": true,
+                "interests": ["Chess", "chess", "Tea"],
+            }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let me = body_json(response).await;
+    assert_eq!(me["full_name"], "Alice Liddell");
+    assert_eq!(me["is_rso"], true);
+    assert_eq!(me["interests"], json!(["chess", "tea"]));
+
+    // Omitted fields are kept; null clears.
+    let response = api
+        .patch_json("/user/profile", &cookie, json!({ "bio": null, "interests": null }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let me = body_json(response).await;
+    assert!(me["bio"].is_null());
+    assert_eq!(me["interests"], json!([]));
+    assert_eq!(me["full_name"], "Alice Liddell");
+
+    let response = api
+        .patch_json("/user/profile", &cookie, json!({ "nickname": "Al" }))
+        .await;
+    assert!(response.status().is_client_error());
+}
+
+#[tokio::test]
+async fn profile_photo_can_be_replaced_and_removed() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let cookie = api.sign_in("oid-alice").await;
+    let photo = format!("/profiles/{alice}/image");
+
+    let response = api
+        .put_bytes("/user/profile/photo", &cookie, "image/jpeg", jpeg(64, 48))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_json(response).await["image_key"].is_string());
+    let response = api.get(&photo, &cookie).await;
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/jpeg");
+
+    let delete = || {
+        Request::delete("/user/profile/photo")
+            .header(header::COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap()
+    };
+    assert_eq!(api.send(delete()).await.status(), StatusCode::NO_CONTENT);
+    assert_eq!(api.get(&photo, &cookie).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(api.send(delete()).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn uploaded_images_are_sniffed_and_stripped() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let cookie = api.sign_in("oid-alice").await;
+
+    let response = api
+        .put_bytes("/user/profile/photo", &cookie, "image/jpeg", "photo bytes")
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = body_json(response).await;
+    assert_eq!(body["details"]["fields"][0]["field"], "image");
+
+    // A JPEG with an EXIF segment and a payload appended after the image.
+    let plain = jpeg(32, 32);
+    let exif = b"Exif\0\0GPS 40.8202N 96.7005W";
+    let mut upload = plain[..2].to_vec();
+    upload.extend([0xFF, 0xE1]);
+    upload.extend((exif.len() as u16 + 2).to_be_bytes());
+    upload.extend(exif);
+    upload.extend(&plain[2..]);
+    upload.extend(b"<?php system($_GET['c']); ?>");
+
+    let response = api
+        .put_bytes("/user/profile/photo", &cookie, "image/png", upload)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = api.get(&format!("/profiles/{alice}/image"), &cookie).await;
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/jpeg");
+    let served = body_bytes(response).await;
+    let contains = |needle: &[u8]| served.windows(needle.len()).any(|w| w == needle);
+    assert!(!contains(b"GPS"));
+    assert!(!contains(b"<?php"));
+    assert_eq!(image::load_from_memory(&served).unwrap().width(), 32);
+
+    // A lossy WebP stays lossy; a lossless re-encode of noise is many times
+    // the size of the upload.
+    let mut seed = 1u32;
+    let noise = image::RgbImage::from_fn(256, 256, |_, _| {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        image::Rgb((seed >> 8).to_le_bytes()[..3].try_into().unwrap())
+    });
+    let upload = webp::Encoder::from_rgb(&noise, 256, 256).encode(75.0).to_vec();
+    let response = api
+        .put_bytes("/user/profile/photo", &cookie, "image/webp", upload.clone())
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = api.get(&format!("/profiles/{alice}/image"), &cookie).await;
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/webp");
+    let served = body_bytes(response).await;
+    assert!(served.len() < upload.len() * 3 / 2, "{} vs {}", served.len(), upload.len());
+
+    // Over the default 8192-pixel side limit.
+    let response = api
+        .put_bytes("/user/profile/photo", &cookie, "image/png", png(8193, 1))
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(body_json(response).await["details"]["fields"][0]["field"], "image");
+}
+
+#[tokio::test]
+async fn profile_photos_have_webp_variants() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let cookie = api.sign_in("oid-alice").await;
+
+    let response = api
+        .put_bytes("/user/profile/photo", &cookie, "image/png", png(1200, 600))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let image_key = body_json(response).await["image_key"].as_str().unwrap().to_string();
+    let store = &api.state.store;
+    for size in [128, 512, 1080] {
+        let key = StorePath::from(format!("{image_key}_{size}.webp"));
+        assert!(store.head(&key).await.is_ok(), "variant {size} stored on upload");
+    }
+
+    // Sizes round up to the next variant.
+    let response = api.get(&format!("/profiles/{alice}/image?size=300"), &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/webp");
+    let variant = image::load_from_memory(&body_bytes(response).await).unwrap();
+    assert_eq!((variant.width(), variant.height()), (512, 256));
+}
+
+#[tokio::test]
+async fn missing_variants_are_generated_on_request() {
+    let api = Api::new().await;
+    let bob = api.create_user("oid-bob", "Bob").await;
+    let cookie = api.sign_in("oid-bob").await;
+
+    // A photo stored before variants existed.
+    let key = format!("profiles/{bob}");
+    api.state
+        .store
+        .put(&StorePath::from(key.as_str()), PutPayload::from(jpeg(300, 200)))
+        .await
+        .unwrap();
+    sqlx::query("UPDATE users SET image_key = ?1 WHERE id = ?2")
+        .bind(&key)
+        .bind(bob)
+        .execute(api.pool())
+        .await
+        .unwrap();
+
+    let response = api.get(&format!("/profiles/{bob}/image?size=128"), &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let variant = image::load_from_memory(&body_bytes(response).await).unwrap();
+    assert_eq!(variant.width(), 128);
+    let stored = StorePath::from(format!("{key}_128.webp"));
+    assert!(api.state.store.head(&stored).await.is_ok());
+
+    // Never enlarged past the original.
+    let response = api.get(&format!("/profiles/{bob}/image?size=1080"), &cookie).await;
+    let variant = image::load_from_memory(&body_bytes(response).await).unwrap();
+    assert_eq!((variant.width(), variant.height()), (300, 200));
+}
+
+#[tokio::test]
+async fn images_support_conditional_and_range_requests() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let cookie = api.sign_in("oid-alice").await;
+    let photo = format!("/profiles/{alice}/image");
+    api.put_bytes("/user/profile/photo", &cookie, "image/png", png(20, 20))
+        .await;
+
+    let response = api.get(&photo, &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CACHE_CONTROL], "private, no-cache");
+    assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+    let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+    let last_modified = response.headers()[header::LAST_MODIFIED].to_str().unwrap().to_string();
+    let full = body_bytes(response).await;
+
+    let conditional = |name: header::HeaderName, value: &str| {
+        Request::get(&photo)
+            .header(header::COOKIE, &cookie)
+            .header(name, value)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = api.send(conditional(header::IF_NONE_MATCH, &etag)).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag.as_str());
+    assert!(body_bytes(response).await.is_empty());
+    let response = api.send(conditional(header::IF_MODIFIED_SINCE, &last_modified)).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    let response = api.send(conditional(header::IF_NONE_MATCH, "\"stale\"")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = api.send(conditional(header::RANGE, "bytes=0-7")).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers()[header::CONTENT_RANGE],
+        format!("bytes 0-7/{}", full.len()).as_str()
+    );
+    assert_eq!(body_bytes(response).await, full.slice(0..8));
+
+    let response = api.send(conditional(header::RANGE, "bytes=-4")).await;
+    assert_eq!(body_bytes(response).await, full.slice(full.len() - 4..));
+
+    let out_of_bounds = format!("bytes={}-", full.len());
+    let response = api.send(conditional(header::RANGE, &out_of_bounds)).await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+}
+
+#[tokio::test]
+async fn replaced_photos_are_retained_then_collected() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let cookie = api.sign_in("oid-alice").await;
+    let store = api.state.store.as_ref();
+
+    let upload = |data: Vec<u8>| api.put_bytes("/user/profile/photo", &cookie, "image/png", data);
+    let first = body_json(upload(png(10, 10)).await).await["image_key"].as_str().unwrap().to_string();
+    let second = body_json(upload(png(12, 12)).await).await["image_key"].as_str().unwrap().to_string();
+    assert_ne!(first, second);
+    assert!(first.starts_with(&format!("photos/{alice}/")));
+
+    // A current `v` makes the photo cacheable for good.
+    let version = second.rsplit('/').next().unwrap();
+    let response = api
+        .get(&format!("/profiles/{alice}/image?v={version}"), &cookie)
+        .await;
+    assert!(response.headers()[header::CACHE_CONTROL].to_str().unwrap().contains("immutable"));
+
+    // Still within the default retention period.
+    assert_eq!(collect_garbage(api.pool(), store, 30).await.unwrap(), 0);
+    assert!(store.head(&StorePath::from(first.as_str())).await.is_ok());
+    let retired: Vec<String> = sqlx::query_scalar("SELECT key FROM retired_images")
+        .fetch_all(api.pool())
+        .await
+        .unwrap();
+    assert_eq!(retired, std::slice::from_ref(&first));
+
+    // An orphan from before keys were content-addressed, and a message image.
+    for key in ["profiles/999", "messages/1"] {
+        store.put(&StorePath::from(key), PutPayload::from(png(1, 1))).await.unwrap();
+    }
+
+    // With no retention, the old photo, its variants and the orphan go; the
+    // current photo and referenced message images stay.
+    let bob = api.create_user("oid-bob", "Bob").await;
+    sqlx::query(
+        "INSERT INTO messages (id, sender_id, recipient_id, content, image_key) VALUES (1, ?1, ?2, '', 'messages/1')",
+    )
+    .bind(alice)
+    .bind(bob)
+    .execute(api.pool())
+    .await
+    .unwrap();
+    assert_eq!(collect_garbage(api.pool(), store, 0).await.unwrap(), 5);
+    assert!(store.head(&StorePath::from(first.as_str())).await.is_err());
+    assert!(store.head(&StorePath::from(format!("{first}_128.webp"))).await.is_err());
+    assert!(store.head(&StorePath::from("profiles/999")).await.is_err());
+    assert!(store.head(&StorePath::from(second.as_str())).await.is_ok());
+    assert!(store.head(&StorePath::from(format!("{second}_512.webp"))).await.is_ok());
+    assert!(store.head(&StorePath::from("messages/1")).await.is_ok());
+}
+
+#[tokio::test]
+async fn profile_photo_gallery() {
+    let api = Api::with_config("[profile]\nmax_photos = 3").await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    api.create_user("oid-bob", "Bob").await;
+    let cookie = api.sign_in("oid-alice").await;
+    let bob_cookie = api.sign_in("oid-bob").await;
+
+    let mut ids = Vec::new();
+    for (size, caption) in [(10, "first"), (12, "  at the  beach "), (14, "")] {
+        let response = api
+            .post_image("/user/photos", &cookie, png(size, size), &[("caption", caption)])
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let photos = body_json(response).await["photos"].clone();
+        ids.push(photos[ids.len()]["id"].as_i64().unwrap());
+    }
+    let profile = body_json(api.get(&format!("/profiles/{alice}"), &bob_cookie).await).await;
+    assert!(profile.get("role").is_none());
+    let photos = profile["photos"].as_array().unwrap();
+    assert_eq!(photos.len(), 3);
+    assert_eq!(photos[0]["is_primary"], true);
+    assert_eq!(photos[0]["image_key"], profile["image_key"]);
+    assert_eq!(photos[1]["caption"], "at the beach");
+    assert!(photos[2]["caption"].is_null());
+    assert!(photos[0].get("content_type").is_none());
+
+    // Full gallery.
+    let response = api.post_image("/user/photos", &cookie, png(16, 16), &[]).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(body_json(response).await["details"]["fields"][0]["field"], "image");
+
+    // Each photo is served on its own.
+    let response = api
+        .get(&format!("/profiles/{alice}/photos/{}/image", ids[1]), &bob_cookie)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let served = image::load_from_memory(&body_bytes(response).await).unwrap();
+    assert_eq!(served.width(), 12);
+
+    // Reorder, then make the (now first) third photo primary.
+    let order = json!({ "ids": [ids[2], ids[0], ids[1]] }).to_string();
+    let response = api.put_bytes("/user/photos/order", &cookie, "application/json", order).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let incomplete = json!({ "ids": [ids[0], ids[1]] }).to_string();
+    let response = api
+        .put_bytes("/user/photos/order", &cookie, "application/json", incomplete)
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = api
+        .patch_json(
+            &format!("/user/photos/{}", ids[2]),
+            &cookie,
+            json!({ "is_primary": true, "caption": "new" }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let profile = body_json(response).await;
+    let photos = profile["photos"].as_array().unwrap();
+    let order: Vec<i64> = photos.iter().map(|p| p["id"].as_i64().unwrap()).collect();
+    assert_eq!(order, [ids[2], ids[0], ids[1]]);
+    assert_eq!(photos[0]["is_primary"], true);
+    assert_eq!(photos[0]["caption"], "new");
+    assert_eq!(photos[1]["is_primary"], false);
+    assert_eq!(profile["image_key"], photos[0]["image_key"]);
+
+    // Someone else's photo is not found.
+    let response = api
+        .patch_json(&format!("/user/photos/{}", ids[0]), &bob_cookie, json!({ "caption": "x" }))
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Deleting the primary photo promotes the next one and closes the gap.
+    let response = api
+        .send(
+            Request::delete(format!("/user/photos/{}", ids[2]))
+                .header(header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let me = body_json(api.get("/user/me", &cookie).await).await;
+    let photos = me["photos"].as_array().unwrap();
+    assert_eq!(photos.len(), 2);
+    assert_eq!(photos[0]["id"], ids[0]);
+    assert_eq!(photos[0]["is_primary"], true);
+    assert_eq!(photos[1]["position"], 1);
+    assert_eq!(me["image_key"], photos[0]["image_key"]);
+}
+
+#[tokio::test]
+async fn blocked_users_cannot_fetch_profile_photos() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    api.create_user("oid-bob", "Bob").await;
+    let cookie = api.sign_in("oid-alice").await;
+    let bob_cookie = api.sign_in("oid-bob").await;
+
+    let response = api.post_image("/user/photos", &cookie, png(10, 10), &[]).await;
+    let photo_id = body_json(response).await["photos"][0]["id"].as_i64().unwrap();
+    let urls = [
+        format!("/profiles/{alice}/image"),
+        format!("/profiles/{alice}/photos/{photo_id}/image"),
+    ];
+    for url in &urls {
+        assert_eq!(api.get(url, &bob_cookie).await.status(), StatusCode::OK);
+    }
+
+    // Either side blocking hides the photos, as it hides the profile.
+    let response = api.post_json(&format!("/users/{alice}/block"), &bob_cookie, json!({})).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    for url in &urls {
+        let response = api.get(url, &bob_cookie).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body_json(response).await["message"], "image not found");
+        assert_eq!(api.get(url, &cookie).await.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn event_stream_resumes_from_last_event_id() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let bob = api.create_user("oid-bob", "Bob").await;
+    let cookie = api.sign_in("oid-alice").await;
+
+    // Each user's events are numbered on their own, whoever else is busy.
+    let hub = &api.state.hub;
+    for match_id in 1..=3 {
+        let event = ServerEvent::Unmatched { match_id, user_id: 99 };
+        hub.publish(api.pool(), alice, event.clone()).await.unwrap();
+        hub.publish(api.pool(), bob, event).await.unwrap();
+    }
+    let seqs: Vec<i64> = sqlx::query_scalar("SELECT seq FROM events WHERE user_id = ?1 ORDER BY seq")
+        .bind(alice)
+        .fetch_all(api.pool())
+        .await
+        .unwrap();
+    assert_eq!(seqs, [1, 2, 3]);
+
+    let response = api
+        .send(
+            Request::get("/events")
+                .header(header::COOKIE, &cookie)
+                .header("last-event-id", "1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut body = response.into_body();
+
+    // Everything after the resume point is replayed, then live events follow.
+    assert_eq!(next_event_ids(&mut body, 2).await, [2, 3]);
+    hub.publish(api.pool(), bob, ServerEvent::Unmatched { match_id: 4, user_id: 99 })
+        .await
+        .unwrap();
+    hub.publish(api.pool(), alice, ServerEvent::Unmatched { match_id: 4, user_id: 99 })
+        .await
+        .unwrap();
+    assert_eq!(next_event_ids(&mut body, 1).await, [4]);
+
+    // A concurrent publisher has logged an event but not yet delivered it
+    // when the next one arrives: the gap is filled from the log.
+    let pending: i64 = sqlx::query_scalar("UPDATE users SET event_seq = event_seq + 1 WHERE id = ?1 RETURNING event_seq")
+        .bind(alice)
+        .fetch_one(api.pool())
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO events (user_id, seq, kind, payload) VALUES (?1, ?2, 'match.deleted', '{}')")
+        .bind(alice)
+        .bind(pending)
+        .execute(api.pool())
+        .await
+        .unwrap();
+    hub.publish(api.pool(), alice, ServerEvent::Unmatched { match_id: 5, user_id: 99 })
+        .await
+        .unwrap();
+    assert_eq!(next_event_ids(&mut body, 2).await, [pending, pending + 1]);
+}
+
+#[tokio::test]
+async fn websocket_relays_messages_typing_and_reads_between_matches() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let bob = api.create_user("oid-bob", "Bob").await;
+    let carol = api.create_user("oid-carol", "Carol").await;
+    let alice_cookie = api.sign_in("oid-alice").await;
+    let bob_cookie = api.sign_in("oid-bob").await;
+    let carol_cookie = api.sign_in("oid-carol").await;
+
+    for (cookie, liked_id) in [(&alice_cookie, bob), (&bob_cookie, alice)] {
+        let response = api
+            .post_json("/like", cookie, json!({ "liked_id": liked_id, "is_like": true }))
+            .await;
+        assert!(response.status().is_success());
+    }
+    let addr = api.serve().await;
+    let mut alice_ws = connect_ws(&addr, &alice_cookie).await;
+    let mut bob_ws = connect_ws(&addr, &bob_cookie).await;
+    let mut carol_ws = connect_ws(&addr, &carol_cookie).await;
+
+    let response = api
+        .post_form("/message", &alice_cookie, &[("recipient_id", &bob.to_string()), ("content", "hi")])
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let message_id = body_json(response).await["id"].as_i64().unwrap();
+    // Both sides see it, so the sender's other devices stay in sync.
+    for ws in [&mut bob_ws, &mut alice_ws] {
+        let event = next_ws_event(ws).await;
+        assert_eq!(event["type"], "message");
+        assert_eq!(event["message"]["id"], message_id);
+    }
+
+    send_ws(&mut alice_ws, json!({ "type": "typing", "recipient_id": bob })).await;
+    assert_eq!(next_ws_event(&mut bob_ws).await, json!({ "type": "typing", "user_id": alice }));
+
+    send_ws(&mut bob_ws, json!({ "type": "read", "sender_id": alice, "up_to": message_id })).await;
+    assert_eq!(
+        next_ws_event(&mut alice_ws).await,
+        json!({ "type": "read", "user_id": bob, "up_to": message_id })
+    );
+
+    // Carol never matched Bob: her typing and read receipts go nowhere, and
+    // his message to her stays unread.
+    let unread: i64 = sqlx::query_scalar(
+        "INSERT INTO messages (sender_id, recipient_id, content) VALUES (?1, ?2, 'hi') RETURNING id",
+    )
+    .bind(bob)
+    .bind(carol)
+    .fetch_one(api.pool())
+    .await
+    .unwrap();
+    send_ws(&mut carol_ws, json!({ "type": "typing", "recipient_id": bob })).await;
+    send_ws(&mut carol_ws, json!({ "type": "read", "sender_id": bob, "up_to": unread })).await;
+    ws_round_trip(&mut carol_ws).await;
+
+    send_ws(&mut alice_ws, json!({ "type": "typing", "recipient_id": bob })).await;
+    assert_eq!(next_ws_event(&mut bob_ws).await, json!({ "type": "typing", "user_id": alice }));
+    let read: bool = sqlx::query_scalar("SELECT read_at IS NOT NULL FROM messages WHERE id = ?1")
+        .bind(unread)
+        .fetch_one(api.pool())
+        .await
+        .unwrap();
+    assert!(!read);
+}
+
+#[tokio::test]
+async fn reports_attach_each_conversation_message_once() {
+    let api = Api::new().await;
+    let alice = api.create_user("oid-alice", "Alice").await;
+    let bob = api.create_user("oid-bob", "Bob").await;
+    let carol = api.create_user("oid-carol", "Carol").await;
+    let cookie = api.sign_in("oid-alice").await;
+
+    let mut ids = Vec::new();
+    for (sender, recipient) in [(bob, alice), (alice, bob), (bob, carol)] {
+        let id: i64 = sqlx::query_scalar(
+            "INSERT INTO messages (sender_id, recipient_id, content) VALUES (?1, ?2, 'hi') RETURNING id",
+        )
+        .bind(sender)
+        .bind(recipient)
+        .fetch_one(api.pool())
+        .await
+        .unwrap();
+        ids.push(id);
+    }
+
+    // Someone else's conversation is not evidence, and nothing is filed.
+    let response = api
+        .post_json(
+            &format!("/users/{bob}/report"),
+            &cookie,
+            json!({ "reason": "harassment", "message_ids": [ids[0], ids[2]] }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let reports: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM reports")
+        .fetch_one(api.pool())
+        .await
+        .unwrap();
+    assert_eq!(reports, 0);
+
+    // At most 50 messages per report.
+    let response = api
+        .post_json(
+            &format!("/users/{bob}/report"),
+            &cookie,
+            json!({ "reason": "harassment", "message_ids": (1..=51).collect::<Vec<i64>>() }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let error = body_json(response).await;
+    assert_eq!(error["details"]["fields"][0]["field"], "message_ids");
+
+    // Repeated ids are attached once.
+    let response = api
+        .post_json(
+            &format!("/users/{bob}/report"),
+            &cookie,
+            json!({ "reason": "harassment", "message_ids": [ids[1], ids[0], ids[1]] }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let report_id = body_json(response).await["id"].as_i64().unwrap();
+    let attached: Vec<i64> = sqlx::query_scalar(
+        "SELECT message_id FROM report_messages WHERE report_id = ?1 ORDER BY message_id",
+    )
+    .bind(report_id)
+    .fetch_all(api.pool())
+    .await
+    .unwrap();
+    assert_eq!(attached, ids[..2]);
+}
+
+/// Ids of the next `count` events on an SSE body, skipping keep-alives.
+async fn next_event_ids(body: &mut Body, count: usize) -> Vec<i64> {
+    let mut ids = Vec::new();
+    while ids.len() < count {
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(5), body.frame())
+            .await
+            .expect("event within 5s")
+            .unwrap()
+            .unwrap();
+        let Ok(data) = frame.into_data() else { continue };
+        let text = String::from_utf8(data.to_vec()).unwrap();
+        ids.extend(
+            text.lines()
+                .filter_map(|line| line.strip_prefix("id:"))
+                .map(|id| id.trim().parse::<i64>().unwrap()),
+        );
+    }
+    ids
+}
+
+/// Kind and data of the next event on an SSE body, skipping keep-alives.
+async fn next_event(body: &mut Body) -> (String, serde_json::Value) {
+    loop {
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(5), body.frame())
+            .await
+            .expect("event within 5s")
+            .unwrap()
+            .unwrap();
+        let Ok(data) = frame.into_data() else { continue };
+        let text = String::from_utf8(data.to_vec()).unwrap();
+        let field = |name: &str| text.lines().find_map(|line| line.strip_prefix(name)).map(str::trim);
+        if let (Some(kind), Some(data)) = (field("event:"), field("data:")) {
+            return (kind.to_owned(), serde_json::from_str(data).unwrap());
+        }
+    }
+}
+
+type WsClient = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Open `/ws` on `addr` as the holder of `cookie`, once the server is
+/// listening for the user's events.
+async fn connect_ws(addr: &str, cookie: &str) -> WsClient {
+    let mut request = format!("ws://{addr}/ws").into_client_request().unwrap();
+    request.headers_mut().insert(header::COOKIE, cookie.parse().unwrap());
+    let (mut ws, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+    ws_round_trip(&mut ws).await;
+    ws
+}
+
+async fn send_ws(ws: &mut WsClient, frame: serde_json::Value) {
+    ws.send(WsMessage::text(frame.to_string())).await.unwrap();
+}
+
+/// Ping and wait for the pong. The server handles a connection's frames in
+/// order, so everything sent before has been dealt with by then.
+async fn ws_round_trip(ws: &mut WsClient) {
+    ws.send(WsMessage::Ping(Default::default())).await.unwrap();
+    loop {
+        if let WsMessage::Pong(_) = next_ws_frame(ws).await {
+            return;
+        }
+    }
+}
+
+/// The next JSON event on a WebSocket, skipping control frames.
+async fn next_ws_event(ws: &mut WsClient) -> serde_json::Value {
+    loop {
+        if let WsMessage::Text(text) = next_ws_frame(ws).await {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+async fn next_ws_frame(ws: &mut WsClient) -> WsMessage {
+    tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+        .await
+        .expect("frame within 5s")
+        .unwrap()
+        .unwrap()
+}
@@ -0,0 +1,88 @@
+mod support;
+
+use backend::auth::backend::{MICROSOFT, RefreshOutcome};
+use reqwest::StatusCode;
+use support::{FakeIdp, FakeUser, TestApp};
+
+#[tokio::test]
+async fn login_callback_then_protected_route() {
+    let idp = FakeIdp::start().await;
+    let app = TestApp::spawn(&idp).await;
+
+    assert_eq!(app.get("/user/me", None).await.status(), StatusCode::UNAUTHORIZED);
+
+    idp.sign_in_as(FakeUser::new("oid-alice", "alice@university.edu"));
+    let cookie = app.log_in().await;
+
+    let response = app.get("/user/me", Some(&cookie)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let me: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(me["oid"], "oid-alice");
+    assert_eq!(me["email"], "alice@university.edu");
+
+    // Linked by provider + subject, with tokens encrypted at rest.
+    let (user_id, access_token): (i64, String) = sqlx::query_as(
+        "SELECT user_id, access_token FROM identities WHERE provider = ?1 AND subject = ?2",
+    )
+    .bind(MICROSOFT)
+    .bind("oid-alice")
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(me["id"], user_id);
+    assert!(access_token.starts_with("v2:"));
+
+    // Signing in again reuses the account.
+    let cookie = app.log_in().await;
+    let again: serde_json::Value = app.get("/user/me", Some(&cookie)).await.json().await.unwrap();
+    assert_eq!(again["id"], me["id"]);
+}
+
+#[tokio::test]
+async fn rejected_refresh_token_deactivates_account() {
+    let idp = FakeIdp::start().await;
+    let app = TestApp::spawn(&idp).await;
+
+    idp.sign_in_as(FakeUser::new("oid-bob", "bob@university.edu"));
+    let cookie = app.log_in().await;
+
+    assert_eq!(
+        app.backend.refresh_tokens(MICROSOFT, "oid-bob").await.unwrap(),
+        RefreshOutcome::Refreshed
+    );
+    assert_eq!(app.get("/user/me", Some(&cookie)).await.status(), StatusCode::OK);
+
+    idp.revoke_refresh_tokens();
+    assert_eq!(
+        app.backend.refresh_tokens(MICROSOFT, "oid-bob").await.unwrap(),
+        RefreshOutcome::Deactivated
+    );
+    assert_eq!(app.get("/user/me", Some(&cookie)).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn logout_all_ends_every_session() {
+    let idp = FakeIdp::start().await;
+    let app = TestApp::spawn(&idp).await;
+
+    // Signing in on a second device leaves the first one signed in.
+    idp.sign_in_as(FakeUser::new("oid-carol", "carol@university.edu"));
+    let laptop = app.log_in().await;
+    let phone = app.log_in().await;
+    assert_ne!(laptop, phone);
+    for cookie in [&laptop, &phone] {
+        assert_eq!(app.get("/user/me", Some(cookie)).await.status(), StatusCode::OK);
+    }
+
+    let response = app
+        .http
+        .post(format!("{}/auth/logout-all", app.base_url))
+        .header(reqwest::header::COOKIE, &phone)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    for cookie in [&laptop, &phone] {
+        assert_eq!(app.get("/user/me", Some(cookie)).await.status(), StatusCode::UNAUTHORIZED);
+    }
+}
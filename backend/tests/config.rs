@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use backend::config::{Config, ConfigError};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+/// A valid configuration with dev login and no identity providers, followed
+/// by `extra`, which may end in tables.
+fn toml(extra: &str) -> String {
+    format!(
+        r#"
+        database_url = "sqlite::memory:"
+        bind_addr = "127.0.0.1:0"
+        dev_login = true
+        token_encryption_keys = "test:{key}"
+        {extra}
+        "#,
+        key = STANDARD.encode([7u8; 32]),
+    )
+}
+
+fn load(toml: &str, env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+    let env: HashMap<String, String> = env
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    Config::from_sources(Some(toml), &|name| env.get(name).cloned())
+}
+
+fn errors(toml: &str, env: &[(&str, &str)]) -> Vec<String> {
+    match load(toml, env) {
+        Ok(_) => panic!("configuration was accepted"),
+        Err(ConfigError(errors)) => errors,
+    }
+}
+
+#[test]
+fn every_invalid_field_is_reported() {
+    let errors = errors(
+        &toml("db_pool_size = 0\nmax_upload_bytes = 0\n[profile]\nmin_age = 16"),
+        &[("SESSION_IDLE_TIMEOUT_HOURS", "soon")],
+    );
+
+    for expected in [
+        "SESSION_IDLE_TIMEOUT_HOURS",
+        "db_pool_size must be at least 1",
+        "max_upload_bytes must be positive",
+        "profile ages must satisfy 18 <= min_age <= max_age",
+    ] {
+        assert!(
+            errors.iter().any(|e| e.starts_with(expected)),
+            "missing {expected:?} in {errors:#?}"
+        );
+    }
+    assert_eq!(errors.len(), 4, "{errors:#?}");
+}
+
+#[test]
+fn environment_overrides_the_file() {
+    let config = load(
+        &toml("db_pool_size = 3\nevent_retention_days = 2"),
+        &[("DB_POOL_SIZE", "9"), ("PROFILE_MAX_AGE", "40")],
+    )
+    .unwrap();
+
+    assert_eq!(config.db_pool_size, 9);
+    assert_eq!(config.profile.max_age, 40);
+    assert_eq!(config.event_retention_days, 2);
+}
+
+#[test]
+fn token_encryption_keys_are_required() {
+    let toml = toml("").replace("token_encryption_keys", "# token_encryption_keys");
+    assert_eq!(
+        errors(&toml, &[]),
+        ["token_encryption_keys (TOKEN_ENCRYPTION_KEYS) is required"]
+    );
+
+    let key = STANDARD.encode([1u8; 32]);
+    let config = load(&toml, &[("TOKEN_ENCRYPTION_KEYS", &format!("env:{key}"))]);
+    assert!(config.is_ok());
+}
+
+#[test]
+fn dev_login_needs_a_loopback_bind_addr() {
+    let errors = errors(&toml(""), &[("BIND_ADDR", "0.0.0.0:8080")]);
+    assert_eq!(
+        errors,
+        ["dev_login requires a loopback bind_addr, got 0.0.0.0:8080"]
+    );
+
+    let config = load(&toml(""), &[("BIND_ADDR", "[::1]:8080")]).unwrap();
+    assert!(config.dev_login);
+}
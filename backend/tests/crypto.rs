@@ -0,0 +1,130 @@
+use backend::crypto::{TokenCipher, TokenColumn, TokenSlot};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+fn key(byte: u8) -> String {
+    STANDARD.encode([byte; 32])
+}
+
+fn cipher(keys: &[(&str, u8)], active: &str) -> TokenCipher {
+    let keys: Vec<String> = keys
+        .iter()
+        .map(|(kid, b)| format!("{kid}:{}", key(*b)))
+        .collect();
+    TokenCipher::from_config(&keys.join(","), Some(active)).unwrap()
+}
+
+fn slot(column: TokenColumn) -> TokenSlot<'static> {
+    TokenSlot::new(1, "microsoft", "oid-alice", column)
+}
+
+#[test]
+fn encrypt_then_decrypt_round_trips() {
+    let cipher = cipher(&[("k1", 1)], "k1");
+    let stored = cipher
+        .encrypt("secret-token", slot(TokenColumn::AccessToken))
+        .unwrap();
+
+    assert!(stored.starts_with("v2:k1:"));
+    assert!(!stored.contains("secret-token"));
+    assert_eq!(
+        cipher
+            .decrypt(&stored, slot(TokenColumn::AccessToken))
+            .unwrap(),
+        "secret-token"
+    );
+}
+
+#[test]
+fn values_under_a_retired_key_still_decrypt_and_rewrap() {
+    let old = cipher(&[("k1", 1)], "k1");
+    let stored = old
+        .encrypt("secret-token", slot(TokenColumn::RefreshToken))
+        .unwrap();
+
+    let rotated = cipher(&[("k2", 2), ("k1", 1)], "k2");
+    assert_eq!(
+        rotated
+            .decrypt(&stored, slot(TokenColumn::RefreshToken))
+            .unwrap(),
+        "secret-token"
+    );
+
+    let rewrapped = rotated
+        .rewrap(&stored, slot(TokenColumn::RefreshToken))
+        .unwrap();
+    assert!(rewrapped.starts_with("v2:k2:"));
+    // Only the DEK is re-wrapped; the token ciphertext is unchanged.
+    assert_eq!(rewrapped.rsplit(':').next(), stored.rsplit(':').next());
+
+    let retired = cipher(&[("k2", 2)], "k2");
+    assert_eq!(
+        retired
+            .decrypt(&rewrapped, slot(TokenColumn::RefreshToken))
+            .unwrap(),
+        "secret-token"
+    );
+    assert!(
+        retired
+            .decrypt(&stored, slot(TokenColumn::RefreshToken))
+            .is_err()
+    );
+}
+
+#[test]
+fn needs_migration_flags_plaintext_and_retired_keys() {
+    let old = cipher(&[("k1", 1)], "k1");
+    let rotated = cipher(&[("k2", 2), ("k1", 1)], "k2");
+    let under_k1 = old.encrypt("t", slot(TokenColumn::IdToken)).unwrap();
+    let under_k2 = rotated.encrypt("t", slot(TokenColumn::IdToken)).unwrap();
+
+    assert!(rotated.needs_migration("eyJ0eXAiOiJKV1QifQ.plaintext"));
+    assert!(rotated.needs_migration(&under_k1));
+    assert!(!rotated.needs_migration(&under_k2));
+    assert!(!old.needs_migration(&under_k1));
+
+    let encrypted = rotated
+        .rewrap("plaintext-token", slot(TokenColumn::IdToken))
+        .unwrap();
+    assert!(!rotated.needs_migration(&encrypted));
+    assert_eq!(
+        rotated
+            .decrypt(&encrypted, slot(TokenColumn::IdToken))
+            .unwrap(),
+        "plaintext-token"
+    );
+}
+
+#[test]
+fn tampered_ciphertext_is_rejected() {
+    let cipher = cipher(&[("k1", 1)], "k1");
+    let stored = cipher
+        .encrypt("secret-token", slot(TokenColumn::AccessToken))
+        .unwrap();
+
+    let (head, body) = stored.rsplit_once(':').unwrap();
+    let mut bytes = STANDARD.decode(body).unwrap();
+    *bytes.last_mut().unwrap() ^= 1;
+    let tampered = format!("{head}:{}", STANDARD.encode(bytes));
+
+    assert!(
+        cipher
+            .decrypt(&tampered, slot(TokenColumn::AccessToken))
+            .is_err()
+    );
+}
+
+#[test]
+fn ciphertext_is_bound_to_its_slot() {
+    let cipher = cipher(&[("k1", 1)], "k1");
+    let stored = cipher
+        .encrypt("secret-token", slot(TokenColumn::RefreshToken))
+        .unwrap();
+
+    assert!(
+        cipher
+            .decrypt(&stored, slot(TokenColumn::AccessToken))
+            .is_err()
+    );
+    let other_user = TokenSlot::new(2, "microsoft", "oid-alice", TokenColumn::RefreshToken);
+    assert!(cipher.decrypt(&stored, other_user).is_err());
+}
@@ -0,0 +1,55 @@
+use backend::{
+    auth::{policy::SignInPolicy, provider::Identity},
+    error::AppError,
+};
+
+const UNIVERSITY: &str = "11111111-2222-3333-4444-555555555555";
+const ELSEWHERE: &str = "99999999-8888-7777-6666-555555555555";
+
+fn identity(email: &str, verified: bool, tenant: Option<&str>) -> Identity {
+    Identity {
+        subject: "subject".into(),
+        email: Some(email.into()),
+        email_verified: verified,
+        preferred_username: Some(email.into()),
+        name: None,
+        tenant_id: tenant.map(String::from),
+    }
+}
+
+fn denied(policy: &SignInPolicy, identity: &Identity) -> bool {
+    matches!(policy.check(identity), Err(AppError::LoginDenied(_)))
+}
+
+#[test]
+fn unverified_email_from_a_foreign_tenant_is_denied() {
+    let policy = SignInPolicy::from_lists("", "university.edu");
+
+    // Anyone can create a tenant and set a user's email to any address.
+    let forged = identity("x@university.edu", false, Some(ELSEWHERE));
+    assert!(denied(&policy, &forged));
+
+    // With `xms_edov` the tenant has proven it owns the domain.
+    let verified = identity("x@university.edu", true, Some(UNIVERSITY));
+    assert!(policy.check(&verified).is_ok());
+    let other_domain = identity("x@elsewhere.edu", true, Some(ELSEWHERE));
+    assert!(denied(&policy, &other_domain));
+}
+
+#[test]
+fn allowed_tenants_vouch_for_their_addresses() {
+    let policy = SignInPolicy::from_lists(UNIVERSITY, "university.edu");
+
+    assert!(policy.check(&identity("x@university.edu", false, Some(UNIVERSITY))).is_ok());
+    assert!(policy.check(&identity("x@cs.university.edu", false, Some(UNIVERSITY))).is_ok());
+    assert!(denied(&policy, &identity("x@elsewhere.edu", false, Some(UNIVERSITY))));
+    assert!(denied(&policy, &identity("x@university.edu", true, Some(ELSEWHERE))));
+}
+
+#[test]
+fn tenant_list_denies_identities_without_a_tenant() {
+    let policy = SignInPolicy::from_lists(UNIVERSITY, "");
+
+    assert!(policy.check(&identity("x@example.com", true, Some(UNIVERSITY))).is_ok());
+    assert!(denied(&policy, &identity("x@example.com", true, None)));
+}
@@ -0,0 +1,73 @@
+use backend::{db::init_pool, session_store::SqliteSessionStore};
+use sqlx::SqlitePool;
+use tower_sessions::{
+    ExpiredDeletion, SessionStore,
+    cookie::time::{Duration, OffsetDateTime},
+    session::{Id, Record},
+};
+
+async fn store() -> (SqliteSessionStore, SqlitePool) {
+    let pool = init_pool("sqlite::memory:", 1).await.unwrap();
+    (
+        SqliteSessionStore::new(pool.clone(), Duration::days(7)),
+        pool,
+    )
+}
+
+/// A new session that stays idle-valid for another hour.
+async fn create(store: &SqliteSessionStore) -> Id {
+    let mut record = Record {
+        id: Id::default(),
+        data: Default::default(),
+        expiry_date: OffsetDateTime::now_utc() + Duration::hours(1),
+    };
+    store.create(&mut record).await.unwrap();
+    record.id
+}
+
+/// Pretend session `id` was created `age` ago.
+async fn backdate(pool: &SqlitePool, id: Id, age: Duration) {
+    sqlx::query("UPDATE sessions SET created_at = ?1 WHERE id = ?2")
+        .bind((OffsetDateTime::now_utc() - age).unix_timestamp())
+        .bind(id.to_string())
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn sessions_past_the_absolute_timeout_are_rejected() {
+    let (store, pool) = store().await;
+    let id = create(&store).await;
+    assert!(store.load(&id).await.unwrap().is_some());
+
+    backdate(&pool, id, Duration::days(6)).await;
+    assert!(store.load(&id).await.unwrap().is_some());
+
+    // Still within the idle timeout, but too old.
+    backdate(&pool, id, Duration::days(8)).await;
+    assert!(store.load(&id).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn cleanup_deletes_idle_and_overage_sessions() {
+    let (store, pool) = store().await;
+    let live = create(&store).await;
+    let idle = create(&store).await;
+    let old = create(&store).await;
+
+    sqlx::query("UPDATE sessions SET expiry_date = ?1 WHERE id = ?2")
+        .bind((OffsetDateTime::now_utc() - Duration::minutes(1)).unix_timestamp())
+        .bind(idle.to_string())
+        .execute(&pool)
+        .await
+        .unwrap();
+    backdate(&pool, old, Duration::days(8)).await;
+
+    store.delete_expired().await.unwrap();
+    let remaining: Vec<String> = sqlx::query_scalar("SELECT id FROM sessions")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, [live.to_string()]);
+}
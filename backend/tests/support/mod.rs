@@ -0,0 +1,643 @@
+//! Test support: a local fake Microsoft identity platform and a backend wired
+//! up against it, served over real HTTP on loopback ports; and the same
+//! backend driven in-process for API tests.
+
+#![allow(dead_code)]
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Form, Json, Router,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{Request, StatusCode, header},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+};
+use http_body_util::BodyExt;
+use base64::{Engine as _, engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use rsa::{
+    RsaPrivateKey,
+    pkcs1::{DecodeRsaPrivateKey, EncodeRsaPrivateKey},
+    traits::PublicKeyParts,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use tower::ServiceExt;
+
+use backend::{
+    AppState,
+    auth::{backend::AuthBackend, oidc::OidcProvider},
+    config::Config,
+    db::init_pool,
+};
+
+/// RSA key the fake identity provider signs id_tokens with.
+const SIGNING_KEY_PEM: &str = include_str!("test_key.pem");
+const SIGNING_KID: &str = "test-key";
+
+pub const CLIENT_ID: &str = "test-client";
+pub const CLIENT_SECRET: &str = "test-secret";
+pub const TENANT_ID: &str = "11111111-2222-3333-4444-555555555555";
+
+/// Someone who can sign in at the fake identity provider.
+#[derive(Clone, Debug)]
+pub struct FakeUser {
+    pub oid: String,
+    pub email: String,
+    pub name: String,
+}
+
+impl FakeUser {
+    pub fn new(oid: &str, email: &str) -> Self {
+        Self {
+            oid: oid.into(),
+            email: email.into(),
+            name: oid.into(),
+        }
+    }
+}
+
+struct PendingCode {
+    user: FakeUser,
+    nonce: String,
+    code_challenge: String,
+    redirect_uri: String,
+}
+
+#[derive(Default)]
+struct IdpState {
+    /// Who is "signed in" at the provider; `/authorize` consents as them.
+    user: Option<FakeUser>,
+    codes: HashMap<String, PendingCode>,
+    /// Issued refresh tokens and their owner.
+    refresh_tokens: HashMap<String, FakeUser>,
+    revoked: HashSet<String>,
+}
+
+/// Fake Microsoft Entra tenant: authorize, token and JWKS endpoints laid out
+/// like `login.microsoftonline.com`, issuing RS256-signed id_tokens.
+#[derive(Clone)]
+pub struct FakeIdp {
+    /// Use as `MicrosoftEndpoints::authority`.
+    pub authority: String,
+    state: Arc<Mutex<IdpState>>,
+    counter: Arc<AtomicU64>,
+}
+
+impl FakeIdp {
+    pub async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let idp = Self {
+            authority: format!("http://{}", listener.local_addr().unwrap()),
+            state: Arc::default(),
+            counter: Arc::default(),
+        };
+
+        let app = Router::new()
+            .route("/{tenant}/oauth2/v2.0/authorize", get(authorize))
+            .route("/{tenant}/oauth2/v2.0/token", post(token))
+            .route("/{tenant}/discovery/v2.0/keys", get(keys))
+            .with_state(idp.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        idp
+    }
+
+    /// Make `user` the account the next authorization consents as.
+    pub fn sign_in_as(&self, user: FakeUser) {
+        self.state.lock().unwrap().user = Some(user);
+    }
+
+    /// Revoke every refresh token issued so far, as if the accounts had been
+    /// removed from the tenant.
+    pub fn revoke_refresh_tokens(&self) {
+        let mut state = self.state.lock().unwrap();
+        let issued: Vec<String> = state.refresh_tokens.keys().cloned().collect();
+        state.revoked.extend(issued);
+    }
+
+    fn next_id(&self, prefix: &str) -> String {
+        format!("{prefix}-{}", self.counter.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn issuer(&self) -> String {
+        format!("{}/{TENANT_ID}/v2.0", self.authority)
+    }
+
+    fn id_token(&self, user: &FakeUser, nonce: Option<&str>) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let mut claims = json!({
+            "iss": self.issuer(),
+            "aud": CLIENT_ID,
+            "sub": format!("pairwise-{}", user.oid),
+            "oid": user.oid,
+            "tid": TENANT_ID,
+            "email": user.email,
+            "preferred_username": user.email,
+            "name": user.name,
+            "iat": now,
+            "nbf": now,
+            "exp": now + 3600,
+        });
+        if let Some(nonce) = nonce {
+            claims["nonce"] = json!(nonce);
+        }
+
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(SIGNING_KID.into());
+        let der = signing_key().to_pkcs1_der().unwrap();
+        let key = EncodingKey::from_rsa_der(der.as_bytes());
+        jsonwebtoken::encode(&header, &claims, &key).unwrap()
+    }
+
+    fn tokens(&self, user: &FakeUser, nonce: Option<&str>) -> Response {
+        let refresh_token = self.next_id("refresh");
+        self.state
+            .lock()
+            .unwrap()
+            .refresh_tokens
+            .insert(refresh_token.clone(), user.clone());
+        Json(json!({
+            "token_type": "Bearer",
+            "access_token": self.next_id("access"),
+            "refresh_token": refresh_token,
+            "id_token": self.id_token(user, nonce),
+            "expires_in": 3600,
+        }))
+        .into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct AuthorizeParams {
+    client_id: String,
+    redirect_uri: String,
+    state: String,
+    nonce: String,
+    code_challenge: String,
+    code_challenge_method: String,
+}
+
+async fn authorize(
+    State(idp): State<FakeIdp>,
+    Path(_tenant): Path<String>,
+    Query(params): Query<AuthorizeParams>,
+) -> Response {
+    if params.client_id != CLIENT_ID || params.code_challenge_method != "S256" {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    let Some(user) = idp.state.lock().unwrap().user.clone() else {
+        return (StatusCode::BAD_REQUEST, "no user signed in at the fake IdP").into_response();
+    };
+
+    let code = idp.next_id("code");
+    idp.state.lock().unwrap().codes.insert(
+        code.clone(),
+        PendingCode {
+            user,
+            nonce: params.nonce,
+            code_challenge: params.code_challenge,
+            redirect_uri: params.redirect_uri.clone(),
+        },
+    );
+
+    let mut location = reqwest::Url::parse(&params.redirect_uri).unwrap();
+    location
+        .query_pairs_mut()
+        .append_pair("code", &code)
+        .append_pair("state", &params.state);
+    Redirect::to(location.as_str()).into_response()
+}
+
+fn oauth_error(error: &str) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response()
+}
+
+async fn token(
+    State(idp): State<FakeIdp>,
+    Path(_tenant): Path<String>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let field = |k: &str| form.get(k).map(String::as_str).unwrap_or_default();
+    if field("client_id") != CLIENT_ID || field("client_secret") != CLIENT_SECRET {
+        return oauth_error("invalid_client");
+    }
+
+    match field("grant_type") {
+        "authorization_code" => {
+            let Some(pending) = idp.state.lock().unwrap().codes.remove(field("code")) else {
+                return oauth_error("invalid_grant");
+            };
+            let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(field("code_verifier")));
+            if challenge != pending.code_challenge || field("redirect_uri") != pending.redirect_uri {
+                return oauth_error("invalid_grant");
+            }
+            idp.tokens(&pending.user, Some(&pending.nonce))
+        }
+        "refresh_token" => {
+            let user = {
+                let state = idp.state.lock().unwrap();
+                let token = field("refresh_token");
+                if state.revoked.contains(token) {
+                    None
+                } else {
+                    state.refresh_tokens.get(token).cloned()
+                }
+            };
+            match user {
+                Some(user) => idp.tokens(&user, None),
+                None => oauth_error("invalid_grant"),
+            }
+        }
+        _ => oauth_error("unsupported_grant_type"),
+    }
+}
+
+fn signing_key() -> RsaPrivateKey {
+    RsaPrivateKey::from_pkcs1_pem(SIGNING_KEY_PEM).unwrap()
+}
+
+async fn keys() -> Json<serde_json::Value> {
+    let key = signing_key();
+    Json(json!({
+        "keys": [{
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": SIGNING_KID,
+            "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+            "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+        }]
+    }))
+}
+
+/// Configuration for a test backend: a fresh in-memory database, a fixed
+/// token encryption key and dev login, plus whatever `extra` TOML adds.
+/// Top-level keys in `extra` must come before any tables.
+pub fn test_config(extra: &str) -> Config {
+    static DB: AtomicU64 = AtomicU64::new(0);
+    let toml = format!(
+        r#"
+        database_url = "sqlite:file:test-{pid}-{db}?mode=memory&cache=shared"
+        bind_addr = "127.0.0.1:0"
+        dev_login = true
+        token_encryption_keys = "test:{key}"
+        {extra}
+        "#,
+        pid = std::process::id(),
+        db = DB.fetch_add(1, Ordering::Relaxed),
+        key = STANDARD.encode([7u8; 32]),
+    );
+    Config::from_sources(Some(&toml), &|_| None).unwrap()
+}
+
+/// The full backend, served over loopback and signing in against a
+/// [`FakeIdp`].
+pub struct TestApp {
+    pub base_url: String,
+    pub pool: SqlitePool,
+    pub backend: AuthBackend,
+    /// Does not follow redirects, so each hop can be checked.
+    pub http: reqwest::Client,
+}
+
+impl TestApp {
+    pub async fn spawn(idp: &FakeIdp) -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let config = test_config(&format!(
+            r#"
+            frontend_url = "{base_url}"
+            redirect_url = "{base_url}/auth/callback"
+
+            [azure]
+            client_id = "{CLIENT_ID}"
+            client_secret = "{CLIENT_SECRET}"
+            tenant_id = "{TENANT_ID}"
+            authority = "{authority}"
+            "#,
+            authority = idp.authority,
+        ));
+        let pool = init_pool(&config.database_url, config.db_pool_size).await.unwrap();
+
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+
+        let azure = config.azure.clone().unwrap();
+        let provider = OidcProvider::microsoft(
+            azure.client_id,
+            azure.client_secret.expose().into(),
+            &azure.tenant_id,
+            config.redirect_url.clone().unwrap(),
+            azure.endpoints,
+            http.clone(),
+        )
+        .unwrap();
+        let backend =
+            AuthBackend::new(pool.clone(), config.token_cipher.clone()).with_provider(provider);
+
+        let state = AppState::builder(pool.clone(), config.clone())
+            .backend(backend.clone())
+            .build();
+        let app = backend::app(state, &config);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        Self {
+            base_url,
+            pool,
+            backend,
+            http,
+        }
+    }
+
+    /// `GET` on the app, sending `cookie` if given.
+    pub async fn get(&self, path: &str, cookie: Option<&str>) -> reqwest::Response {
+        let mut request = self.http.get(format!("{}{path}", self.base_url));
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        request.send().await.unwrap()
+    }
+
+    /// Run the whole sign-in dance for whoever is signed in at `idp` and
+    /// return the session cookie.
+    pub async fn log_in(&self) -> String {
+        let response = self.get("/auth/login", None).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let pre_login = session_cookie(&response).expect("login sets a session cookie");
+
+        // The browser follows the redirect to the IdP, which sends it back.
+        let response = self.http.get(location(&response)).send().await.unwrap();
+        assert!(response.status().is_redirection());
+        let callback = location(&response);
+        assert!(callback.starts_with(&format!("{}/auth/callback?", self.base_url)));
+
+        let response = self
+            .http
+            .get(callback)
+            .header(header::COOKIE, &pre_login)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        session_cookie(&response).unwrap_or(pre_login)
+    }
+}
+
+pub fn location(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get(header::LOCATION)
+        .expect("redirect without Location")
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+/// `name=value` of the session cookie set by `response`, if any.
+pub fn session_cookie(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find(|v| v.starts_with("id="))
+        .and_then(|v| v.split(';').next())
+        .map(String::from)
+}
+
+// ---------------------------------------------------------------------------
+// In-process API
+// ---------------------------------------------------------------------------
+
+/// The full router over an in-memory database and object store, driven one
+/// request at a time with `tower::ServiceExt::oneshot`. Users sign in through
+/// dev login instead of an identity provider.
+pub struct Api {
+    pub router: Router,
+    pub state: AppState,
+}
+
+impl Api {
+    pub async fn new() -> Self {
+        Self::with_config("").await
+    }
+
+    /// Like [`Api::new`], with `extra` TOML as for [`test_config`].
+    pub async fn with_config(extra: &str) -> Self {
+        Self::from_config(test_config(extra)).await
+    }
+
+    /// Like [`Api::new`], with a configuration built by hand.
+    pub async fn from_config(config: Config) -> Self {
+        let pool = init_pool(&config.database_url, config.db_pool_size).await.unwrap();
+        let state = AppState::builder(pool, config.clone()).build();
+        let router = backend::app(state.clone(), &config);
+        Self { router, state }
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.state.pool
+    }
+
+    pub async fn send(&self, request: Request<Body>) -> Response {
+        self.router.clone().oneshot(request).await.unwrap()
+    }
+
+    /// Serve the router on a loopback port, for clients that need a real
+    /// connection (WebSockets), and return its address.
+    pub async fn serve(&self) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let router = self.router.clone();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        addr
+    }
+
+    pub async fn get(&self, path: &str, cookie: &str) -> Response {
+        self.send(
+            Request::get(path)
+                .header(header::COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+    }
+
+    pub async fn post_json(&self, path: &str, cookie: &str, body: serde_json::Value) -> Response {
+        self.send(
+            Request::post(path)
+                .header(header::COOKIE, cookie)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    pub async fn patch_json(&self, path: &str, cookie: &str, body: serde_json::Value) -> Response {
+        self.send(
+            Request::patch(path)
+                .header(header::COOKIE, cookie)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// PUT a raw body with the given `Content-Type`.
+    pub async fn put_bytes(
+        &self,
+        path: &str,
+        cookie: &str,
+        content_type: &str,
+        body: impl Into<Body>,
+    ) -> Response {
+        self.send(
+            Request::put(path)
+                .header(header::COOKIE, cookie)
+                .header(header::CONTENT_TYPE, content_type)
+                .body(body.into())
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// `multipart/form-data` POST with text fields only.
+    pub async fn post_form(&self, path: &str, cookie: &str, fields: &[(&str, &str)]) -> Response {
+        const BOUNDARY: &str = "test-boundary";
+        let mut body = String::new();
+        for (name, value) in fields {
+            body.push_str(&format!(
+                "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+            ));
+        }
+        body.push_str(&format!("--{BOUNDARY}--\r\n"));
+        self.send(
+            Request::post(path)
+                .header(header::COOKIE, cookie)
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={BOUNDARY}"),
+                )
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// `multipart/form-data` POST of `image` as the `image` field, plus text
+    /// fields.
+    pub async fn post_image(
+        &self,
+        path: &str,
+        cookie: &str,
+        image: Vec<u8>,
+        fields: &[(&str, &str)],
+    ) -> Response {
+        const BOUNDARY: &str = "test-boundary";
+        let mut body = Vec::new();
+        for (name, value) in fields {
+            body.extend(format!(
+                "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+            ).into_bytes());
+        }
+        body.extend(
+            format!(
+                "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"image\"; filename=\"photo\"\r\n\r\n"
+            )
+            .into_bytes(),
+        );
+        body.extend(image);
+        body.extend(format!("\r\n--{BOUNDARY}--\r\n").into_bytes());
+        self.send(
+            Request::post(path)
+                .header(header::COOKIE, cookie)
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={BOUNDARY}"),
+                )
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// Insert a user and return their id.
+    pub async fn create_user(&self, oid: &str, name: &str) -> i64 {
+        sqlx::query_scalar(
+            "INSERT INTO users (oid, email, display_name) VALUES (?1, ?2, ?3) RETURNING id",
+        )
+        .bind(oid)
+        .bind(format!("{oid}@university.edu"))
+        .bind(name)
+        .fetch_one(self.pool())
+        .await
+        .unwrap()
+    }
+
+    /// Sign in as the user with `oid` via dev login and return the session
+    /// cookie.
+    pub async fn sign_in(&self, oid: &str) -> String {
+        let response = self
+            .send(
+                Request::get(format!("/auth/dev-login?oid={oid}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .find(|v| v.starts_with("id="))
+            .and_then(|v| v.split(';').next())
+            .expect("dev login sets a session cookie")
+            .to_string()
+    }
+}
+
+/// A `width` x `height` PNG.
+pub fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    image::RgbImage::from_pixel(width, height, image::Rgb([200, 80, 40]))
+        .write_to(&mut std::io::Cursor::new(&mut out), image::ImageFormat::Png)
+        .unwrap();
+    out
+}
+
+/// A `width` x `height` JPEG.
+pub fn jpeg(width: u32, height: u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    image::RgbImage::from_pixel(width, height, image::Rgb([40, 80, 200]))
+        .write_to(&mut std::io::Cursor::new(&mut out), image::ImageFormat::Jpeg)
+        .unwrap();
+    out
+}
+
+/// Read a response body to completion.
+pub async fn body_bytes(response: Response) -> bytes::Bytes {
+    response.into_body().collect().await.unwrap().to_bytes()
+}
+
+/// Read a JSON response body.
+pub async fn body_json(response: Response) -> serde_json::Value {
+    serde_json::from_slice(&body_bytes(response).await).unwrap()
+}
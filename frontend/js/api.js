@@ -6,7 +6,7 @@ async function request(method, path, body) {
     credentials: 'include',
     headers: {},
   };
-  if (body instanceof FormData) {
+  if (body instanceof FormData || body instanceof Blob) {
     opts.body = body;
   } else if (body !== undefined) {
     opts.headers['Content-Type'] = 'application/json';
@@ -20,6 +20,27 @@ async function request(method, path, body) {
   return res;
 }
 
+// Error responses are JSON `{ code, message, details, request_id }`;
+// validation errors list the failing fields in `details.fields`.
+async function errorMessage(res, fallback) {
+  try {
+    const body = await res.json();
+    const fields = body.details?.fields;
+    if (fields?.length) {
+      return fields.map(f => `${f.field} ${f.message}`).join('; ');
+    }
+    return body.message || fallback;
+  } catch {
+    return fallback;
+  }
+}
+
+export async function getAuthProviders() {
+  const res = await fetch('/auth/providers', { credentials: 'include' });
+  if (!res.ok) throw new Error('getAuthProviders failed');
+  return res.json();
+}
+
 export async function getMe() {
   const res = await fetch('/user/me', { credentials: 'include' });
   if (res.status === 401) return null;
@@ -27,12 +48,48 @@ export async function getMe() {
   return res.json();
 }
 
-export async function updateProfile(formData) {
-  const res = await request('POST', '/user/profile', formData);
-  if (!res.ok) throw new Error(await res.text());
+// `fields` is a partial profile; `null` clears a field.
+export async function updateProfile(fields) {
+  const res = await request('PATCH', '/user/profile', fields);
+  if (!res.ok) throw new Error(await errorMessage(res, 'updateProfile failed'));
+  return res.json();
+}
+
+export async function uploadProfilePhoto(file) {
+  const res = await request('PUT', '/user/profile/photo', file);
+  if (!res.ok) throw new Error(await errorMessage(res, 'uploadProfilePhoto failed'));
+  return res.json();
+}
+
+// Appends a photo to the gallery; returns the updated profile.
+export async function addProfilePhoto(file, caption) {
+  const fd = new FormData();
+  fd.append('image', file);
+  if (caption) fd.append('caption', caption);
+  const res = await request('POST', '/user/photos', fd);
+  if (!res.ok) throw new Error(await errorMessage(res, 'addProfilePhoto failed'));
+  return res.json();
+}
+
+// `fields` may hold `caption` (`null` clears it) and `is_primary: true`.
+export async function updateProfilePhoto(id, fields) {
+  const res = await request('PATCH', `/user/photos/${id}`, fields);
+  if (!res.ok) throw new Error(await errorMessage(res, 'updateProfilePhoto failed'));
+  return res.json();
+}
+
+// `ids` lists every photo of the gallery in the new order.
+export async function reorderProfilePhotos(ids) {
+  const res = await request('PUT', '/user/photos/order', { ids });
+  if (!res.ok) throw new Error(await errorMessage(res, 'reorderProfilePhotos failed'));
   return res.json();
 }
 
+export async function deleteProfilePhoto(id) {
+  const res = await request('DELETE', `/user/photos/${id}`);
+  if (!res.ok) throw new Error(await errorMessage(res, 'deleteProfilePhoto failed'));
+}
+
 export async function getCompatibleProfiles() {
   const res = await request('GET', '/profiles/compatible');
   if (!res.ok) throw new Error('getCompatibleProfiles failed');
@@ -56,6 +113,11 @@ export async function getMatches() {
   return res.json();
 }
 
+export async function unmatch(matchId) {
+  const res = await request('DELETE', `/matches/${matchId}`);
+  if (!res.ok) throw new Error('unmatch failed');
+}
+
 export async function getMessages(userId) {
   const res = await request('GET', `/messages/${userId}`);
   if (!res.ok) throw new Error('getMessages failed');
@@ -72,8 +134,47 @@ export async function sendMessage(recipient_id, content, image) {
   return res.json();
 }
 
-export function profileImageUrl(id) {
-  return `/profiles/${id}/image`;
+// Opens the real-time `/ws` connection. `onEvent` receives each parsed
+// server event; returns the WebSocket so callers can send frames / close it.
+export function openSocket(onEvent) {
+  const proto = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+  const ws = new WebSocket(`${proto}//${window.location.host}/ws`);
+  ws.addEventListener('message', e => {
+    try { onEvent(JSON.parse(e.data)); } catch { /* ignore */ }
+  });
+  return ws;
+}
+
+// Server-Sent Events fallback for networks that strip WebSocket upgrades.
+// Delivers the same event objects as `openSocket`; the browser resumes with
+// Last-Event-ID automatically on reconnect.
+export function openEventStream(onEvent) {
+  const es = new EventSource('/events', { withCredentials: true });
+  for (const kind of ['message.created', 'match.created', 'match.deleted', 'profile.updated']) {
+    es.addEventListener(kind, e => {
+      try { onEvent(JSON.parse(e.data)); } catch { /* ignore */ }
+    });
+  }
+  return es;
+}
+
+// `user` needs `id` and `image_key`. `size` (longest side in px) picks a
+// downscaled WebP variant. The key's version makes the URL change with the
+// photo, so browsers may cache it indefinitely.
+export function profileImageUrl(user, size) {
+  const params = new URLSearchParams();
+  if (size) params.set('size', size);
+  if (user.image_key) params.set('v', user.image_key.split('/').pop());
+  const query = params.toString();
+  return `/profiles/${user.id}/image${query ? `?${query}` : ''}`;
+}
+
+// One photo of `user.photos`, versioned by its key like `profileImageUrl`.
+export function profilePhotoUrl(user, photo, size) {
+  const params = new URLSearchParams();
+  if (size) params.set('size', size);
+  params.set('v', photo.image_key.split('/').pop());
+  return `/profiles/${user.id}/photos/${photo.id}/image?${params}`;
 }
 
 export function messageImageUrl(messageId) {
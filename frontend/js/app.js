@@ -13,11 +13,12 @@ async function boot() {
 
   if (!me) {
     // Not logged in — always show login
-    register('#/login', () => renderLogin(container));
+    register('#/login', hash => renderLogin(container, hash));
     register('#/feed',     () => navigate('#/login'));
     register('#/profile',  () => navigate('#/login'));
     register('#/messages', () => navigate('#/login'));
-    navigate('#/login');
+    // Keep #/login/<reason> so the login view can explain a rejected sign-in.
+    if (!window.location.hash.startsWith('#/login')) navigate('#/login');
     start();
     return;
   }
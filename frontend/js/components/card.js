@@ -1,4 +1,4 @@
-import { profileImageUrl } from '../api.js';
+import { profileImageUrl, profilePhotoUrl } from '../api.js';
 
 export function buildCard(user, className = '') {
   const card = document.createElement('div');
@@ -11,16 +11,30 @@ export function buildCard(user, className = '') {
   const bio = user.bio || '';
   const tags = (user.interests || []).slice(0, 5);
 
-  const bgHtml = user.image_key
-    ? `<div class="card-bg" style="background-image:url('${profileImageUrl(user.id)}')"></div>`
-    : `<div class="card-bg-placeholder"><span>🎓</span></div>`;
+  // One background per gallery photo; only the current one is shown.
+  const photos = user.photos || [];
+  let bgHtml;
+  if (photos.length) {
+    bgHtml = photos.map((p, i) => `
+      <div class="card-bg${i === 0 ? ' active' : ''}" style="background-image:url('${profilePhotoUrl(user, p, 1080)}')"></div>
+    `).join('');
+  } else if (user.image_key) {
+    bgHtml = `<div class="card-bg active" style="background-image:url('${profileImageUrl(user, 1080)}')"></div>`;
+  } else {
+    bgHtml = `<div class="card-bg-placeholder"><span>🎓</span></div>`;
+  }
+  const barsHtml = photos.length > 1
+    ? `<div class="card-photo-bars">${photos.map((_, i) => `<span class="${i === 0 ? 'active' : ''}"></span>`).join('')}</div>`
+    : '';
 
   card.innerHTML = `
     ${bgHtml}
+    ${barsHtml}
     <div class="card-gradient"></div>
     <div class="card-label like">LIKE</div>
     <div class="card-label nope">NOPE</div>
     <div class="card-info">
+      <div class="card-caption"></div>
       <div class="card-name">${escHtml(name)}${age}</div>
       ${major ? `<div class="card-major">${escHtml(major)}</div>` : ''}
       ${bio ? `<div class="card-bio">${escHtml(bio)}</div>` : ''}
@@ -28,9 +42,35 @@ export function buildCard(user, className = '') {
     </div>
   `;
 
+  if (photos.length) attachGallery(card, photos);
+
   return card;
 }
 
+// Tapping the right or left half of the card steps through the photos. A
+// pointer that moved is a drag (see swipe.js), not a tap.
+function attachGallery(card, photos) {
+  let current = 0;
+  let downX = 0;
+  const caption = card.querySelector('.card-caption');
+
+  const show = index => {
+    current = Math.max(0, Math.min(photos.length - 1, index));
+    card.querySelectorAll('.card-bg').forEach((bg, i) => bg.classList.toggle('active', i === current));
+    card.querySelectorAll('.card-photo-bars span').forEach((bar, i) => bar.classList.toggle('active', i === current));
+    caption.textContent = photos[current].caption || '';
+  };
+
+  card.addEventListener('pointerdown', e => { downX = e.clientX; });
+  card.addEventListener('click', e => {
+    if (Math.abs(e.clientX - downX) > 10) return;
+    const rect = card.getBoundingClientRect();
+    show(current + (e.clientX - rect.left > rect.width / 2 ? 1 : -1));
+  });
+
+  show(0);
+}
+
 function escHtml(str) {
   return String(str)
     .replace(/&/g, '&amp;')
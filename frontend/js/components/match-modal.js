@@ -29,7 +29,7 @@ function hideMatchModal() {
 function setAvatar(elementId, user) {
   const el = document.getElementById(elementId);
   if (user.image_key) {
-    el.innerHTML = `<img src="${profileImageUrl(user.id)}" alt="" />`;
+    el.innerHTML = `<img src="${profileImageUrl(user, 512)}" alt="" />`;
   } else {
     el.textContent = '🎓';
   }
@@ -1,21 +1,51 @@
 import { hideNavbar } from '../components/navbar.js';
+import { getAuthProviders } from '../api.js';
 
-export function renderLogin(container) {
+// Reasons the backend may send us back here with: #/login/<reason>
+const LOGIN_ERRORS = {
+  not_allowed: 'Huskr is only open to students. Please sign in with your university account.',
+  suspended: 'This account has been suspended.',
+};
+
+export function renderLogin(container, hash = '') {
   hideNavbar();
+  const reason = hash.split('/')[2];
+  const errorHtml = LOGIN_ERRORS[reason]
+    ? `<p style="color:var(--coral); margin-bottom:1rem;">${LOGIN_ERRORS[reason]}</p>`
+    : '';
   container.innerHTML = `
     <div class="center-content" style="min-height:100vh; padding-bottom:0;">
       <div style="max-width:340px; width:100%;">
         <h1 style="font-family:'Liberator',sans-serif; font-size:3rem; font-weight:900; font-style:italic; color:var(--coral); margin-bottom:0.25rem;">Huskr</h1>
         <p style="color:var(--text-muted); font-size:1.1rem; margin-bottom:3rem;">Find your people on campus</p>
-        <a href="/auth/login" style="display:block;">
-          <button class="btn-primary" style="width:100%; padding:0.9rem; font-size:1rem; border-radius:var(--radius-sm);">
-            Sign in with Microsoft
-          </button>
-        </a>
+        ${errorHtml}
+        <div id="login-providers">
+          ${providerButton({ name: '', label: 'Microsoft' })}
+        </div>
         <p style="font-size:0.8rem; color:var(--text-muted); margin-top:1.5rem; text-align:center;">
-          Use your university Microsoft account
+          Use your university account
         </p>
       </div>
     </div>
   `;
+
+  // One button per configured identity provider; the default button above
+  // stays if the list can't be loaded.
+  getAuthProviders()
+    .then(providers => {
+      if (providers.length === 0) return;
+      container.querySelector('#login-providers').innerHTML = providers.map(providerButton).join('');
+    })
+    .catch(() => {});
+}
+
+function providerButton({ name, label }) {
+  const href = name ? `/auth/login/${encodeURIComponent(name)}` : '/auth/login';
+  return `
+    <a href="${href}" style="display:block; margin-bottom:0.75rem;">
+      <button class="btn-primary" style="width:100%; padding:0.9rem; font-size:1rem; border-radius:var(--radius-sm);">
+        Sign in with ${label}
+      </button>
+    </a>
+  `;
 }
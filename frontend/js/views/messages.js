@@ -1,8 +1,16 @@
-import { getMatches, getMessages, sendMessage, getMe, profileImageUrl, messageImageUrl } from '../api.js';
+import { getMatches, getMessages, sendMessage, unmatch, getMe, openEventStream, openSocket, profileImageUrl, messageImageUrl } from '../api.js';
 import { renderNavbar } from '../components/navbar.js';
 import { navigate } from '../router.js';
 
 let pollTimer = null;
+let socket = null;
+let eventSource = null;
+
+function stopLiveUpdates() {
+  if (pollTimer) { clearInterval(pollTimer); pollTimer = null; }
+  if (socket) { socket.close(); socket = null; }
+  if (eventSource) { eventSource.close(); eventSource = null; }
+}
 
 export async function renderMessages(container, hash) {
   renderNavbar('#/messages');
@@ -21,7 +29,7 @@ export async function renderMessages(container, hash) {
     </div>
   `;
 
-  if (pollTimer) { clearInterval(pollTimer); pollTimer = null; }
+  stopLiveUpdates();
 
   let me, matches;
   try {
@@ -36,7 +44,7 @@ export async function renderMessages(container, hash) {
 
   if (targetId) {
     const match = matches.find(m => m.user.id === targetId);
-    if (match) openChat(match.user, me);
+    if (match) openChat(match, me);
   }
 }
 
@@ -59,7 +67,7 @@ function renderMatchList(matches, me) {
     const user = m.user;
     const name = user.full_name || user.display_name || 'Unknown';
     const avatarHtml = user.image_key
-      ? `<img src="${profileImageUrl(user.id)}" alt="" />`
+      ? `<img src="${profileImageUrl(user, 128)}" alt="" />`
       : '🎓';
     return `
       <li class="match-item" data-user-id="${user.id}">
@@ -77,13 +85,14 @@ function renderMatchList(matches, me) {
       const match = matches.find(m => m.user.id === userId);
       if (match) {
         window.history.replaceState(null, '', `#/messages/${userId}`);
-        openChat(match.user, me);
+        openChat(match, me);
       }
     });
   });
 }
 
-function openChat(user, me) {
+function openChat(match, me) {
+  const user = match.user;
   const panel = document.getElementById('messages-list-panel');
   const chatView = document.getElementById('chat-view');
   if (!panel || !chatView) return;
@@ -93,7 +102,7 @@ function openChat(user, me) {
 
   const name = user.full_name || user.display_name || 'Unknown';
   const avatarHtml = user.image_key
-    ? `<img src="${profileImageUrl(user.id)}" alt="" />`
+    ? `<img src="${profileImageUrl(user, 128)}" alt="" />`
     : '🎓';
 
   chatView.innerHTML = `
@@ -103,8 +112,10 @@ function openChat(user, me) {
       </button>
       <div id="chat-other-avatar">${avatarHtml}</div>
       <div id="chat-other-name">${escHtml(name)}</div>
+      <button id="chat-unmatch-btn" title="Unmatch">Unmatch</button>
     </div>
     <div id="chat-messages"></div>
+    <div id="chat-typing" hidden>${escHtml(name)} is typing…</div>
     <div id="chat-image-preview" hidden>
       <img id="chat-image-preview-img" src="" alt="preview" />
       <button id="chat-image-clear-btn" title="Remove image">
@@ -126,7 +137,7 @@ function openChat(user, me) {
   document.querySelector('.view')?.classList.add('chat-active');
 
   document.getElementById('chat-back-btn').addEventListener('click', () => {
-    if (pollTimer) { clearInterval(pollTimer); pollTimer = null; }
+    stopLiveUpdates();
     window.history.replaceState(null, '', '#/messages');
     document.querySelector('.view')?.classList.remove('chat-active');
     chatView.classList.remove('visible');
@@ -134,13 +145,73 @@ function openChat(user, me) {
     panel.classList.remove('hidden');
   });
 
+  document.getElementById('chat-unmatch-btn').addEventListener('click', async () => {
+    if (!confirm(`Unmatch ${name}? Your conversation will be removed.`)) return;
+    try {
+      await unmatch(match.id);
+      leaveChat();
+    } catch { /* ignore */ }
+  });
+
+  // The match is gone (either side unmatched): drop back to a fresh list.
+  function leaveChat() {
+    stopLiveUpdates();
+    document.querySelector('.view')?.classList.remove('chat-active');
+    navigate('#/messages');
+  }
+
   async function loadMessages(scrollToBottom = false) {
     try {
       const msgs = await getMessages(user.id);
       renderChatMessages(msgs, me.id, scrollToBottom);
+      sendReadReceipt(msgs);
     } catch { /* ignore */ }
   }
 
+  function sendReadReceipt(msgs) {
+    const last = msgs.filter(m => m.sender_id === user.id).pop();
+    if (last && !last.read_at && socket?.readyState === WebSocket.OPEN) {
+      socket.send(JSON.stringify({ type: 'read', sender_id: user.id, up_to: last.id }));
+    }
+  }
+
+  let typingTimer = null;
+  function showTyping() {
+    const el = document.getElementById('chat-typing');
+    if (!el) return;
+    el.hidden = false;
+    clearTimeout(typingTimer);
+    typingTimer = setTimeout(() => { el.hidden = true; }, 3000);
+  }
+
+  function handleEvent(event) {
+    if (event.type === 'message') {
+      const m = event.message;
+      if (m.sender_id === user.id || m.recipient_id === user.id) loadMessages(m.sender_id === me.id);
+    } else if (event.type === 'typing' && event.user_id === user.id) {
+      showTyping();
+    } else if (event.type === 'unmatched' && event.user_id === user.id) {
+      leaveChat();
+    }
+  }
+
+  // Prefer pushed updates over the WebSocket; if it can't stay open (e.g. a
+  // proxy that strips upgrades) fall back to SSE, then to polling.
+  function startLiveUpdates() {
+    const ws = openSocket(handleEvent);
+    socket = ws;
+    ws.addEventListener('close', () => {
+      if (socket !== ws || eventSource) return;
+      const es = openEventStream(handleEvent);
+      eventSource = es;
+      es.addEventListener('error', () => {
+        if (eventSource === es && es.readyState === EventSource.CLOSED && !pollTimer) {
+          pollTimer = setInterval(() => loadMessages(false), 5000);
+        }
+      });
+    });
+  }
+
   let pendingImage = null;
 
   document.getElementById('chat-attach-btn').addEventListener('click', () => {
@@ -170,8 +241,14 @@ function openChat(user, me) {
   });
 
   document.getElementById('chat-send-btn').addEventListener('click', doSend);
+  let lastTypingSent = 0;
   document.getElementById('chat-input').addEventListener('keydown', e => {
-    if (e.key === 'Enter') doSend();
+    if (e.key === 'Enter') { doSend(); return; }
+    const now = Date.now();
+    if (socket?.readyState === WebSocket.OPEN && now - lastTypingSent > 2000) {
+      lastTypingSent = now;
+      socket.send(JSON.stringify({ type: 'typing', recipient_id: user.id }));
+    }
   });
 
   async function doSend() {
@@ -196,7 +273,7 @@ function openChat(user, me) {
   }
 
   loadMessages(true);
-  pollTimer = setInterval(() => loadMessages(false), 5000);
+  startLiveUpdates();
 }
 
 function renderChatMessages(messages, myId, scrollToBottom = false) {
@@ -1,4 +1,7 @@
-import { getMe, updateProfile, profileImageUrl } from '../api.js';
+import {
+  getMe, updateProfile, profileImageUrl, profilePhotoUrl,
+  addProfilePhoto, updateProfilePhoto, reorderProfilePhotos, deleteProfilePhoto,
+} from '../api.js';
 import { renderNavbar } from '../components/navbar.js';
 import { showToast } from '../components/toast.js';
 import { navigate } from '../router.js';
@@ -17,18 +20,20 @@ export async function renderProfile(container) {
   if (!me) { navigate('#/login'); return; }
 
   const interests = [...(me.interests || [])];
+  let photos = me.photos || [];
 
   container.innerHTML = `
     <div id="profile-view">
       <h1>Your Profile</h1>
 
       <div class="avatar-wrapper">
-        <div class="avatar-circle" id="avatar-preview">
-          ${me.image_key
-            ? `<img src="${profileImageUrl(me.id)}" alt="Profile photo" />`
-            : '🎓'}
-        </div>
-        <button class="upload-btn" id="upload-btn">Change photo</button>
+        <div class="avatar-circle" id="avatar-preview"></div>
+      </div>
+
+      <div class="form-group">
+        <label>Photos</label>
+        <div id="photo-gallery"></div>
+        <button class="upload-btn" id="upload-btn">Add photo</button>
         <input type="file" id="photo-file" accept="image/*" style="display:none" />
       </div>
 
@@ -50,6 +55,11 @@ export async function renderProfile(container) {
           value="${escHtml(me.major || '')}" />
       </div>
 
+      <div class="form-group">
+        <label for="rso-input">RSO Member</label>
+        <input id="rso-input" type="checkbox" ${me.is_rso ? 'checked' : ''} />
+      </div>
+
       <div class="form-group">
         <label for="bio-input">Bio</label>
         <textarea id="bio-input" rows="3" placeholder="Tell people about yourself...">${escHtml(me.bio || '')}</textarea>
@@ -70,6 +80,7 @@ export async function renderProfile(container) {
   `;
 
   renderInterestTags();
+  renderPhotos();
 
   // Interest input
   const interestInput = document.getElementById('interest-input');
@@ -85,15 +96,15 @@ export async function renderProfile(container) {
     }
   });
 
-  // Photo upload
+  // Photos are saved as soon as they change, independently of the form.
   document.getElementById('upload-btn').addEventListener('click', () => {
     document.getElementById('photo-file').click();
   });
-  document.getElementById('photo-file').addEventListener('change', e => {
+  document.getElementById('photo-file').addEventListener('change', async e => {
     const file = e.target.files[0];
+    e.target.value = '';
     if (!file) return;
-    const url = URL.createObjectURL(file);
-    document.getElementById('avatar-preview').innerHTML = `<img src="${url}" alt="" />`;
+    await changePhotos(() => addProfilePhoto(file));
   });
 
   // Save
@@ -102,20 +113,18 @@ export async function renderProfile(container) {
     btn.disabled = true;
     btn.textContent = 'Saving…';
 
-    const fd = new FormData();
-    fd.append('full_name', document.getElementById('fullname-input').value.trim());
-    fd.append('age', document.getElementById('age-input').value || '');
-    fd.append('major', document.getElementById('major-input').value.trim());
-    fd.append('bio', document.getElementById('bio-input').value.trim());
-    interests.forEach(i => fd.append('interests', i));
-
-    const fileInput = document.getElementById('photo-file');
-    if (fileInput.files[0]) {
-      fd.append('image', fileInput.files[0]);
-    }
+    const age = document.getElementById('age-input').value;
+    const fields = {
+      full_name: document.getElementById('fullname-input').value,
+      age: age ? Number(age) : null,
+      major: document.getElementById('major-input').value,
+      bio: document.getElementById('bio-input').value,
+      is_rso: document.getElementById('rso-input').checked,
+      interests,
+    };
 
     try {
-      await updateProfile(fd);
+      await updateProfile(fields);
       showToast('Profile saved!');
     } catch (err) {
       showToast('Error saving: ' + err.message);
@@ -130,6 +139,67 @@ export async function renderProfile(container) {
     window.location.href = '/auth/logout';
   });
 
+  // Runs a photo change and re-renders from the profile it returns.
+  async function changePhotos(action) {
+    try {
+      const profile = await action();
+      photos = profile.photos;
+      me.image_key = profile.image_key;
+      renderPhotos();
+    } catch (err) {
+      showToast('Error updating photos: ' + err.message);
+    }
+  }
+
+  function renderPhotos() {
+    document.getElementById('avatar-preview').innerHTML = me.image_key
+      ? `<img src="${profileImageUrl(me, 512)}" alt="Profile photo" />`
+      : '🎓';
+
+    const gallery = document.getElementById('photo-gallery');
+    gallery.innerHTML = photos.map((p, i) => `
+      <div class="photo-tile${p.is_primary ? ' primary' : ''}" data-index="${i}">
+        <img src="${profilePhotoUrl(me, p, 128)}" alt="" />
+        <div class="photo-actions">
+          <button data-action="left" title="Move left" ${i === 0 ? 'disabled' : ''}>‹</button>
+          <button data-action="primary" title="Make primary" ${p.is_primary ? 'disabled' : ''}>★</button>
+          <button data-action="delete" title="Remove">×</button>
+          <button data-action="right" title="Move right" ${i === photos.length - 1 ? 'disabled' : ''}>›</button>
+        </div>
+        <input class="photo-caption" type="text" placeholder="Caption" value="${escHtml(p.caption || '')}" />
+      </div>
+    `).join('');
+
+    gallery.querySelectorAll('.photo-tile').forEach(tile => {
+      const index = Number(tile.dataset.index);
+      const photo = photos[index];
+
+      tile.querySelectorAll('button').forEach(btn => {
+        btn.addEventListener('click', async () => {
+          const action = btn.dataset.action;
+          if (action === 'primary') {
+            await changePhotos(() => updateProfilePhoto(photo.id, { is_primary: true }));
+          } else if (action === 'delete') {
+            await changePhotos(async () => {
+              await deleteProfilePhoto(photo.id);
+              return getMe();
+            });
+          } else {
+            const ids = photos.map(p => p.id);
+            const to = action === 'left' ? index - 1 : index + 1;
+            [ids[index], ids[to]] = [ids[to], ids[index]];
+            await changePhotos(() => reorderProfilePhotos(ids));
+          }
+        });
+      });
+
+      tile.querySelector('.photo-caption').addEventListener('change', async e => {
+        const caption = e.target.value.trim();
+        await changePhotos(() => updateProfilePhoto(photo.id, { caption: caption || null }));
+      });
+    });
+  }
+
   function renderInterestTags() {
     const container = document.getElementById('interest-tags');
     if (!container) return;